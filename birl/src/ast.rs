@@ -0,0 +1,256 @@
+//! A whole program as a tree. The parser only sees one line at a time, so this puts the lines
+//! together into functions and blocks, for tools that need to look at the program as a whole.
+
+use parser::{ parse_line, ParserResult, FunctionDeclaration, Command, CommandKind };
+use error::{ Error, ParseError, IoError, Span };
+
+use std::fs;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    // Global code and functions, in the order they show up in the source
+    pub items : Vec<Item>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Item {
+    Function(Function),
+    Statement(Statement),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub declaration : FunctionDeclaration,
+    pub body : Vec<Statement>,
+    // Where the JAULA is
+    pub span : Span,
+    // Where the SAINDO DA JAULA is
+    pub end : Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Command(Command, Span),
+    Block(Box<Block>),
+}
+
+/// A conditional or a loop, from the command starting it to its FIM
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub header : Command,
+    pub span : Span,
+    pub body : Vec<Statement>,
+    // The SENÃO and the commands after it, if there's one
+    pub alternative : Option<(Span, Vec<Statement>)>,
+    // Where the FIM is
+    pub end : Span,
+}
+
+impl Program {
+    pub fn functions(&self) -> Vec<&Function> {
+        self.items.iter()
+            .filter_map(|i| match i {
+                Item::Function(f) => Some(f),
+                Item::Statement(_) => None,
+            })
+            .collect()
+    }
+}
+
+// A function or block whose end wasn't found yet
+enum Open {
+    Function(FunctionDeclaration, Span, Vec<Statement>),
+    Block(Command, Span, Vec<Statement>, Option<(Span, Vec<Statement>)>),
+}
+
+fn structure_error(message : &str, span : &Span) -> Error {
+    let mut error = Error::from(ParseError::new(message));
+
+    *error.span_mut() = span.clone();
+
+    error
+}
+
+fn push_statement(items : &mut Vec<Item>, open : &mut [Open], statement : Statement) {
+    match open.last_mut() {
+        Some(Open::Function(_, _, body)) => body.push(statement),
+        Some(Open::Block(_, _, _, Some((_, alternative)))) => alternative.push(statement),
+        Some(Open::Block(_, _, body, None)) => body.push(statement),
+        None => items.push(Item::Statement(statement)),
+    }
+}
+
+fn parse_lines<'a, I>(lines : I, file : Option<&str>) -> Result<Program, Error>
+    where I : Iterator<Item = &'a str> {
+    let mut items = vec![];
+    let mut open = vec![];
+
+    for (index, line) in lines.enumerate() {
+        let span = Span {
+            file : file.map(|f| f.to_owned()),
+            line : Some(index + 1),
+            column : Some(line.chars().take_while(|c| c.is_whitespace()).count() + 1),
+            function : None,
+        };
+
+        let result = match parse_line(line) {
+            Ok(r) => r,
+            // The parser only knows the column
+            Err(e) => return Err(match file {
+                Some(f) => e.with_line(index + 1).with_file(f),
+                None => e.with_line(index + 1),
+            }),
+        };
+
+        match result {
+            ParserResult::Nothing => {}
+            ParserResult::FunctionStart(declaration) => {
+                if !open.is_empty() {
+                    return Err(structure_error("Erro : Declaração de função fora do escopo global", &span));
+                }
+
+                open.push(Open::Function(declaration, span, vec![]));
+            }
+            ParserResult::FunctionEnd => {
+                match open.pop() {
+                    Some(Open::Function(declaration, start, body)) => {
+                        items.push(Item::Function(Function { declaration, body, span : start, end : span }));
+                    }
+                    Some(Open::Block(..)) => {
+                        return Err(structure_error("Erro : Feche todos os scopes antes de terminar a função", &span));
+                    }
+                    None => return Err(structure_error("Erro : Fim de função fora de uma função", &span)),
+                }
+            }
+            ParserResult::Command(command) => {
+                match command.kind {
+                    CommandKind::EndExecuteIf => {
+                        match open.pop() {
+                            Some(Open::Block(header, start, body, alternative)) => {
+                                let block = Block { header, span : start, body, alternative, end : span };
+
+                                push_statement(&mut items, &mut open, Statement::Block(Box::new(block)));
+                            }
+                            _ => return Err(structure_error("Erro : FIM fora de um bloco", &span)),
+                        }
+                    }
+                    CommandKind::Else => {
+                        match open.last_mut() {
+                            Some(Open::Block(_, _, _, alternative @ None)) => *alternative = Some((span, vec![])),
+                            Some(Open::Block(_, _, _, Some(_))) => {
+                                return Err(structure_error("Erro : O bloco condicional já tem um SENÃO", &span));
+                            }
+                            _ => return Err(structure_error("Erro : SENÃO fora de um bloco condicional", &span)),
+                        }
+                    }
+                    kind if kind.opens_block() => open.push(Open::Block(command, span, vec![], None)),
+                    _ => push_statement(&mut items, &mut open, Statement::Command(command, span)),
+                }
+            }
+        }
+    }
+
+    match open.pop() {
+        Some(Open::Function(declaration, span, _)) => {
+            Err(structure_error(&format!("Erro : A JAULA {} não foi terminada", declaration.name), &span))
+        }
+        Some(Open::Block(_, span, _, _)) => Err(structure_error("Erro : O bloco não foi terminado com FIM", &span)),
+        None => Ok(Program { items }),
+    }
+}
+
+/// Parses a whole source. Besides the errors of each line, reports blocks and functions that
+/// don't end or end in the wrong place
+pub fn parse_program(source : &str) -> Result<Program, Error> {
+    parse_lines(source.lines(), None)
+}
+
+pub fn parse_file(filename : &str) -> Result<Program, Error> {
+    let source = match fs::read_to_string(filename) {
+        Ok(s) => s,
+        Err(e) => return Err(Error::from(IoError::new(format!("{:?}", e))).with_file(filename)),
+    };
+
+    parse_lines(source.lines(), Some(filename))
+}
+
+mod tests {
+    #[test]
+    fn program_structure() {
+        use ast::*;
+
+        let source = "VEM: X, 0\n\
+                      \n\
+                      JAULA F(N : BATATA DOCE)\n\
+                      \x20   E VERDADE: N > 0\n\
+                      \x20       CE QUER VER: N\n\
+                      \x20   SENAO\n\
+                      \x20       ENQUANTO FOR VERDADE: X < 2\n\
+                      \x20           BORA: X, X + 1\n\
+                      \x20       FIM\n\
+                      \x20   FIM\n\
+                      SAINDO DA JAULA\n\
+                      E HORA DO: F, X";
+
+        let program = match parse_program(source) {
+            Ok(p) => p,
+            Err(e) => panic!("Erro lendo o programa : {}", e),
+        };
+
+        assert_eq!(program.items.len(), 3);
+        assert_eq!(program.functions().len(), 1);
+
+        let function = program.functions()[0];
+        assert_eq!(function.declaration.name, "F");
+        assert_eq!((function.span.line, function.end.line), (Some(3), Some(11)));
+        assert_eq!(function.body.len(), 1);
+
+        let block = match function.body[0] {
+            Statement::Block(ref b) => b,
+            ref s => panic!("Era esperado um bloco, encontrado {:?}", s),
+        };
+
+        assert_eq!(block.header.kind, CommandKind::ExecuteIfTrue);
+        assert_eq!((block.span.line, block.span.column, block.end.line), (Some(4), Some(5), Some(10)));
+        assert_eq!(block.body.len(), 1);
+
+        let alternative = match block.alternative {
+            Some((ref span, ref body)) => {
+                assert_eq!(span.line, Some(6));
+                body
+            }
+            None => panic!("O bloco deveria ter um SENÃO"),
+        };
+
+        match alternative[..] {
+            [Statement::Block(ref inner)] => {
+                assert_eq!(inner.header.kind, CommandKind::ExecuteWhileTrue);
+                assert_eq!(inner.body.len(), 1);
+            }
+            ref s => panic!("Era esperado um ENQUANTO, encontrado {:?}", s),
+        }
+
+        match program.items[2] {
+            Item::Statement(Statement::Command(ref c, ref span)) => {
+                assert_eq!(c.kind, CommandKind::Call);
+                assert_eq!(span.line, Some(12));
+            }
+            ref i => panic!("Era esperada uma chamada, encontrado {:?}", i),
+        }
+
+        let errors = [
+            ("E VERDADE: 1 == 1\nCE QUER VER: 1", 1),
+            ("FIM", 1),
+            ("JAULA F\nJAULA G\nSAINDO DA JAULA", 2),
+            ("JAULA F\nE VERDADE: 1 == 1\nSAINDO DA JAULA", 3),
+            ("CE QUER VER: 1\nVEM X", 2),
+        ];
+
+        for &(source, line) in &errors {
+            match parse_program(source) {
+                Ok(_) => panic!("Deveria ter falhado : {}", source),
+                Err(e) => assert_eq!(e.span().line, Some(line), "{}", source),
+            }
+        }
+    }
+}
@@ -0,0 +1,604 @@
+//! Binary format for compiled programs, so they can be loaded without going through the parser
+//!
+//! The file starts with the magic bytes and the format version, followed by the state of the
+//! context and the function table. Integers are little endian, strings are prefixed by their
+//! length in bytes and every instruction is an opcode followed by its operands.
+
+use vm::Instruction;
+use parser::{ FunctionParameter, TypeKind, IntegerType };
+use compiler::Variable;
+use context::{ FunctionEntry, LineEntry, BIRL_GLOBAL_FUNCTION_ID, BIRL_MAIN_FUNCTION_ID };
+use error::BytecodeError;
+
+use std::convert::TryFrom;
+
+pub const BYTECODE_MAGIC : &[u8] = b"BIRLC";
+/// Bumped every time the layout or the instruction set changes
+pub const BYTECODE_VERSION : u32 = 7;
+pub const BYTECODE_EXTENSION : &str = "birlc";
+
+/// Everything the context needs to run a program that was already compiled
+#[derive(Debug, Clone)]
+pub struct CompiledProgram {
+    pub functions : Vec<FunctionEntry>,
+    pub has_main : bool,
+    pub next_function_id : u64,
+}
+
+pub fn is_bytecode(bytes : &[u8]) -> bool {
+    bytes.starts_with(BYTECODE_MAGIC)
+}
+
+struct Encoder {
+    bytes : Vec<u8>,
+}
+
+impl Encoder {
+    fn write_u8(&mut self, value : u8) {
+        self.bytes.push(value);
+    }
+
+    fn write_bool(&mut self, value : bool) {
+        self.write_u8(if value { 1 } else { 0 });
+    }
+
+    fn write_u32(&mut self, value : u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, value : u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn write_len(&mut self, len : usize) {
+        self.write_u64(len as u64);
+    }
+
+    fn write_str(&mut self, value : &str) {
+        self.write_len(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn write_kind(&mut self, kind : TypeKind) {
+        self.write_u8(match kind {
+            TypeKind::Integer => 0,
+            TypeKind::Number => 1,
+            TypeKind::Text => 2,
+            TypeKind::List => 3,
+            TypeKind::Boolean => 4,
+        });
+    }
+
+    fn write_optional_kind(&mut self, kind : Option<TypeKind>) {
+        match kind {
+            Some(kind) => {
+                self.write_bool(true);
+                self.write_kind(kind);
+            }
+            None => self.write_bool(false),
+        }
+    }
+
+    fn write_instruction(&mut self, inst : &Instruction) {
+        match inst {
+            Instruction::PushMainInt(i) => {
+                self.write_u8(0);
+                // Always 64 bits, so the file doesn't depend on the size of IntegerType
+                self.write_u64(*i as u64);
+            }
+            Instruction::PushMainNum(n) => {
+                self.write_u8(1);
+                self.write_u64(n.to_bits());
+            }
+            Instruction::PushMainStr(s) => {
+                self.write_u8(2);
+                self.write_str(s.as_str());
+            }
+            Instruction::PushMainBool(b) => {
+                self.write_u8(3);
+                self.write_bool(*b);
+            }
+            Instruction::PushNull => self.write_u8(4),
+            Instruction::MainAdd => self.write_u8(5),
+            Instruction::MainSub => self.write_u8(6),
+            Instruction::MainDiv => self.write_u8(7),
+            Instruction::MainMul => self.write_u8(8),
+            Instruction::MainMod => self.write_u8(9),
+            Instruction::MainPow => self.write_u8(10),
+            Instruction::MainNeg => self.write_u8(11),
+            Instruction::MainEqual => self.write_u8(12),
+            Instruction::MainNotEqual => self.write_u8(13),
+            Instruction::MainLess => self.write_u8(14),
+            Instruction::MainLessOrEqual => self.write_u8(15),
+            Instruction::MainGreater => self.write_u8(16),
+            Instruction::MainGreaterOrEqual => self.write_u8(17),
+            Instruction::MainAnd => self.write_u8(18),
+            Instruction::MainOr => self.write_u8(19),
+            Instruction::MainNot => self.write_u8(20),
+            Instruction::MainPrint => self.write_u8(21),
+            Instruction::PrintNewLine => self.write_u8(22),
+            Instruction::MainPrintDebug => self.write_u8(23),
+            Instruction::FlushStdout => self.write_u8(24),
+            Instruction::Quit => self.write_u8(25),
+            Instruction::ReadVarWithId(id) => {
+                self.write_u8(26);
+                self.write_u64(*id);
+            }
+            Instruction::ReadGlobalVarWithId(id) => {
+                self.write_u8(27);
+                self.write_u64(*id);
+            }
+            Instruction::WriteToVarWithId(id) => {
+                self.write_u8(28);
+                self.write_u64(*id);
+            }
+            Instruction::WriteToLastFrameVarWithId(id) => {
+                self.write_u8(29);
+                self.write_u64(*id);
+            }
+            Instruction::WriteToGlobalVarWithId(id) => {
+                self.write_u8(30);
+                self.write_u64(*id);
+            }
+            Instruction::CreateVarWithId(id) => {
+                self.write_u8(31);
+                self.write_u64(*id);
+            }
+            Instruction::CompareMainTop => self.write_u8(32),
+            Instruction::Return => self.write_u8(33),
+            Instruction::EndExecuteIf => self.write_u8(34),
+            Instruction::ExecuteIfEqual => self.write_u8(35),
+            Instruction::ExecuteIfNotEqual => self.write_u8(36),
+            Instruction::ExecuteIfGreater => self.write_u8(37),
+            Instruction::ExecuteIfGreaterOrEqual => self.write_u8(38),
+            Instruction::ExecuteIfLess => self.write_u8(39),
+            Instruction::ExecuteIfLessOrEqual => self.write_u8(40),
+            Instruction::ExecuteIfTrue => self.write_u8(41),
+            Instruction::ExecuteElse => self.write_u8(42),
+            Instruction::MakeNewFrame(id) => {
+                self.write_u8(43);
+                self.write_u64(*id);
+            }
+            Instruction::SetLastFrameReady => self.write_u8(44),
+            Instruction::Jump(address) => {
+                self.write_u8(45);
+                self.write_len(*address);
+            }
+            Instruction::AssertMainTopTypeCompatible(kind) => {
+                self.write_u8(46);
+                self.write_kind(*kind);
+            }
+            Instruction::ReadInput => self.write_u8(47),
+            Instruction::ConvertToString => self.write_u8(48),
+            Instruction::ConvertToNum => self.write_u8(49),
+            Instruction::ConvertToInt => self.write_u8(50),
+            Instruction::MakeList(len) => {
+                self.write_u8(51);
+                self.write_len(*len);
+            }
+            Instruction::ListGet => self.write_u8(52),
+            Instruction::ListPush => self.write_u8(53),
+            Instruction::ListPop => self.write_u8(54),
+            Instruction::ListLength => self.write_u8(55),
+            Instruction::CallNative(id) => {
+                self.write_u8(56);
+                self.write_u64(*id);
+            }
+            Instruction::PopMain => self.write_u8(57),
+        }
+    }
+
+    fn write_vars(&mut self, vars : &[Variable]) {
+        self.write_len(vars.len());
+        for v in vars {
+            self.write_str(v.name.as_str());
+            self.write_u64(v.id);
+            self.write_bool(v.writeable);
+            self.write_optional_kind(v.kind);
+        }
+    }
+
+    fn write_function(&mut self, func : &FunctionEntry) {
+        self.write_u64(func.id);
+        self.write_str(func.name.as_str());
+
+        self.write_len(func.params.len());
+        for p in &func.params {
+            self.write_str(p.name.as_str());
+            self.write_kind(p.kind);
+        }
+
+        self.write_optional_kind(func.return_kind);
+
+        self.write_vars(&func.vars);
+        self.write_vars(&func.declared_vars);
+        self.write_u64(func.next_var_id);
+
+        self.write_len(func.lines.len());
+        for l in &func.lines {
+            self.write_len(l.start);
+            self.write_len(l.line);
+        }
+
+        match func.file {
+            Some(ref file) => {
+                self.write_bool(true);
+                self.write_str(file.as_str());
+            }
+            None => self.write_bool(false),
+        }
+
+        self.write_len(func.body.len());
+        for inst in &func.body {
+            self.write_instruction(inst);
+        }
+    }
+}
+
+struct Decoder<'a> {
+    bytes : &'a [u8],
+    offset : usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn read_bytes(&mut self, len : usize) -> Result<&'a [u8], BytecodeError> {
+        if self.bytes.len() - self.offset < len {
+            return Err(BytecodeError::new(format!("Fim inesperado do arquivo no byte {}", self.offset)));
+        }
+
+        let bytes = &self.bytes[self.offset..self.offset + len];
+        self.offset += len;
+
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, BytecodeError> {
+        match self.read_bytes(1) {
+            Ok(b) => Ok(b[0]),
+            Err(e) => Err(e)
+        }
+    }
+
+    fn read_bool(&mut self) -> Result<bool, BytecodeError> {
+        match self.read_u8() {
+            Ok(0) => Ok(false),
+            Ok(1) => Ok(true),
+            Ok(b) => Err(BytecodeError::new(format!("Valor inválido pra um BIRLEANO : {}", b))),
+            Err(e) => Err(e)
+        }
+    }
+
+    fn read_u32(&mut self) -> Result<u32, BytecodeError> {
+        let bytes = self.read_bytes(4)?;
+        let mut buf = [0u8; 4];
+        buf.copy_from_slice(bytes);
+
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, BytecodeError> {
+        let bytes = self.read_bytes(8)?;
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(bytes);
+
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    fn read_len(&mut self) -> Result<usize, BytecodeError> {
+        let len = self.read_u64()?;
+
+        // Every item takes at least a byte, so a length bigger than what's left is corruption
+        if len > (self.bytes.len() - self.offset) as u64 {
+            return Err(BytecodeError::new(format!("Tamanho inválido no byte {} : {}", self.offset - 8, len)));
+        }
+
+        Ok(len as usize)
+    }
+
+    fn read_address(&mut self) -> Result<usize, BytecodeError> {
+        let value = self.read_u64()?;
+
+        match usize::try_from(value) {
+            Ok(v) => Ok(v),
+            Err(_) => Err(BytecodeError::new(format!("Valor grande demais pra essa plataforma : {}", value))),
+        }
+    }
+
+    fn read_str(&mut self) -> Result<String, BytecodeError> {
+        let len = self.read_len()?;
+        let bytes = self.read_bytes(len)?;
+
+        match String::from_utf8(bytes.to_vec()) {
+            Ok(s) => Ok(s),
+            Err(_) => Err(BytecodeError::new(format!("Texto inválido no byte {}", self.offset - len))),
+        }
+    }
+
+    fn read_kind(&mut self) -> Result<TypeKind, BytecodeError> {
+        match self.read_u8()? {
+            0 => Ok(TypeKind::Integer),
+            1 => Ok(TypeKind::Number),
+            2 => Ok(TypeKind::Text),
+            3 => Ok(TypeKind::List),
+            4 => Ok(TypeKind::Boolean),
+            k => Err(BytecodeError::new(format!("Tipo desconhecido : {}", k))),
+        }
+    }
+
+    fn read_optional_kind(&mut self) -> Result<Option<TypeKind>, BytecodeError> {
+        if self.read_bool()? {
+            Ok(Some(self.read_kind()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn read_instruction(&mut self) -> Result<Instruction, BytecodeError> {
+        let opcode = self.read_u8()?;
+
+        let inst = match opcode {
+            0 => {
+                let value = self.read_u64()? as i64;
+
+                match IntegerType::try_from(value) {
+                    Ok(i) => Instruction::PushMainInt(i),
+                    Err(_) => return Err(BytecodeError::new(format!("Inteiro grande demais pra essa plataforma : {}", value))),
+                }
+            }
+            1 => Instruction::PushMainNum(f64::from_bits(self.read_u64()?)),
+            2 => Instruction::PushMainStr(self.read_str()?),
+            3 => Instruction::PushMainBool(self.read_bool()?),
+            4 => Instruction::PushNull,
+            5 => Instruction::MainAdd,
+            6 => Instruction::MainSub,
+            7 => Instruction::MainDiv,
+            8 => Instruction::MainMul,
+            9 => Instruction::MainMod,
+            10 => Instruction::MainPow,
+            11 => Instruction::MainNeg,
+            12 => Instruction::MainEqual,
+            13 => Instruction::MainNotEqual,
+            14 => Instruction::MainLess,
+            15 => Instruction::MainLessOrEqual,
+            16 => Instruction::MainGreater,
+            17 => Instruction::MainGreaterOrEqual,
+            18 => Instruction::MainAnd,
+            19 => Instruction::MainOr,
+            20 => Instruction::MainNot,
+            21 => Instruction::MainPrint,
+            22 => Instruction::PrintNewLine,
+            23 => Instruction::MainPrintDebug,
+            24 => Instruction::FlushStdout,
+            25 => Instruction::Quit,
+            26 => Instruction::ReadVarWithId(self.read_u64()?),
+            27 => Instruction::ReadGlobalVarWithId(self.read_u64()?),
+            28 => Instruction::WriteToVarWithId(self.read_u64()?),
+            29 => Instruction::WriteToLastFrameVarWithId(self.read_u64()?),
+            30 => Instruction::WriteToGlobalVarWithId(self.read_u64()?),
+            31 => Instruction::CreateVarWithId(self.read_u64()?),
+            32 => Instruction::CompareMainTop,
+            33 => Instruction::Return,
+            34 => Instruction::EndExecuteIf,
+            35 => Instruction::ExecuteIfEqual,
+            36 => Instruction::ExecuteIfNotEqual,
+            37 => Instruction::ExecuteIfGreater,
+            38 => Instruction::ExecuteIfGreaterOrEqual,
+            39 => Instruction::ExecuteIfLess,
+            40 => Instruction::ExecuteIfLessOrEqual,
+            41 => Instruction::ExecuteIfTrue,
+            42 => Instruction::ExecuteElse,
+            43 => Instruction::MakeNewFrame(self.read_u64()?),
+            44 => Instruction::SetLastFrameReady,
+            45 => Instruction::Jump(self.read_address()?),
+            46 => Instruction::AssertMainTopTypeCompatible(self.read_kind()?),
+            47 => Instruction::ReadInput,
+            48 => Instruction::ConvertToString,
+            49 => Instruction::ConvertToNum,
+            50 => Instruction::ConvertToInt,
+            51 => Instruction::MakeList(self.read_address()?),
+            52 => Instruction::ListGet,
+            53 => Instruction::ListPush,
+            54 => Instruction::ListPop,
+            55 => Instruction::ListLength,
+            56 => Instruction::CallNative(self.read_u64()?),
+            57 => Instruction::PopMain,
+            _ => return Err(BytecodeError::new(format!("Instrução desconhecida no byte {} : {}", self.offset - 1, opcode))),
+        };
+
+        Ok(inst)
+    }
+
+    fn read_vars(&mut self) -> Result<Vec<Variable>, BytecodeError> {
+        let num_vars = self.read_len()?;
+        let mut vars = Vec::with_capacity(num_vars);
+        for _ in 0..num_vars {
+            let name = self.read_str()?;
+            let id = self.read_u64()?;
+            let writeable = self.read_bool()?;
+            let kind = self.read_optional_kind()?;
+
+            vars.push(Variable { name, id, writeable, kind });
+        }
+
+        Ok(vars)
+    }
+
+    fn read_function(&mut self) -> Result<FunctionEntry, BytecodeError> {
+        let id = self.read_u64()?;
+        let name = self.read_str()?;
+
+        let num_params = self.read_len()?;
+        let mut params = Vec::with_capacity(num_params);
+        for _ in 0..num_params {
+            let name = self.read_str()?;
+            let kind = self.read_kind()?;
+
+            params.push(FunctionParameter { name, kind });
+        }
+
+        let return_kind = self.read_optional_kind()?;
+
+        let vars = self.read_vars()?;
+        let declared_vars = self.read_vars()?;
+        let next_var_id = self.read_u64()?;
+
+        let num_lines = self.read_len()?;
+        let mut lines = Vec::with_capacity(num_lines);
+        for _ in 0..num_lines {
+            let start = self.read_address()?;
+            let line = self.read_address()?;
+
+            lines.push(LineEntry { start, line });
+        }
+
+        let file = if self.read_bool()? { Some(self.read_str()?) } else { None };
+
+        let body_len = self.read_len()?;
+        let mut body = Vec::with_capacity(body_len);
+        for _ in 0..body_len {
+            body.push(self.read_instruction()?);
+        }
+
+        Ok(FunctionEntry { name, id, body, params, vars, next_var_id, declared_vars, lines, file, return_kind })
+    }
+}
+
+/// Serializes the program into the bytecode format
+pub fn encode(program : &CompiledProgram) -> Vec<u8> {
+    let mut encoder = Encoder { bytes : vec![] };
+
+    encoder.bytes.extend_from_slice(BYTECODE_MAGIC);
+    encoder.write_u32(BYTECODE_VERSION);
+
+    encoder.write_bool(program.has_main);
+    encoder.write_u64(program.next_function_id);
+
+    encoder.write_len(program.functions.len());
+    for f in &program.functions {
+        encoder.write_function(f);
+    }
+
+    encoder.bytes
+}
+
+/// Reads a program serialized by `encode`, refusing files made for another version of the format
+pub fn decode(bytes : &[u8]) -> Result<CompiledProgram, BytecodeError> {
+    if !is_bytecode(bytes) {
+        return Err(BytecodeError::new("O arquivo não é um programa compilado do BirlScript"));
+    }
+
+    let mut decoder = Decoder { bytes, offset : BYTECODE_MAGIC.len() };
+
+    let version = decoder.read_u32()?;
+    if version != BYTECODE_VERSION {
+        return Err(BytecodeError::new(format!("Versão do bytecode incompatível : {}. A versão suportada é {}",
+                                              version, BYTECODE_VERSION)));
+    }
+
+    let has_main = decoder.read_bool()?;
+    let next_function_id = decoder.read_u64()?;
+
+    let num_functions = decoder.read_len()?;
+    let mut functions : Vec<FunctionEntry> = Vec::with_capacity(num_functions);
+    for _ in 0..num_functions {
+        let f = decoder.read_function()?;
+
+        if functions.iter().any(|other| other.id == f.id) {
+            return Err(BytecodeError::new(format!("Múltiplas funções com ID {}", f.id)));
+        }
+
+        functions.push(f);
+    }
+
+    if decoder.offset != bytes.len() {
+        return Err(BytecodeError::new(format!("Dados sobrando depois do byte {}", decoder.offset)));
+    }
+
+    if !functions.iter().any(|f| f.id == BIRL_GLOBAL_FUNCTION_ID) {
+        return Err(BytecodeError::new("O programa não tem o escopo global"));
+    }
+
+    if has_main != functions.iter().any(|f| f.id == BIRL_MAIN_FUNCTION_ID) {
+        return Err(BytecodeError::new("O programa não bate com a declaração da função principal"));
+    }
+
+    Ok(CompiledProgram { functions, has_main, next_function_id })
+}
+
+mod tests {
+    #[test]
+    fn round_trip() {
+        use bytecode::*;
+        use context::{ Context, FunctionEntry, BIRL_GLOBAL_FUNCTION_ID };
+        use vm::Instruction;
+
+        let mut context = Context::new();
+
+        let source = "VEM: GLOBAL, 42\n\
+                      JAULA SOMA(A : TRAPEZIO DESCENDENTE, B : TRAPEZIO DESCENDENTE) : TRAPEZIO DESCENDENTE\n\
+                      BIRL: A + B\n\
+                      SAINDO DA JAULA\n\
+                      JAULA SHOW\n\
+                      VEM: X, SOMA(1.5, 2) * -3 % 7\n\
+                      VEM: L : MARMITA, [1, \"dois\", VERDADE]\n\
+                      ENQUANTO FOR VERDADE: X < 10 E NAO MENTIRA\n\
+                      BORA: X, X + 1\n\
+                      FIM\n\
+                      CE QUER VER: L[1], X\n\
+                      SAINDO DA JAULA";
+
+        if let Err(e) = context.add_source_string(source.to_owned()) {
+            panic!("Erro compilando o código : {}", e);
+        }
+
+        let bytes = match context.to_bytecode() {
+            Ok(b) => b,
+            Err(e) => panic!("Erro gerando o bytecode : {}", e),
+        };
+
+        let program = match decode(bytes.as_slice()) {
+            Ok(p) => p,
+            Err(e) => panic!("Erro lendo o bytecode : {}", e),
+        };
+
+        let original : Vec<&FunctionEntry> = ["__global__", "SOMA", "SHOW"].iter()
+            .map(|n| context.get_function_by_name(n).expect("Função não encontrada"))
+            .collect();
+
+        assert_eq!(program.functions.len(), original.len());
+        assert!(program.has_main);
+
+        for (loaded, original) in program.functions.iter().zip(original.iter()) {
+            assert_eq!(loaded.name, original.name);
+            assert_eq!(loaded.id, original.id);
+            assert_eq!(loaded.params, original.params);
+            assert_eq!(loaded.return_kind, original.return_kind);
+            assert_eq!(loaded.next_var_id, original.next_var_id);
+            assert_eq!(format!("{:?}", loaded.vars), format!("{:?}", original.vars));
+            assert_eq!(format!("{:?}", loaded.declared_vars), format!("{:?}", original.declared_vars));
+            assert_eq!(loaded.lines, original.lines);
+            assert_eq!(loaded.file, original.file);
+
+            // The name of the user is left out, see Context::to_bytecode
+            let mut body = original.body.clone();
+
+            if original.id == BIRL_GLOBAL_FUNCTION_ID {
+                body[0] = Instruction::PushMainStr(String::new());
+            }
+
+            assert_eq!(format!("{:?}", loaded.body), format!("{:?}", body));
+        }
+
+        // Encoding what was loaded must give back the same bytes
+        assert_eq!(encode(&program), bytes);
+
+        // Every truncated file is an error, never a panic
+        for len in 0..bytes.len() {
+            assert!(decode(&bytes[..len]).is_err(), "Bytecode cortado em {} foi aceito", len);
+        }
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[BYTECODE_MAGIC.len()] = 0xFF;
+        assert!(decode(wrong_version.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,809 @@
+//! Looks for values of the wrong type before the program runs. The VM only finds them when the
+//! instruction using them runs, so a mistake in a branch that rarely runs goes unnoticed. Only what
+//! is sure to fail is reported: values that can't be known before running, like what a function
+//! returns, are accepted anywhere.
+
+use ast::{ Program, Item, Statement, Block };
+use parser::{ Expression, ExpressionNode, MathOperator, MathValue, Command, CommandArgument, CommandKind,
+              TypeKind, FunctionDeclaration };
+use compiler::{ Compiler, PRECEDENCE_COMPARISION, PRECEDENCE_POWER };
+use context::{ FunctionEntry, BIRL_GLOBAL_FUNCTION_ID, BIRL_FUNCTION_NAME_CONSTANT };
+use error::{ Error, TypeError, Span };
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Type {
+    Known(TypeKind),
+    // Either Int or Num, like the result of ^ between integers, which depends on the exponent
+    Numeric,
+    // Only known when running
+    Unknown,
+}
+
+impl Type {
+    // What a value checked against `kind` can be. Int is accepted for a Num, and stays an Int
+    fn annotated(kind : TypeKind) -> Type {
+        match kind {
+            TypeKind::Number => Type::Numeric,
+            kind => Type::Known(kind),
+        }
+    }
+
+    fn is_numeric(self) -> bool {
+        matches!(self, Type::Known(TypeKind::Integer) | Type::Known(TypeKind::Number) | Type::Numeric)
+    }
+
+    // Same as the VM: numbers go with numbers, anything else only with its own type
+    fn is_compatible(self, other : Type) -> bool {
+        match (self, other) {
+            (Type::Unknown, _) | (_, Type::Unknown) => true,
+            (l, r) if l.is_numeric() && r.is_numeric() => true,
+            (l, r) => l == r,
+        }
+    }
+
+    // Whether a value of this type passes AssertMainTopTypeCompatible, where an Int is also a Num
+    fn fits(self, kind : TypeKind) -> bool {
+        match (self, kind) {
+            (Type::Unknown, _) => true,
+            (Type::Numeric, TypeKind::Integer) | (Type::Numeric, TypeKind::Number) => true,
+            (Type::Known(TypeKind::Integer), TypeKind::Number) => true,
+            (Type::Known(k), kind) => k == kind,
+            (Type::Numeric, _) => false,
+        }
+    }
+
+    // Type of a variable that may have been written with either, like after a conditional
+    fn join(self, other : Type) -> Type {
+        match (self, other) {
+            (l, r) if l == r => l,
+            (l, r) if l.is_numeric() && r.is_numeric() => Type::Numeric,
+            _ => Type::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Known(kind) => kind.fmt(f),
+            Type::Numeric => write!(f, "número"),
+            Type::Unknown => write!(f, "desconhecido"),
+        }
+    }
+}
+
+fn symbol(op : MathOperator) -> &'static str {
+    match op {
+        MathOperator::Plus => "+",
+        MathOperator::Minus | MathOperator::Negate => "-",
+        MathOperator::Division => "/",
+        MathOperator::Multiplication => "*",
+        MathOperator::Modulo => "%",
+        MathOperator::Power => "^",
+        MathOperator::ParenthesisLeft => "(",
+        MathOperator::ParenthesisRight => ")",
+        MathOperator::Equal => "==",
+        MathOperator::NotEqual => "!=",
+        MathOperator::Less => "<",
+        MathOperator::LessOrEqual => "<=",
+        MathOperator::Greater => ">",
+        MathOperator::GreaterOrEqual => ">=",
+        MathOperator::And => "E",
+        MathOperator::Or => "OU",
+        MathOperator::Not => "NAO",
+    }
+}
+
+// Variables the commands write to, besides declaring them
+fn written_names(statements : &[Statement], names : &mut Vec<String>) {
+    for statement in statements {
+        match statement {
+            Statement::Command(cmd, _) => {
+                match cmd.kind {
+                    CommandKind::Set | CommandKind::ListPop | CommandKind::ListLength |
+                    CommandKind::GetStringInput | CommandKind::GetNumberInput | CommandKind::GetIntegerInput |
+                    CommandKind::ConvertToNum | CommandKind::ConvertToInt | CommandKind::IntoString => {
+                        for arg in &cmd.arguments {
+                            if let CommandArgument::Name(name) = arg {
+                                names.push(name.clone());
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Statement::Block(block) => {
+                written_names(&block.body, names);
+
+                if let Some((_, alternative)) = &block.alternative {
+                    written_names(alternative, names);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Var {
+    name : String,
+    value : Type,
+    // Declared type. Every write is checked against it, so the value never changes
+    kind : Option<TypeKind>,
+}
+
+impl Var {
+    fn new(name : &str, value : Type, kind : Option<TypeKind>) -> Var {
+        let value = match kind {
+            Some(kind) => Type::annotated(kind),
+            None => value,
+        };
+
+        Var { name : name.to_owned(), value, kind }
+    }
+}
+
+struct Checker {
+    functions : Vec<FunctionDeclaration>,
+    // Globals as seen from inside functions
+    globals : Vec<Var>,
+    // Variables of the code being checked, from the outermost scope to the innermost
+    vars : Vec<Var>,
+    // Variables the global code can't trust the type of, since functions write to them
+    volatile : Vec<String>,
+    function : Option<String>,
+    span : Span,
+    errors : Vec<Error>,
+}
+
+impl Checker {
+    fn error(&mut self, message : String) {
+        let mut error = TypeError::new(message);
+
+        error.span = self.span.clone();
+        error.span.function = self.function.clone();
+
+        self.errors.push(error.into());
+    }
+
+    fn find(&self, name : &str) -> Option<&Var> {
+        self.vars.iter().rev()
+            .chain(self.globals.iter().rev())
+            .find(|v| v.name == name)
+    }
+
+    fn variable(&self, name : &str) -> Type {
+        match self.find(name) {
+            Some(v) => v.value,
+            None => Type::Unknown,
+        }
+    }
+
+    fn expect_kind(&mut self, name : &str, kind : Option<TypeKind>, value : Type) {
+        if let Some(kind) = kind {
+            if !value.fits(kind) {
+                self.error(format!("Erro de tipo : A variável {} é {}, mas recebeu {}", name, kind, value));
+            }
+        }
+    }
+
+    fn declare(&mut self, name : &str, kind : Option<TypeKind>, value : Type) {
+        self.expect_kind(name, kind, value);
+
+        let value = if self.function.is_none() && self.volatile.iter().any(|v| v == name) {
+            Type::Unknown
+        } else {
+            value
+        };
+
+        self.vars.push(Var::new(name, value, kind));
+    }
+
+    fn write(&mut self, name : &str, value : Type) {
+        let kind = self.find(name).and_then(|v| v.kind);
+
+        if kind.is_some() {
+            return self.expect_kind(name, kind, value);
+        }
+
+        let volatile = self.function.is_none() && self.volatile.iter().any(|v| v == name);
+
+        // Globals written from a function are already unknown there
+        if let Some(var) = self.vars.iter_mut().rev().find(|v| v.name == name) {
+            var.value = if volatile { Type::Unknown } else { value };
+        }
+    }
+
+    // Keeps, for each variable, a type that also covers what it had in `other`
+    fn join_vars(&mut self, other : &[Var]) {
+        for (var, old) in self.vars.iter_mut().zip(other.iter()) {
+            var.value = var.value.join(old.value);
+        }
+    }
+
+    fn expect_list(&mut self, value : Type, what : &str) {
+        if !value.fits(TypeKind::List) {
+            self.error(format!("Erro de tipo : {} espera uma {}, mas recebeu {}", what, TypeKind::List, value));
+        }
+    }
+
+    fn operation(&mut self, op : MathOperator, left : Type, right : Type) -> Type {
+        let valid = match op {
+            MathOperator::Plus => {
+                let allowed = |t : Type| t == Type::Unknown || t.is_numeric() || t == Type::Known(TypeKind::Text);
+
+                allowed(left) && allowed(right) && left.is_compatible(right)
+            }
+            MathOperator::Minus | MathOperator::Division | MathOperator::Multiplication |
+            MathOperator::Modulo | MathOperator::Power => {
+                left.fits(TypeKind::Number) && right.fits(TypeKind::Number)
+            }
+            MathOperator::And | MathOperator::Or => left.fits(TypeKind::Boolean) && right.fits(TypeKind::Boolean),
+            _ => left.is_compatible(right),
+        };
+
+        if !valid {
+            self.error(format!("Erro de tipo : O operador {} não funciona com {} e {}", symbol(op), left, right));
+
+            return Type::Unknown;
+        }
+
+        match op {
+            MathOperator::Plus | MathOperator::Minus | MathOperator::Division | MathOperator::Multiplication |
+            MathOperator::Modulo | MathOperator::Power => {
+                match (left, right) {
+                    (Type::Unknown, Type::Unknown) if op == MathOperator::Plus => Type::Unknown,
+                    (Type::Known(TypeKind::Text), _) | (_, Type::Known(TypeKind::Text)) => Type::Known(TypeKind::Text),
+                    (Type::Known(TypeKind::Number), _) | (_, Type::Known(TypeKind::Number)) => Type::Known(TypeKind::Number),
+                    (Type::Known(TypeKind::Integer), Type::Known(TypeKind::Integer)) if op != MathOperator::Power => {
+                        Type::Known(TypeKind::Integer)
+                    }
+                    _ => Type::Numeric,
+                }
+            }
+            _ => Type::Known(TypeKind::Boolean),
+        }
+    }
+
+    // Same precedence climbing as the compiler, so operators get the values they'll get when running.
+    // Malformed expressions are left for the compiler to report
+    fn binary(&mut self, expr : &Expression, offset : &mut usize, min_precedence : u8) -> Type {
+        let mut left = self.unary(expr, offset);
+
+        while *offset < expr.nodes.len() {
+            let op = match expr.nodes[*offset] {
+                ExpressionNode::Operator(MathOperator::ParenthesisRight) => break,
+                ExpressionNode::Operator(op) => op,
+                _ => {
+                    *offset = expr.nodes.len();
+                    return Type::Unknown;
+                }
+            };
+
+            let precedence = match Compiler::get_binary_precedence(op) {
+                Some(p) => p,
+                None => {
+                    *offset = expr.nodes.len();
+                    return Type::Unknown;
+                }
+            };
+
+            if precedence < min_precedence {
+                break;
+            }
+
+            *offset += 1;
+
+            let right_precedence = if op == MathOperator::Power {
+                precedence
+            } else {
+                precedence + 1
+            };
+
+            let right = self.binary(expr, offset, right_precedence);
+
+            left = self.operation(op, left, right);
+        }
+
+        left
+    }
+
+    fn unary(&mut self, expr : &Expression, offset : &mut usize) -> Type {
+        let node = match expr.nodes.get(*offset) {
+            Some(n) => n,
+            None => return Type::Unknown,
+        };
+
+        *offset += 1;
+
+        let mut value = match node {
+            ExpressionNode::Operator(MathOperator::Not) => {
+                let value = self.binary(expr, offset, PRECEDENCE_COMPARISION);
+
+                if !value.fits(TypeKind::Boolean) {
+                    self.error(format!("Erro de tipo : O operador NAO não funciona com {}", value));
+                }
+
+                return Type::Known(TypeKind::Boolean);
+            }
+            ExpressionNode::Operator(MathOperator::Negate) => {
+                let value = self.binary(expr, offset, PRECEDENCE_POWER);
+
+                if !value.fits(TypeKind::Number) {
+                    self.error(format!("Erro de tipo : O operador - não funciona com {}", value));
+
+                    return Type::Unknown;
+                }
+
+                return value;
+            }
+            ExpressionNode::Operator(MathOperator::ParenthesisLeft) => {
+                let value = self.binary(expr, offset, 0);
+
+                if let Some(ExpressionNode::Operator(MathOperator::ParenthesisRight)) = expr.nodes.get(*offset) {
+                    *offset += 1;
+                }
+
+                value
+            }
+            ExpressionNode::Operator(_) | ExpressionNode::Index(_) => {
+                *offset = expr.nodes.len();
+                return Type::Unknown;
+            }
+            ExpressionNode::Value(MathValue::Integer(_)) => Type::Known(TypeKind::Integer),
+            ExpressionNode::Value(MathValue::Number(_)) => Type::Known(TypeKind::Number),
+            ExpressionNode::Value(MathValue::Text(_)) => Type::Known(TypeKind::Text),
+            ExpressionNode::Symbol(name) => self.variable(name.as_str()),
+            ExpressionNode::List(elements) => {
+                for element in elements {
+                    self.expression(element);
+                }
+
+                Type::Known(TypeKind::List)
+            }
+            ExpressionNode::Call(name, args) => self.call(name.as_str(), args),
+        };
+
+        while let Some(ExpressionNode::Index(index)) = expr.nodes.get(*offset) {
+            *offset += 1;
+
+            self.expect_list(value, "O índice");
+
+            let index = self.expression(index);
+
+            if !index.fits(TypeKind::Integer) {
+                self.error(format!("Erro de tipo : O índice de uma {} deve ser {}, mas é {}",
+                                   TypeKind::List, TypeKind::Integer, index));
+            }
+
+            value = Type::Unknown;
+        }
+
+        value
+    }
+
+    fn expression(&mut self, expr : &Expression) -> Type {
+        let mut offset = 0usize;
+
+        self.binary(expr, &mut offset, 0)
+    }
+
+    // Returns the type of what the function returns, known only if it was declared
+    fn call(&mut self, name : &str, args : &[Expression]) -> Type {
+        let declaration = self.functions.iter()
+            .find(|f| f.name == name)
+            .cloned();
+
+        for (i, arg) in args.iter().enumerate() {
+            let value = self.expression(arg);
+
+            // Unknown functions and the wrong number of arguments are reported by the compiler
+            let param = match declaration.as_ref().and_then(|f| f.arguments.get(i)) {
+                Some(p) => p,
+                None => continue,
+            };
+
+            if !value.fits(param.kind) {
+                self.error(format!("Erro de tipo : O parâmetro {} da JAULA {} é {}, mas recebeu {}",
+                                   param.name, name, param.kind, value));
+            }
+        }
+
+        match declaration.and_then(|f| f.return_kind) {
+            Some(kind) => Type::annotated(kind),
+            None => Type::Unknown,
+        }
+    }
+
+    fn return_kind(&self) -> Option<TypeKind> {
+        let name = self.function.as_ref()?;
+
+        self.functions.iter()
+            .find(|f| &f.name == name)
+            .and_then(|f| f.return_kind)
+    }
+
+    fn command(&mut self, cmd : &Command) {
+        let mut exprs = vec![];
+        let mut names = vec![];
+        let mut kind = None;
+
+        for arg in &cmd.arguments {
+            match arg {
+                CommandArgument::Expression(e) => exprs.push(e),
+                CommandArgument::Name(n) => names.push(n.as_str()),
+                CommandArgument::Type(k) => kind = Some(*k),
+            }
+        }
+
+        match cmd.kind {
+            CommandKind::Declare | CommandKind::Set => {
+                let value = match exprs.first() {
+                    Some(e) => self.expression(e),
+                    None => return,
+                };
+
+                if let Some(name) = names.first() {
+                    if cmd.kind == CommandKind::Declare {
+                        self.declare(name, kind, value);
+                    } else {
+                        self.write(name, value);
+                    }
+                }
+            }
+            CommandKind::Compare |
+            CommandKind::ExecuteWhileEqual | CommandKind::ExecuteWhileNotEqual |
+            CommandKind::ExecuteWhileEqualOrLess | CommandKind::ExecuteWhileLess |
+            CommandKind::ExecuteWhileEqualOrGreater | CommandKind::ExecuteWhileGreater => {
+                let values : Vec<Type> = exprs.iter().map(|e| self.expression(e)).collect();
+
+                if let [left, right] = values[..] {
+                    if !left.is_compatible(right) {
+                        self.error(format!("Erro de tipo : Não dá pra comparar {} com {}", left, right));
+                    }
+                }
+            }
+            CommandKind::ExecuteIfTrue | CommandKind::ExecuteWhileTrue => {
+                for e in exprs {
+                    let value = self.expression(e);
+
+                    if !value.fits(TypeKind::Boolean) {
+                        self.error(format!("Erro de tipo : A condição deve ser um {}, mas é {}", TypeKind::Boolean, value));
+                    }
+                }
+            }
+            CommandKind::Repeat => {
+                for e in exprs {
+                    let value = self.expression(e);
+
+                    if !value.fits(TypeKind::Integer) {
+                        self.error(format!("Erro de tipo : O número de repetições deve ser {}, mas é {}",
+                                           TypeKind::Integer, value));
+                    }
+                }
+            }
+            CommandKind::ListPush | CommandKind::ListPop | CommandKind::ListLength => {
+                let values : Vec<Type> = exprs.iter().map(|e| self.expression(e)).collect();
+
+                if let Some(&list) = values.first() {
+                    self.expect_list(list, "O comando");
+                }
+
+                if let Some(name) = names.first() {
+                    let value = if cmd.kind == CommandKind::ListLength {
+                        Type::Known(TypeKind::Integer)
+                    } else {
+                        Type::Unknown
+                    };
+
+                    self.write(name, value);
+                }
+            }
+            CommandKind::GetStringInput | CommandKind::GetNumberInput | CommandKind::GetIntegerInput |
+            CommandKind::ConvertToNum | CommandKind::ConvertToInt | CommandKind::IntoString => {
+                let name = match names.first() {
+                    Some(n) => *n,
+                    None => return,
+                };
+
+                let converted = match cmd.kind {
+                    CommandKind::ConvertToNum | CommandKind::ConvertToInt => {
+                        let value = self.variable(name);
+
+                        value == Type::Unknown || value.is_numeric() || value == Type::Known(TypeKind::Text)
+                    }
+                    _ => true,
+                };
+
+                let result = match cmd.kind {
+                    CommandKind::GetStringInput | CommandKind::IntoString => TypeKind::Text,
+                    CommandKind::GetNumberInput | CommandKind::ConvertToNum => TypeKind::Number,
+                    _ => TypeKind::Integer,
+                };
+
+                if !converted {
+                    self.error(format!("Erro de tipo : Não dá pra converter {} ({}) pra {}",
+                                       name, self.variable(name), result));
+                }
+
+                self.write(name, Type::Known(result));
+            }
+            CommandKind::Call => {
+                let args : Vec<Expression> = exprs.into_iter().cloned().collect();
+
+                if let Some(name) = names.first() {
+                    self.call(name, &args);
+                }
+            }
+            CommandKind::Return => {
+                let value = exprs.first().map(|e| self.expression(e));
+
+                let kind = match self.return_kind() {
+                    Some(k) => k,
+                    None => return,
+                };
+
+                let name = self.function.clone().unwrap_or_default();
+
+                match value {
+                    // Without a value, the function returns Null, which never fits
+                    None => self.error(format!("Erro de tipo : A JAULA {} retorna {}, mas BIRL não tem valor",
+                                               name, kind)),
+                    Some(value) if !value.fits(kind) => {
+                        self.error(format!("Erro de tipo : A JAULA {} retorna {}, mas BIRL recebeu {}",
+                                           name, kind, value));
+                    }
+                    Some(_) => {}
+                }
+            }
+            CommandKind::Print | CommandKind::PrintLn | CommandKind::PrintDebug => {
+                for e in exprs {
+                    self.expression(e);
+                }
+            }
+            CommandKind::Quit | CommandKind::EndExecuteIf | CommandKind::Else |
+            CommandKind::ExecuteIfEqual | CommandKind::ExecuteIfNotEqual |
+            CommandKind::ExecuteIfEqualOrLess | CommandKind::ExecuteIfLess |
+            CommandKind::ExecuteIfEqualOrGreater | CommandKind::ExecuteIfGreater => {}
+        }
+    }
+
+    fn block(&mut self, block : &Block) {
+        let outer = self.vars.len();
+        let start = self.vars.clone();
+
+        self.span = block.span.clone();
+        self.command(&block.header);
+        self.statements(&block.body);
+        self.vars.truncate(outer);
+
+        if let Some((span, alternative)) = &block.alternative {
+            let body = self.vars.clone();
+
+            self.vars = start.clone();
+            self.span = span.clone();
+            self.statements(alternative);
+            self.vars.truncate(outer);
+            self.join_vars(&body);
+        } else {
+            // The body may not run at all. Loops end the same way, since the condition is checked
+            // before the body, so a variable has one of the types it had before or after it
+            self.join_vars(&start);
+        }
+    }
+
+    fn statements(&mut self, statements : &[Statement]) {
+        for statement in statements {
+            match statement {
+                Statement::Command(cmd, span) => {
+                    self.span = span.clone();
+                    self.command(cmd);
+                }
+                Statement::Block(block) => self.block(block),
+            }
+        }
+    }
+}
+
+/// Checks the types of the whole program. `known` has the functions that were compiled before it, so
+/// calls to them and their constants are checked too. Returns every error found, in order
+pub fn check(program : &Program, known : &[FunctionEntry]) -> Vec<Error> {
+    let mut functions : Vec<FunctionDeclaration> = known.iter()
+        .filter(|f| f.id != BIRL_GLOBAL_FUNCTION_ID)
+        .map(|f| FunctionDeclaration {
+            name : f.name.clone(),
+            arguments : f.params.clone(),
+            return_kind : f.return_kind,
+        })
+        .collect();
+
+    for f in program.functions() {
+        if !functions.iter().any(|k| k.name == f.declaration.name) {
+            functions.push(f.declaration.clone());
+        }
+    }
+
+    let mut vars = vec![];
+
+    if let Some(global) = known.iter().find(|f| f.id == BIRL_GLOBAL_FUNCTION_ID) {
+        for var in &global.vars {
+            // add_constant records the type of the value it gives the constant
+            let kind = match var.kind {
+                Some(k) if !var.writeable => Type::Known(k),
+                _ => Type::Unknown,
+            };
+
+            vars.push(Var::new(&var.name, kind, var.kind));
+        }
+    }
+
+    let mut global_code = vec![];
+    let mut volatile = vec![];
+
+    for item in &program.items {
+        match item {
+            Item::Statement(s) => global_code.push(s.clone()),
+            Item::Function(f) => written_names(&f.body, &mut volatile),
+        }
+    }
+
+    let mut checker = Checker {
+        functions,
+        globals : vec![],
+        vars,
+        volatile,
+        function : None,
+        span : Span::default(),
+        errors : vec![],
+    };
+
+    checker.statements(&global_code);
+
+    // Functions can run at any point of the global code, so they only trust globals nobody writes to
+    let mut written = vec![];
+    written_names(&global_code, &mut written);
+
+    checker.globals = checker.vars.iter()
+        .map(|v| {
+            let unchanged = v.kind.is_some() || (!written.contains(&v.name) && !checker.volatile.contains(&v.name));
+
+            Var { value : if unchanged { v.value } else { Type::Unknown }, ..v.clone() }
+        })
+        .collect();
+
+    for f in program.functions() {
+        checker.function = Some(f.declaration.name.clone());
+        checker.vars = vec![
+            Var::new("TREZE", Type::Unknown, None),
+            Var::new(BIRL_FUNCTION_NAME_CONSTANT, Type::Known(TypeKind::Text), None),
+        ];
+
+        // Parameters are checked like declared variables, since BORA on them is checked too
+        for param in &f.declaration.arguments {
+            checker.vars.push(Var::new(&param.name, Type::Unknown, Some(param.kind)));
+        }
+
+        checker.statements(&f.body);
+    }
+
+    let mut errors = checker.errors;
+
+    errors.sort_by_key(|e| e.span().line);
+
+    errors
+}
+
+mod tests {
+    #[test]
+    fn type_errors() {
+        use ast::parse_program;
+        use checker::check;
+        use context::Context;
+
+        fn errors(source : &str) -> Vec<(Option<usize>, String)> {
+            let program = match parse_program(source) {
+                Ok(p) => p,
+                Err(e) => panic!("Erro lendo o programa : {}", e),
+            };
+
+            let context = Context::new();
+
+            check(&program, context.functions()).iter()
+                .map(|e| (e.span().line, e.message().to_owned()))
+                .collect()
+        }
+
+        let valid = "VEM: X, 1\n\
+                     VEM: Y, X * 2.5 + 1\n\
+                     VEM: T, \"A\" + CUMPADE\n\
+                     VEM: L, [1, \"B\"]\n\
+                     BORA: X, L[X - 1]\n\
+                     E VERDADE: X == 1 E NAO Y < 2\n\
+                     \x20   BORA: T, 1\n\
+                     FIM\n\
+                     CE QUER VER: T + 1\n\
+                     JAULA F(N : TRAPEZIO DESCENDENTE, M : BATATA DOCE)\n\
+                     \x20   BIRL: N - M\n\
+                     SAINDO DA JAULA\n\
+                     E HORA DO: F, 1, 2\n\
+                     VEM: R, F(1.5, 2) + \"\"";
+
+        assert_eq!(errors(valid), vec![]);
+
+        let invalid = "VEM: X, \"A\" - 1\n\
+                       VEM: T, \"A\"\n\
+                       REPETE: T\n\
+                       FIM\n\
+                       E VERDADE: T\n\
+                       FIM\n\
+                       VEM: L, [T]\n\
+                       CE QUER VER: L[T] + VERDADE\n\
+                       JAULA F(N : BATATA DOCE)\n\
+                       \x20   BIRL: -JAULA\n\
+                       SAINDO DA JAULA\n\
+                       E HORA DO: F, 1.5\n\
+                       CE QUER VER: F(T)";
+
+        let found = errors(invalid);
+        let lines : Vec<Option<usize>> = found.iter().map(|e| e.0).collect();
+
+        assert_eq!(lines, vec![Some(1), Some(3), Some(5), Some(8), Some(8), Some(10), Some(12), Some(13)], "{:?}", found);
+        assert!(found[0].1.contains("FIBRA e BATATA DOCE"), "{:?}", found[0]);
+        assert!(found[6].1.contains("parâmetro N da JAULA F"), "{:?}", found[6]);
+
+        // After a loop, the variable may still have the type it had before it
+        let looping = "VEM: X, 1\n\
+                       REPETE: 2\n\
+                       \x20   CE QUER VER: X - 1\n\
+                       \x20   BORA: X, \"A\"\n\
+                       FIM\n\
+                       CE QUER VER: X + 1\n\
+                       CE QUER VER: X * VERDADE";
+
+        assert_eq!(errors(looping).len(), 1);
+
+        // Functions can change globals at any time
+        let globals = "VEM: X, 1\n\
+                       JAULA F\n\
+                       \x20   BORA: X, \"A\"\n\
+                       SAINDO DA JAULA\n\
+                       E HORA DO: F\n\
+                       CE QUER VER: X + \"B\"";
+
+        assert_eq!(errors(globals), vec![]);
+
+        // The default constants have the type of their value
+        let constants = "CE QUER VER: UM - BODYBUILDER\n\
+                         E VERDADE: CUMPADE\n\
+                         FIM\n\
+                         CE QUER VER: UM + 1, BODYBUILDER + CUMPADE, NAO MENTIRA";
+
+        let lines : Vec<Option<usize>> = errors(constants).iter().map(|e| e.0).collect();
+
+        assert_eq!(lines, vec![Some(1), Some(2)]);
+
+        // Declared types stay the same whatever is written, and calls give what the function returns
+        let annotated = "VEM: X : BATATA DOCE, 1\n\
+                         JAULA F\n\
+                         \x20   BORA: X, 2\n\
+                         SAINDO DA JAULA\n\
+                         JAULA G(N : TRAPEZIO DESCENDENTE) : FIBRA\n\
+                         \x20   E VERDADE: N > 0\n\
+                         \x20       BIRL: N\n\
+                         \x20   FIM\n\
+                         \x20   BORA: N, \"A\"\n\
+                         \x20   BIRL\n\
+                         SAINDO DA JAULA\n\
+                         VEM: T : FIBRA, X\n\
+                         CE QUER VER: X - G(1)\n\
+                         CE QUER VER: X * 2";
+
+        let found = errors(annotated);
+        let lines : Vec<Option<usize>> = found.iter().map(|e| e.0).collect();
+
+        assert_eq!(lines, vec![Some(7), Some(9), Some(10), Some(12), Some(13)], "{:?}", found);
+        assert!(found[0].1.contains("JAULA G retorna FIBRA"), "{:?}", found[0]);
+        assert!(found[3].1.contains("variável T é FIBRA"), "{:?}", found[3]);
+    }
+}
@@ -1,20 +1,37 @@
-use parser::{ Expression, ExpressionNode, Command, CommandArgument, MathOperator, MathValue, CommandKind };
+use parser::{ Expression, ExpressionNode, Command, CommandArgument, MathOperator, MathValue, CommandKind, TypeKind };
 use vm::Instruction;
-use context::{ BIRL_GLOBAL_FUNCTION_ID, FunctionEntry };
+use context::{ BIRL_GLOBAL_FUNCTION_ID, BIRL_RET_VAL_VAR_ID, FunctionEntry };
+use error::CompileError;
 
 #[derive(Debug, Clone)]
 pub struct Variable {
     pub name : String,
     pub id : u64,
     pub writeable : bool,
+    // Type every value written to the variable must have, if it was declared with one. For constants,
+    // the type of their value
+    pub kind : Option<TypeKind>,
 }
 
 pub enum CompilerHint {
     DeclareVar(Variable),
     ScopeStart,
     ScopeEnd,
+    // Ends the current conditional scope and starts the alternative one
+    ScopeElse,
+    // Starts a loop scope. Holds the address of the first instruction of the loop condition, so the
+    // end of the scope can jump back to it, and the hidden counter variable, if the loop has one
+    LoopStart(usize, Option<Variable>),
 }
 
+// Precedence of the operators, from the loosest to the tightest
+pub const PRECEDENCE_OR : u8 = 1;
+pub const PRECEDENCE_AND : u8 = 2;
+pub const PRECEDENCE_COMPARISION : u8 = 3;
+pub const PRECEDENCE_SUM : u8 = 4;
+pub const PRECEDENCE_PRODUCT : u8 = 5;
+pub const PRECEDENCE_POWER : u8 = 6;
+
 pub struct Compiler {}
 
 impl Compiler {
@@ -24,154 +41,262 @@ impl Compiler {
             MathOperator::Minus => Some(Instruction::MainSub),
             MathOperator::Division => Some(Instruction::MainDiv),
             MathOperator::Multiplication => Some(Instruction::MainMul),
+            MathOperator::Modulo => Some(Instruction::MainMod),
+            MathOperator::Power => Some(Instruction::MainPow),
+            MathOperator::Negate => Some(Instruction::MainNeg),
+            MathOperator::Equal => Some(Instruction::MainEqual),
+            MathOperator::NotEqual => Some(Instruction::MainNotEqual),
+            MathOperator::Less => Some(Instruction::MainLess),
+            MathOperator::LessOrEqual => Some(Instruction::MainLessOrEqual),
+            MathOperator::Greater => Some(Instruction::MainGreater),
+            MathOperator::GreaterOrEqual => Some(Instruction::MainGreaterOrEqual),
+            MathOperator::And => Some(Instruction::MainAnd),
+            MathOperator::Or => Some(Instruction::MainOr),
+            MathOperator::Not => Some(Instruction::MainNot),
+            _ => None,
+        }
+    }
+
+    // How tight each binary operator binds. Operators with a higher precedence are applied first
+    pub fn get_binary_precedence(op : MathOperator) -> Option<u8> {
+        match op {
+            MathOperator::Or => Some(PRECEDENCE_OR),
+            MathOperator::And => Some(PRECEDENCE_AND),
+            MathOperator::Equal | MathOperator::NotEqual |
+            MathOperator::Less | MathOperator::LessOrEqual |
+            MathOperator::Greater | MathOperator::GreaterOrEqual => Some(PRECEDENCE_COMPARISION),
+            MathOperator::Plus | MathOperator::Minus => Some(PRECEDENCE_SUM),
+            MathOperator::Multiplication | MathOperator::Division | MathOperator::Modulo => Some(PRECEDENCE_PRODUCT),
+            MathOperator::Power => Some(PRECEDENCE_POWER),
             _ => None,
         }
     }
 
-    fn compile_sub_expression(expr : &Expression, offset : &mut usize, inst : &mut Vec<Instruction>,
-                              func : &FunctionEntry, global : &Option<&FunctionEntry>) -> Result<(), String> {
-        let mut buffer : Vec<Instruction> = vec![];
+    // Compiles operands and the binary operators between them, as long as the operators bind at
+    // least as tight as min_precedence (precedence climbing). The right side of an operator is
+    // compiled with a higher minimum, so operators of the same level are applied from left to right,
+    // except for ^, which goes from right to left
+    fn compile_binary(expr : &Expression, offset : &mut usize, min_precedence : u8, inst : &mut Vec<Instruction>,
+                      func : &FunctionEntry, global : &Option<&FunctionEntry>, funcs : &Vec<FunctionEntry>) -> Result<(), CompileError> {
+        match Compiler::compile_unary(expr, offset, inst, func, global, funcs) {
+            Ok(_) => {}
+            Err(e) => return Err(e),
+        }
 
-        let mut last_imp_op : Option<MathOperator> = None;
+        while *offset < expr.nodes.len() {
+            let op = match expr.nodes[*offset] {
+                ExpressionNode::Operator(MathOperator::ParenthesisRight) => break,
+                ExpressionNode::Operator(op) => op,
+                ref node => return Err(CompileError::new(format!("Esperado um operador na expressão, encontrado {:?}", node))),
+            };
 
-        let mut op_stack : Vec<MathOperator> = vec![];
+            let precedence = match Compiler::get_binary_precedence(op) {
+                Some(p) => p,
+                None => return Err(CompileError::new(format!("O operador {:?} precisa de um valor antes dele", op))),
+            };
 
-        loop {
-            if *offset >= expr.nodes.len() {
+            if precedence < min_precedence {
                 break;
             }
 
-            let ref current = expr.nodes[*offset];
             *offset += 1;
 
-            match current {
-                &ExpressionNode::Operator(op) => {
-                    match op {
-                        MathOperator::ParenthesisLeft => {
-                            match Compiler::compile_sub_expression(expr, offset, inst, func, &global) {
-                                Ok(_) => {}
-                                Err(e) => return Err(e),
-                            }
+            let right_precedence = if op == MathOperator::Power {
+                precedence
+            } else {
+                precedence + 1
+            };
 
-                            if let Some(op) = last_imp_op {
-                                let i = match Compiler::get_inst_for_op(op) {
-                                    Some(i) => i,
-                                    None => return Err("Invalid operator in important operator".to_owned()),
-                                };
+            match Compiler::compile_binary(expr, offset, right_precedence, inst, func, global, funcs) {
+                Ok(_) => {}
+                Err(e) => return Err(e),
+            }
 
-                                buffer.push(i);
+            match Compiler::get_inst_for_op(op) {
+                Some(i) => inst.push(i),
+                None => return Err(CompileError::new(format!("Erro interno : Operador sem instrução : {:?}", op))),
+            }
+        }
 
-                                last_imp_op = None;
-                            }
-                        }
-                        MathOperator::ParenthesisRight => break,
-                        MathOperator::Plus | MathOperator::Minus => {
-                            op_stack.push(op);
-                        }
-                        _ => {
-                            if let Some(_) = last_imp_op {
-                                return Err("Two subsequent important operators".to_owned());
-                            }
+        Ok(())
+    }
 
-                            last_imp_op = Some(op);
-                        }
-                    }
-                }
-                &ExpressionNode::Value(ref v) => {
-                    match v {
-                        &MathValue::Integer(i) => {
-                            buffer.push(Instruction::PushMainInt(i))
-                        }
-                        &MathValue::Number(n) => {
-                            buffer.push(Instruction::PushMainNum(n))
-                        }
-                        &MathValue::Text(ref s) => {
-                            buffer.push(Instruction::PushMainStr(s.clone()))
-                        }
-                    }
+    // Compiles a single operand, with the unary operators before it and the indexes after it
+    fn compile_unary(expr : &Expression, offset : &mut usize, inst : &mut Vec<Instruction>,
+                     func : &FunctionEntry, global : &Option<&FunctionEntry>, funcs : &Vec<FunctionEntry>) -> Result<(), CompileError> {
+        if *offset >= expr.nodes.len() {
+            return Err(CompileError::new("Expressão acaba esperando um valor"));
+        }
 
-                    if let Some(op) = last_imp_op {
-                        let i = match Compiler::get_inst_for_op(op) {
-                            Some(i) => i,
-                            None => return Err("Invalid operator in important operator".to_owned()),
-                        };
+        let current = &expr.nodes[*offset];
+        *offset += 1;
 
-                        buffer.push(i);
+        match current {
+            ExpressionNode::Operator(MathOperator::Not) => {
+                // NAO X == Y is NAO (X == Y), but NAO X E Y is (NAO X) E Y
+                match Compiler::compile_binary(expr, offset, PRECEDENCE_COMPARISION, inst, func, global, funcs) {
+                    Ok(_) => {}
+                    Err(e) => return Err(e),
+                }
 
-                        last_imp_op = None;
-                    }
+                inst.push(Instruction::MainNot);
+
+                return Ok(());
+            }
+            ExpressionNode::Operator(MathOperator::Negate) => {
+                // -X ^ 2 is -(X ^ 2), but -X * 2 is (-X) * 2
+                match Compiler::compile_binary(expr, offset, PRECEDENCE_POWER, inst, func, global, funcs) {
+                    Ok(_) => {}
+                    Err(e) => return Err(e),
                 }
-                &ExpressionNode::Symbol(ref s) => {
-                    let mut on_global = false;
 
-                    let id = match func.get_id_for(s.as_str()) {
-                        Some(i) => {
-                            if func.id == BIRL_GLOBAL_FUNCTION_ID {
-                                on_global = true;
-                            }
+                inst.push(Instruction::MainNeg);
 
-                            i
-                        }
-                        None => {
-                            if func.id == BIRL_GLOBAL_FUNCTION_ID {
-                                return Err(format!("Variável não encontrada : {}", s.as_str()));
-                            }
+                return Ok(());
+            }
+            ExpressionNode::Operator(MathOperator::ParenthesisLeft) => {
+                match Compiler::compile_binary(expr, offset, 0, inst, func, global, funcs) {
+                    Ok(_) => {}
+                    Err(e) => return Err(e),
+                }
 
-                            if let &Some(ref g) = global {
-                                on_global = true;
+                match expr.nodes.get(*offset) {
+                    Some(&ExpressionNode::Operator(MathOperator::ParenthesisRight)) => *offset += 1,
+                    _ => return Err(CompileError::new("Parêntese aberto e nunca fechado na expressão")),
+                }
+            }
+            ExpressionNode::Operator(op) => {
+                return Err(CompileError::new(format!("Esperado um valor na expressão, encontrado o operador {:?}", op)));
+            }
+            ExpressionNode::Value(v) => {
+                match *v {
+                    MathValue::Integer(i) => inst.push(Instruction::PushMainInt(i)),
+                    MathValue::Number(n) => inst.push(Instruction::PushMainNum(n)),
+                    MathValue::Text(ref s) => inst.push(Instruction::PushMainStr(s.clone())),
+                }
+            }
+            ExpressionNode::Symbol(s) => {
+                let mut is_global = false;
 
-                                match g.get_id_for(s.as_str()) {
-                                    Some(i) => i,
-                                    None => return Err(format!("Variável não encontrada : {}", s.as_str())),
-                                }
-                            } else {
-                                return Err("Erro interno : Função não é global e global é None".to_owned());
-                            }
-                        }
-                    };
+                let id = match Compiler::get_id_and_globalness(s.as_str(), func, global, &mut is_global) {
+                    Some(id) => id,
+                    None => return Err(CompileError::new(format!("Variável não encontrada : {}", s.as_str()))),
+                };
 
-                    let inst = if on_global {
-                        Instruction::ReadGlobalVarWithId(id)
-                    } else {
-                        Instruction::ReadVarWithId(id)
-                    };
+                if is_global {
+                    inst.push(Instruction::ReadGlobalVarWithId(id));
+                } else {
+                    inst.push(Instruction::ReadVarWithId(id));
+                }
+            }
+            ExpressionNode::List(elements) => {
+                for element in elements {
+                    match Compiler::compile_expression(element, inst, func, global, funcs) {
+                        Ok(_) => {}
+                        Err(e) => return Err(e),
+                    }
+                }
 
-                    buffer.push(inst);
+                inst.push(Instruction::MakeList(elements.len()));
+            }
+            ExpressionNode::Index(_) => {
+                return Err(CompileError::new("Erro : Índice sem um valor antes dele"));
+            }
+            ExpressionNode::Call(name, args) => {
+                match Compiler::compile_call(name.as_str(), args, inst, func, global, funcs) {
+                    Ok(_) => {}
+                    Err(e) => return Err(e),
+                }
 
-                    if let Some(op) = last_imp_op {
-                        let i = match Compiler::get_inst_for_op(op) {
-                            Some(i) => i,
-                            None => return Err("Invalid operator in important operator".to_owned()),
-                        };
+                // The function is done when the next instruction runs, with its result on the main
+                // stack
+            }
+        }
 
-                        buffer.push(i);
+        Compiler::compile_indexes(expr, offset, inst, func, global, funcs)
+    }
 
-                        last_imp_op = None;
+    // Compiles the indexes right after a value, so they're applied before any operator
+    fn compile_indexes(expr : &Expression, offset : &mut usize, inst : &mut Vec<Instruction>,
+                       func : &FunctionEntry, global : &Option<&FunctionEntry>, funcs : &Vec<FunctionEntry>) -> Result<(), CompileError> {
+        while *offset < expr.nodes.len() {
+            match expr.nodes[*offset] {
+                ExpressionNode::Index(ref index) => {
+                    match Compiler::compile_expression(index, inst, func, global, funcs) {
+                        Ok(_) => {}
+                        Err(e) => return Err(e),
                     }
+
+                    inst.push(Instruction::ListGet);
                 }
+                _ => break,
             }
+
+            *offset += 1;
         }
 
-        for op in op_stack {
-            let inst = match Compiler::get_inst_for_op(op) {
-                Some(i) => i,
-                None => return Err("Erro: Invalid operator on stack".to_owned()),
-            };
+        Ok(())
+    }
 
-            buffer.push(inst);
+    // Creates the frame for the function, writes the arguments to it and starts it. The function runs
+    // before the instruction after these, and leaves its result on the top of the main stack
+    fn compile_call(name : &str, args : &[Expression], inst : &mut Vec<Instruction>, func : &FunctionEntry,
+                    global : &Option<&FunctionEntry>, funcs : &Vec<FunctionEntry>) -> Result<(), CompileError> {
+        let cf = match funcs.iter().find(|f| f.name == name) {
+            Some(f) => f,
+            None => return Err(CompileError::new(format!("A função {} não foi encontrada", name))),
+        };
+
+        if cf.params.len() != args.len() {
+            return Err(CompileError::new(format!("A função {} espera {} argumentos, mas {} foram passados",
+                name, cf.params.len(), args.len())));
         }
 
-        for i in buffer {
-            inst.push(i);
+        inst.push(Instruction::MakeNewFrame(cf.id));
+
+        // Push arguments and check their type
+
+        for (param, expr) in cf.params.iter().zip(args.iter()) {
+            let arg_id = match cf.vars.iter().find(|v| v.name == param.name) {
+                Some(v) => v.id,
+                None => return Err(CompileError::new(format!("Erro interno : O parâmetro {} não está registrado como variável",
+                                                             param.name))),
+            };
+
+            match Compiler::check_known_kind(expr, param.kind, func, global) {
+                Ok(_) => {}
+                Err(e) => return Err(e)
+            };
+
+            match Compiler::compile_expression(expr, inst, func, global, funcs) {
+                Ok(_) => {}
+                Err(e) => return Err(e)
+            };
+
+            inst.push(Instruction::AssertMainTopTypeCompatible(param.kind));
+
+            inst.push(Instruction::WriteToLastFrameVarWithId(arg_id));
         }
 
+        inst.push(Instruction::SetLastFrameReady);
+
         Ok(())
     }
 
     pub fn compile_expression(expr : &Expression, inst : &mut Vec<Instruction>, func : &FunctionEntry,
-                              global : &Option<&FunctionEntry>) -> Result<(), String> {
+                              global : &Option<&FunctionEntry>, funcs : &Vec<FunctionEntry>) -> Result<(), CompileError> {
         let mut offset = 0usize;
-        Compiler::compile_sub_expression(expr, &mut offset, inst, func, global)
+
+        match Compiler::compile_binary(expr, &mut offset, 0, inst, func, global, funcs) {
+            Ok(_) => {}
+            Err(e) => return Err(e),
+        }
+
+        if offset < expr.nodes.len() {
+            return Err(CompileError::new("Parêntese fechado sem ter sido aberto na expressão"));
+        }
+
+        Ok(())
     }
 
     fn get_id_and_globalness(name : &str, func : &FunctionEntry, global : &Option<&FunctionEntry>, is_global : &mut bool) -> Option<u64> {
@@ -203,28 +328,121 @@ impl Compiler {
         }
     }
 
+    // Same as get_id_and_globalness, but fails if the variable doesn't exist or is a constant
+    fn get_writeable_id_and_globalness(name : &str, func : &FunctionEntry, global : &Option<&FunctionEntry>,
+                                       is_global : &mut bool) -> Result<u64, CompileError> {
+        let id = match Compiler::get_id_and_globalness(name, func, global, is_global) {
+            Some(id) => id,
+            None => return Err(CompileError::new(format!("Variável {} não encontrada", name)))
+        };
+
+        let owner = match global {
+            Some(g) if *is_global => g,
+            _ => func,
+        };
+
+        for v in &owner.vars {
+            if v.id == id && !v.writeable {
+                return Err(CompileError::new(format!("A variável {} é constante e não pode ser modificada", name)));
+            }
+        }
+
+        Ok(id)
+    }
+
+    // Type the expression is known to have before the program runs: literals, lists, constants and
+    // variables declared with a type. A TRAPEZIO DESCENDENTE variable may hold a BATATA DOCE, so its
+    // type is left unknown
+    fn known_kind(expr : &Expression, func : &FunctionEntry, global : &Option<&FunctionEntry>) -> Option<TypeKind> {
+        if expr.nodes.len() != 1 {
+            return None;
+        }
+
+        match expr.nodes[0] {
+            ExpressionNode::Value(MathValue::Integer(_)) => Some(TypeKind::Integer),
+            ExpressionNode::Value(MathValue::Number(_)) => Some(TypeKind::Number),
+            ExpressionNode::Value(MathValue::Text(_)) => Some(TypeKind::Text),
+            ExpressionNode::List(_) => Some(TypeKind::List),
+            ExpressionNode::Symbol(ref name) => {
+                let mut is_global = false;
+
+                let id = Compiler::get_id_and_globalness(name.as_str(), func, global, &mut is_global)?;
+
+                let owner = match global {
+                    Some(g) if is_global => g,
+                    _ => func,
+                };
+
+                match owner.vars.iter().find(|v| v.id == id).and_then(|v| v.kind) {
+                    Some(TypeKind::Number) => None,
+                    kind => kind,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    // Fails the compilation if the expression is known to give a value that doesn't fit where a value
+    // of `kind` is expected. Values of unknown type are checked when the program runs
+    fn check_known_kind(expr : &Expression, kind : TypeKind, func : &FunctionEntry,
+                        global : &Option<&FunctionEntry>) -> Result<(), CompileError> {
+        match Compiler::known_kind(expr, func, global) {
+            Some(TypeKind::Integer) if kind == TypeKind::Number => Ok(()),
+            Some(found) if found != kind => {
+                Err(CompileError::new(format!("Tipo incompatível : Esperado {}, encontrado {}", kind, found)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Type the variable was declared with, if it was declared with one
+    fn var_kind(id : u64, is_global : bool, func : &FunctionEntry, global : &Option<&FunctionEntry>) -> Option<TypeKind> {
+        let owner = match global {
+            Some(g) if is_global => g,
+            _ => func,
+        };
+
+        owner.vars.iter().find(|v| v.id == id).and_then(|v| v.kind)
+    }
+
+    // Writes the main top to the variable, checking first that it has the type the variable was
+    // declared with
+    fn write_var(id : u64, is_global : bool, func : &FunctionEntry, global : &Option<&FunctionEntry>,
+                 instructions : &mut Vec<Instruction>) {
+        if let Some(kind) = Compiler::var_kind(id, is_global, func, global) {
+            instructions.push(Instruction::AssertMainTopTypeCompatible(kind));
+        }
+
+        if is_global {
+            instructions.push(Instruction::WriteToGlobalVarWithId(id));
+        } else {
+            instructions.push(Instruction::WriteToVarWithId(id));
+        }
+    }
+
     pub fn compile_command(mut cmd : Command, func : &FunctionEntry, global : &Option<&FunctionEntry>,
-        funcs : &Vec<FunctionEntry>, instructions : &mut Vec<Instruction>) -> Result<Option<CompilerHint>, String> {
+        funcs : &Vec<FunctionEntry>, instructions : &mut Vec<Instruction>) -> Result<Option<CompilerHint>, CompileError> {
 
         match cmd.kind {
             CommandKind::PrintDebug => {
                 // Evaluate the single argument and print-debug it
 
                 if cmd.arguments.len() != 1 {
-                    return Err("Internal error : Debug print command has more than 1 argument (or less)".to_owned());
+                    return Err(CompileError::new("Internal error : Debug print command has more than 1 argument (or less)"));
                 }
 
                 for arg in cmd.arguments {
                     match arg {
                         CommandArgument::Expression(expr) => {
-                            match Compiler::compile_expression(&expr, instructions, &func, global) {
+                            match Compiler::compile_expression(&expr, instructions, func, global, funcs) {
                                 Ok(_) => {},
                                 Err(e) => return Err(e),
                             };
 
                             instructions.push(Instruction::MainPrintDebug);
+                            instructions.push(Instruction::PopMain);
                         }
-                        _ => return Err("Erro : Um argumento diferente de valor foi passado pra print. Erro interno.".to_owned()),
+                        _ => return Err(CompileError::new("Erro : Um argumento diferente de valor foi passado pra print. Erro interno.")),
                     }
                 }
             }
@@ -232,14 +450,15 @@ impl Compiler {
                 for arg in cmd.arguments {
                     match arg {
                         CommandArgument::Expression(expr) => {
-                            match Compiler::compile_expression(&expr, instructions, &func, global) {
+                            match Compiler::compile_expression(&expr, instructions, func, global, funcs) {
                                 Ok(_) => {},
                                 Err(e) => return Err(e),
                             };
 
                             instructions.push(Instruction::MainPrint);
+                            instructions.push(Instruction::PopMain);
                         }
-                        _ => return Err("Erro : Um argumento diferente de valor foi passado pra print. Erro interno.".to_owned()),
+                        _ => return Err(CompileError::new("Erro : Um argumento diferente de valor foi passado pra print. Erro interno.")),
                     }
                 }
 
@@ -249,14 +468,15 @@ impl Compiler {
                 for arg in cmd.arguments {
                     match arg {
                         CommandArgument::Expression(expr) => {
-                            match Compiler::compile_expression(&expr, instructions, &func, global) {
+                            match Compiler::compile_expression(&expr, instructions, func, global, funcs) {
                                 Ok(_) => {},
                                 Err(e) => return Err(e),
                             };
 
                             instructions.push(Instruction::MainPrint);
+                            instructions.push(Instruction::PopMain);
                         }
-                        _ => return Err("Erro : Um argumento diferente de valor foi passado pra print. Erro interno.".to_owned()),
+                        _ => return Err(CompileError::new("Erro : Um argumento diferente de valor foi passado pra print. Erro interno.")),
                     }
                 }
 
@@ -265,53 +485,60 @@ impl Compiler {
             CommandKind::Quit => instructions.push(Instruction::Quit),
             CommandKind::Set => {
                 if cmd.arguments.len() != 2 {
-                    return Err(format!("O comando BORA espera 2 argumentos, mas {} foram passados (Erro interno)", cmd.arguments.len()));
+                    return Err(CompileError::new(format!("O comando BORA espera 2 argumentos, mas {} foram passados (Erro interno)", cmd.arguments.len())));
                 }
 
                 let name_arg = cmd.arguments.remove(0);
 
                 let name = match name_arg {
                     CommandArgument::Name(n) => n,
-                    _ => return Err(format!("Erro interno : Esperado um nome pro BORA, encontrado {:?}", name_arg)),
+                    _ => return Err(CompileError::new(format!("Erro interno : Esperado um nome pro BORA, encontrado {:?}", name_arg))),
                 };
 
                 let mut is_global = false;
 
-                let id = match Compiler::get_id_and_globalness(name.as_str(), func, global, &mut is_global) {
-                    Some(id) => id,
-                    None => return Err(format!("Variável {} não encontrada", name))
-                };
+                let id = Compiler::get_writeable_id_and_globalness(name.as_str(), func, global, &mut is_global)?;
 
                 let expr_arg = cmd.arguments.remove(0);
 
                 match expr_arg {
                     CommandArgument::Expression(expr) => {
-                        match Compiler::compile_expression(&expr, instructions, &func, global) {
+                        if let Some(kind) = Compiler::var_kind(id, is_global, func, global) {
+                            match Compiler::check_known_kind(&expr, kind, func, global) {
+                                Ok(_) => {}
+                                Err(e) => return Err(e)
+                            }
+                        }
+
+                        match Compiler::compile_expression(&expr, instructions, func, global, funcs) {
                             Ok(_) => {}
                             Err(e) => return Err(e)
                         }
                     }
-                    _ => return Err(format!("Erro interno : Esperado uma expressão depois do nome, encontrado {:?}", expr_arg)),
+                    _ => return Err(CompileError::new(format!("Erro interno : Esperado uma expressão depois do nome, encontrado {:?}", expr_arg))),
                 }
 
-                let inst = if is_global {
-                    Instruction::WriteToGlobalVarWithId(id)
-                } else {
-                    Instruction::WriteToVarWithId(id)
-                };
-
-                instructions.push(inst);
+                Compiler::write_var(id, is_global, func, global, instructions);
             }
             CommandKind::Declare => {
-                if cmd.arguments.len() != 2 {
-                    return Err(format!("O comando BORA espera 2 argumentos, mas {} foram passados (Erro interno)", cmd.arguments.len()));
+                if cmd.arguments.len() != 2 && cmd.arguments.len() != 3 {
+                    return Err(CompileError::new(format!("O comando VEM espera 2 argumentos, mas {} foram passados (Erro interno)", cmd.arguments.len())));
                 }
 
                 let name_arg = cmd.arguments.remove(0);
 
                 let name = match name_arg {
                     CommandArgument::Name(n) => n,
-                    _ => return Err(format!("Erro interno : Esperado um nome pro BORA, encontrado {:?}", name_arg)),
+                    _ => return Err(CompileError::new(format!("Erro interno : Esperado um nome pro VEM, encontrado {:?}", name_arg))),
+                };
+
+                let kind = match cmd.arguments[0] {
+                    CommandArgument::Type(kind) => {
+                        cmd.arguments.remove(0);
+
+                        Some(kind)
+                    }
+                    _ => None,
                 };
 
                 let is_global = func.id == BIRL_GLOBAL_FUNCTION_ID;
@@ -320,47 +547,69 @@ impl Compiler {
 
                 match expr_arg {
                     CommandArgument::Expression(expr) => {
-                        match Compiler::compile_expression(&expr, instructions, &func, global) {
+                        if let Some(kind) = kind {
+                            match Compiler::check_known_kind(&expr, kind, func, global) {
+                                Ok(_) => {}
+                                Err(e) => return Err(e)
+                            }
+                        }
+
+                        match Compiler::compile_expression(&expr, instructions, func, global, funcs) {
                             Ok(_) => {}
                             Err(e) => return Err(e)
                         }
                     }
-                    _ => return Err(format!("Erro interno : Esperado uma expressão depois do nome, encontrado {:?}", expr_arg)),
+                    _ => return Err(CompileError::new(format!("Erro interno : Esperado uma expressão depois do nome, encontrado {:?}", expr_arg))),
                 }
 
                 // Add the variable after the expression is parsed, so we can't use the variable before a value is set
 
+                if let Some(kind) = kind {
+                    instructions.push(Instruction::AssertMainTopTypeCompatible(kind));
+                }
+
                 let id = func.next_var_id;
 
-                let result = CompilerHint::DeclareVar(Variable { name, id, writeable : true });
+                let result = CompilerHint::DeclareVar(Variable { name, id, writeable : true, kind });
 
                 instructions.push(Instruction::CreateVarWithId(id));
 
-                if is_global {
-                    instructions.push(Instruction::WriteToGlobalVarWithId(id));
-                } else {
-                    instructions.push(Instruction::WriteToVarWithId(id));
-                }
+                Compiler::write_var(id, is_global, func, global, instructions);
 
                 return Ok(Some(result));
             }
             CommandKind::Return => {
                 if cmd.arguments.is_empty() {
+                    if let Some(kind) = func.return_kind {
+                        return Err(CompileError::new(format!("A função {} retorna {}, mas nenhum valor foi retornado", func.name, kind)));
+                    }
+
                     instructions.push(Instruction::PushNull);
                 } else {
                     let expr_arg = cmd.arguments.remove(0);
 
                     match expr_arg {
                         CommandArgument::Expression(expr) => {
-                            match Compiler::compile_expression(&expr, instructions, &func, global) {
+                            if let Some(kind) = func.return_kind {
+                                match Compiler::check_known_kind(&expr, kind, func, global) {
+                                    Ok(_) => {}
+                                    Err(e) => return Err(e)
+                                }
+                            }
+
+                            match Compiler::compile_expression(&expr, instructions, func, global, funcs) {
                                 Ok(_) => {}
                                 Err(e) => return Err(e)
                             }
                         }
-                        _ => return Err(format!("Esperado uma expressão como argumento pro comando Return, encontrado {:?}", expr_arg)),
+                        _ => return Err(CompileError::new(format!("Esperado uma expressão como argumento pro comando Return, encontrado {:?}", expr_arg))),
                     }
                 }
 
+                if let Some(kind) = func.return_kind {
+                    instructions.push(Instruction::AssertMainTopTypeCompatible(kind));
+                }
+
                 instructions.push(Instruction::Return);
             }
             CommandKind::Compare => {
@@ -368,24 +617,24 @@ impl Compiler {
 
                 match left_expr_arg {
                     CommandArgument::Expression(expr) => {
-                        match Compiler::compile_expression(&expr, instructions, &func, global) {
+                        match Compiler::compile_expression(&expr, instructions, func, global, funcs) {
                             Ok(_) => {}
                             Err(e) => return Err(e)
                         }
                     }
-                    _ => return Err(format!("Esperado uma expressão como argumento pro comando Return, encontrado {:?}", left_expr_arg)),
+                    _ => return Err(CompileError::new(format!("Esperado uma expressão como argumento pro comando Return, encontrado {:?}", left_expr_arg))),
                 }
 
                 let right_expr_arg = cmd.arguments.remove(0);
 
                 match right_expr_arg {
                     CommandArgument::Expression(expr) => {
-                        match Compiler::compile_expression(&expr, instructions, &func, global) {
+                        match Compiler::compile_expression(&expr, instructions, func, global, funcs) {
                             Ok(_) => {}
                             Err(e) => return Err(e)
                         }
                     }
-                    _ => return Err(format!("Esperado uma expressão como argumento pro comando Return, encontrado {:?}", right_expr_arg)),
+                    _ => return Err(CompileError::new(format!("Esperado uma expressão como argumento pro comando Return, encontrado {:?}", right_expr_arg))),
                 }
 
                 instructions.push(Instruction::CompareMainTop);
@@ -425,160 +674,259 @@ impl Compiler {
 
                 return Ok(Some(CompilerHint::ScopeStart));
             },
-            CommandKind::Call => {
-                // First argument is the function name
+            CommandKind::ExecuteIfTrue => {
+                let expr_arg = cmd.arguments.remove(0);
 
-                let name_arg = cmd.arguments.remove(0);
+                match expr_arg {
+                    CommandArgument::Expression(expr) => {
+                        Compiler::compile_expression(&expr, instructions, func, global, funcs)?;
+                    }
+                    _ => return Err(CompileError::new(format!("Esperado uma expressão como argumento pro É VERDADE, encontrado {:?}", expr_arg))),
+                }
 
-                let name = match name_arg {
-                    CommandArgument::Name(n) => n,
-                    _ => return Err(format!("Erro interno : Esperado um nome pra função")),
+                instructions.push(Instruction::ExecuteIfTrue);
+
+                return Ok(Some(CompilerHint::ScopeStart));
+            },
+            CommandKind::Else => {
+                instructions.push(Instruction::ExecuteElse);
+
+                return Ok(Some(CompilerHint::ScopeElse));
+            },
+            CommandKind::ExecuteWhileEqual |
+            CommandKind::ExecuteWhileNotEqual |
+            CommandKind::ExecuteWhileEqualOrGreater |
+            CommandKind::ExecuteWhileGreater |
+            CommandKind::ExecuteWhileEqualOrLess |
+            CommandKind::ExecuteWhileLess => {
+                // The loop condition is evaluated at the start of every iteration, so the end of the
+                // loop jumps right back to the comparision
+
+                let start = func.body.len() + instructions.len();
+
+                for _ in 0..2 {
+                    let expr_arg = cmd.arguments.remove(0);
+
+                    match expr_arg {
+                        CommandArgument::Expression(expr) => {
+                            Compiler::compile_expression(&expr, instructions, func, global, funcs)?;
+                        }
+                        _ => return Err(CompileError::new(format!("Esperado uma expressão como argumento pro ENQUANTO, encontrado {:?}", expr_arg))),
+                    }
+                }
+
+                instructions.push(Instruction::CompareMainTop);
+
+                let inst = match cmd.kind {
+                    CommandKind::ExecuteWhileEqual => Instruction::ExecuteIfEqual,
+                    CommandKind::ExecuteWhileNotEqual => Instruction::ExecuteIfNotEqual,
+                    CommandKind::ExecuteWhileEqualOrGreater => Instruction::ExecuteIfGreaterOrEqual,
+                    CommandKind::ExecuteWhileGreater => Instruction::ExecuteIfGreater,
+                    CommandKind::ExecuteWhileEqualOrLess => Instruction::ExecuteIfLessOrEqual,
+                    _ => Instruction::ExecuteIfLess,
                 };
 
-                for cf in funcs {
-                    if cf.name == name {
+                instructions.push(inst);
 
-                        instructions.push(Instruction::MakeNewFrame(cf.id));
+                return Ok(Some(CompilerHint::LoopStart(start, None)));
+            }
+            CommandKind::ExecuteWhileTrue => {
+                let start = func.body.len() + instructions.len();
 
-                        // Check number of arguments
+                let expr_arg = cmd.arguments.remove(0);
 
-                        if cf.params.len() != cmd.arguments.len() {
-                            return Err(format!("A função {} espera {} argumentos, mas {} foram passados",
-                                name, cf.params.len(), cmd.arguments.len()));
-                        }
+                match expr_arg {
+                    CommandArgument::Expression(expr) => {
+                        Compiler::compile_expression(&expr, instructions, func, global, funcs)?;
+                    }
+                    _ => return Err(CompileError::new(format!("Esperado uma expressão como argumento pro ENQUANTO FOR VERDADE, encontrado {:?}", expr_arg))),
+                }
 
-                        // Push arguments and check their type
+                instructions.push(Instruction::ExecuteIfTrue);
 
-                        for index in 0..cf.params.len() {
-                            let arg_arg = cmd.arguments.remove(0);
+                return Ok(Some(CompilerHint::LoopStart(start, None)));
+            }
+            CommandKind::Repeat => {
+                // REPETE keeps a hidden counter with the number of iterations left. Its name can't be
+                // typed by the user, since it contains the comment character
 
-                            let expr = match arg_arg {
-                                CommandArgument::Expression(e) => e,
-                                _ => return Err("Erro interno : Era esperado um valor como argumento \
-                                                    pro comando.".to_owned()),
-                            };
+                let expr_arg = cmd.arguments.remove(0);
 
-                            let expected_type = cf.params[index].kind;
-                            let arg_name = cf.params[index].name.as_str();
+                match expr_arg {
+                    CommandArgument::Expression(expr) => {
+                        Compiler::compile_expression(&expr, instructions, func, global, funcs)?;
+                    }
+                    _ => return Err(CompileError::new(format!("Esperado uma expressão como argumento pro REPETE, encontrado {:?}", expr_arg))),
+                }
 
-                            let mut arg_id = None;
+                instructions.push(Instruction::AssertMainTopTypeCompatible(TypeKind::Integer));
 
-                            for v in &cf.vars {
-                                if v.name == arg_name {
-                                    arg_id = Some(v.id);
-                                }
-                            }
+                let id = func.next_var_id;
+                let counter = Variable { name : format!("#REPETE{}", id), id, writeable : false, kind : None };
 
-                            if let None = arg_id {
-                                return Err(format!("Erro interno : O parâmetro {} não está registrado como variável",
-                                                   arg_name));
-                            }
+                let (read, write) = if func.id == BIRL_GLOBAL_FUNCTION_ID {
+                    (Instruction::ReadGlobalVarWithId(id), Instruction::WriteToGlobalVarWithId(id))
+                } else {
+                    (Instruction::ReadVarWithId(id), Instruction::WriteToVarWithId(id))
+                };
+
+                instructions.push(Instruction::CreateVarWithId(id));
+                instructions.push(write.clone());
+
+                let start = func.body.len() + instructions.len();
+
+                instructions.push(read.clone());
+                instructions.push(Instruction::PushMainInt(0));
+                instructions.push(Instruction::CompareMainTop);
+                instructions.push(Instruction::ExecuteIfGreater);
+
+                instructions.push(read);
+                instructions.push(Instruction::PushMainInt(1));
+                instructions.push(Instruction::MainSub);
+                instructions.push(write);
+
+                return Ok(Some(CompilerHint::LoopStart(start, Some(counter))));
+            }
+            CommandKind::ListPush => {
+                for _ in 0..2 {
+                    let expr_arg = cmd.arguments.remove(0);
 
-                            match Compiler::compile_expression(&expr, instructions, &func, global) {
+                    match expr_arg {
+                        CommandArgument::Expression(expr) => {
+                            match Compiler::compile_expression(&expr, instructions, func, global, funcs) {
                                 Ok(_) => {}
                                 Err(e) => return Err(e)
-                            };
+                            }
+                        }
+                        _ => return Err(CompileError::new(format!("Erro interno : Esperado uma expressão pro BOTA NA MARMITA, encontrado {:?}", expr_arg))),
+                    }
+                }
 
-                            instructions.push(Instruction::AssertMainTopTypeCompatible(expected_type));
+                instructions.push(Instruction::ListPush);
+            }
+            CommandKind::ListPop | CommandKind::ListLength => {
+                let expr_arg = cmd.arguments.remove(0);
 
-                            instructions.push(Instruction::WriteToLastFrameVarWithId(arg_id.unwrap()));
+                match expr_arg {
+                    CommandArgument::Expression(expr) => {
+                        match Compiler::compile_expression(&expr, instructions, func, global, funcs) {
+                            Ok(_) => {}
+                            Err(e) => return Err(e)
                         }
+                    }
+                    _ => return Err(CompileError::new(format!("Erro interno : Esperado uma expressão com a MARMITA, encontrado {:?}", expr_arg))),
+                }
+
+                let name_arg = cmd.arguments.remove(0);
+
+                let name = match name_arg {
+                    CommandArgument::Name(s) => s,
+                    _ => return Err(CompileError::new("Erro interno : Esperado um nome pra guardar o resultado")),
+                };
+
+                let mut is_global = false;
+
+                let id = Compiler::get_writeable_id_and_globalness(name.as_str(), func, global, &mut is_global)?;
+
+                if cmd.kind == CommandKind::ListPop {
+                    instructions.push(Instruction::ListPop);
+                } else {
+                    instructions.push(Instruction::ListLength);
+                }
+
+                Compiler::write_var(id, is_global, func, global, instructions);
+            }
+            CommandKind::Call => {
+                // First argument is the function name
 
-                        instructions.push(Instruction::SetLastFrameReady);
+                let name_arg = cmd.arguments.remove(0);
 
-                        return Ok(None);
+                let name = match name_arg {
+                    CommandArgument::Name(n) => n,
+                    _ => return Err(CompileError::new("Erro interno : Esperado um nome pra função")),
+                };
+
+                let mut args = vec![];
+
+                for arg in cmd.arguments {
+                    match arg {
+                        CommandArgument::Expression(e) => args.push(e),
+                        _ => return Err(CompileError::new("Erro interno : Era esperado um valor como argumento \
+                                                    pro comando.".to_owned())),
                     }
                 }
 
-                return Err(format!("A função {} não foi encontrada", name));
+                match Compiler::compile_call(name.as_str(), &args, instructions, func, global, funcs) {
+                    Ok(_) => {}
+                    Err(e) => return Err(e)
+                }
+
+                // Only calls made with E HORA DO change TREZE
+                instructions.push(Instruction::WriteToVarWithId(BIRL_RET_VAL_VAR_ID));
             }
             CommandKind::GetStringInput => {
                 let name_arg = cmd.arguments.remove(0);
 
                 let name = match name_arg {
                     CommandArgument::Name(s) => s,
-                    _ => return Err("Erro interno : Esperado um nome pra GetInput*".to_owned()),
+                    _ => return Err(CompileError::new("Erro interno : Esperado um nome pra GetInput*")),
                 };
 
                 let mut is_global = false;
 
-                let id = match Compiler::get_id_and_globalness(name.as_str(), func, global, &mut is_global) {
-                    Some(id) => id,
-                    None => return Err(format!("Variável {} não encontrada", name))
-                };
+                let id = Compiler::get_writeable_id_and_globalness(name.as_str(), func, global, &mut is_global)?;
 
                 instructions.push(Instruction::ReadInput);
 
-                if is_global {
-                    instructions.push(Instruction::WriteToGlobalVarWithId(id));
-                } else {
-                    instructions.push(Instruction::WriteToVarWithId(id));
-                }
+                Compiler::write_var(id, is_global, func, global, instructions);
             }
             CommandKind::GetIntegerInput => {
                 let name_arg = cmd.arguments.remove(0);
 
                 let name = match name_arg {
                     CommandArgument::Name(s) => s,
-                    _ => return Err("Erro interno : Esperado um nome pra GetInput*".to_owned()),
+                    _ => return Err(CompileError::new("Erro interno : Esperado um nome pra GetInput*")),
                 };
 
                 let mut is_global = false;
 
-                let id = match Compiler::get_id_and_globalness(name.as_str(), func, global, &mut is_global) {
-                    Some(id) => id,
-                    None => return Err(format!("Variável {} não encontrada", name))
-                };
+                let id = Compiler::get_writeable_id_and_globalness(name.as_str(), func, global, &mut is_global)?;
 
                 instructions.push(Instruction::ReadInput);
 
                 instructions.push(Instruction::ConvertToInt);
 
-                if is_global {
-                    instructions.push(Instruction::WriteToGlobalVarWithId(id));
-                } else {
-                    instructions.push(Instruction::WriteToVarWithId(id));
-                }
+                Compiler::write_var(id, is_global, func, global, instructions);
             }
             CommandKind::GetNumberInput => {
                 let name_arg = cmd.arguments.remove(0);
 
                 let name = match name_arg {
                     CommandArgument::Name(s) => s,
-                    _ => return Err("Erro interno : Esperado um nome pra GetInput*".to_owned()),
+                    _ => return Err(CompileError::new("Erro interno : Esperado um nome pra GetInput*")),
                 };
 
                 let mut is_global = false;
 
-                let id = match Compiler::get_id_and_globalness(name.as_str(), func, global, &mut is_global) {
-                    Some(id) => id,
-                    None => return Err(format!("Variável {} não encontrada", name))
-                };
+                let id = Compiler::get_writeable_id_and_globalness(name.as_str(), func, global, &mut is_global)?;
 
                 instructions.push(Instruction::ReadInput);
 
                 instructions.push(Instruction::ConvertToNum);
 
-                if is_global {
-                    instructions.push(Instruction::WriteToGlobalVarWithId(id));
-                } else {
-                    instructions.push(Instruction::WriteToVarWithId(id));
-                }
+                Compiler::write_var(id, is_global, func, global, instructions);
             }
             CommandKind::ConvertToInt => {
                 let name_arg = cmd.arguments.remove(0);
 
                 let name = match name_arg {
                     CommandArgument::Name(s) => s,
-                    _ => return Err("Erro interno : Esperado um nome pra GetInput*".to_owned()),
+                    _ => return Err(CompileError::new("Erro interno : Esperado um nome pra GetInput*")),
                 };
 
                 let mut is_global = false;
 
-                let id = match Compiler::get_id_and_globalness(name.as_str(), func, global, &mut is_global) {
-                    Some(id) => id,
-                    None => return Err(format!("Variável {} não encontrada", name))
-                };
+                let id = Compiler::get_writeable_id_and_globalness(name.as_str(), func, global, &mut is_global)?;
 
                 if is_global {
                     instructions.push(Instruction::ReadGlobalVarWithId(id));
@@ -588,26 +936,19 @@ impl Compiler {
 
                 instructions.push(Instruction::ConvertToInt);
 
-                if is_global {
-                    instructions.push(Instruction::WriteToGlobalVarWithId(id));
-                } else {
-                    instructions.push(Instruction::WriteToVarWithId(id));
-                }
+                Compiler::write_var(id, is_global, func, global, instructions);
             }
             CommandKind::ConvertToNum => {
                 let name_arg = cmd.arguments.remove(0);
 
                 let name = match name_arg {
                     CommandArgument::Name(s) => s,
-                    _ => return Err("Erro interno : Esperado um nome pra GetInput*".to_owned()),
+                    _ => return Err(CompileError::new("Erro interno : Esperado um nome pra GetInput*")),
                 };
 
                 let mut is_global = false;
 
-                let id = match Compiler::get_id_and_globalness(name.as_str(), func, global, &mut is_global) {
-                    Some(id) => id,
-                    None => return Err(format!("Variável {} não encontrada", name))
-                };
+                let id = Compiler::get_writeable_id_and_globalness(name.as_str(), func, global, &mut is_global)?;
 
                 if is_global {
                     instructions.push(Instruction::ReadGlobalVarWithId(id));
@@ -617,26 +958,19 @@ impl Compiler {
 
                 instructions.push(Instruction::ConvertToNum);
 
-                if is_global {
-                    instructions.push(Instruction::WriteToGlobalVarWithId(id));
-                } else {
-                    instructions.push(Instruction::WriteToVarWithId(id));
-                }
+                Compiler::write_var(id, is_global, func, global, instructions);
             }
             CommandKind::IntoString => {
                 let name_arg = cmd.arguments.remove(0);
 
                 let name = match name_arg {
                     CommandArgument::Name(s) => s,
-                    _ => return Err("Erro interno : Esperado um nome pra GetInput*".to_owned()),
+                    _ => return Err(CompileError::new("Erro interno : Esperado um nome pra GetInput*")),
                 };
 
                 let mut is_global = false;
 
-                let id = match Compiler::get_id_and_globalness(name.as_str(), func, global, &mut is_global) {
-                    Some(id) => id,
-                    None => return Err(format!("Variável {} não encontrada", name))
-                };
+                let id = Compiler::get_writeable_id_and_globalness(name.as_str(), func, global, &mut is_global)?;
 
                 if is_global {
                     instructions.push(Instruction::ReadGlobalVarWithId(id));
@@ -646,14 +980,394 @@ impl Compiler {
 
                 instructions.push(Instruction::ConvertToString);
 
-                if is_global {
-                    instructions.push(Instruction::WriteToGlobalVarWithId(id));
-                } else {
-                    instructions.push(Instruction::WriteToVarWithId(id));
-                }
+                Compiler::write_var(id, is_global, func, global, instructions);
             }
         }
 
         Ok(None)
     }
-}
\ No newline at end of file
+}
+mod tests {
+    #[test]
+    fn operator_precedence() {
+        use context::Context;
+
+        use std::cell::RefCell;
+        use std::io::{ self, Write };
+        use std::rc::Rc;
+
+        struct Output(Rc<RefCell<Vec<u8>>>);
+
+        impl Write for Output {
+            fn write(&mut self, buf : &[u8]) -> io::Result<usize> {
+                self.0.borrow_mut().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        // Runs a program that prints the expression and returns what got printed
+        fn eval(expr : &str) -> String {
+            let output = Rc::new(RefCell::new(vec![]));
+
+            let mut ctx = Context::new();
+            ctx.set_stdout(Some(Box::new(Output(output.clone()))));
+
+            let src = format!("JAULA SHOW\nVEM: X, 2\nCE QUER VER: {}\nSAINDO DA JAULA", expr);
+
+            match ctx.add_source_string(src) {
+                Ok(_) => {}
+                Err(e) => panic!("Erro compilando {} : {}", expr, e),
+            }
+
+            match ctx.start_program() {
+                Ok(_) => {}
+                Err(e) => panic!("Erro executando {} : {}", expr, e),
+            }
+
+            let printed = output.borrow().clone();
+
+            String::from_utf8(printed).unwrap()
+        }
+
+        let cases = [
+            // Left associativity
+            ("10 - 4 + 3", "9"),
+            ("10 - 4 - 3", "3"),
+            ("100 / 10 / 5", "2"),
+            ("2 * 3 * 4", "24"),
+            ("20 % 7 % 4", "2"),
+            ("12 / 3 * 2", "8"),
+            // Right associativity
+            ("2 ^ 3 ^ 2", "512"),
+            // Precedence between levels
+            ("1 + 2 * 3", "7"),
+            ("2 * 3 + 1", "7"),
+            ("1 - 2 * 3 + 4", "-1"),
+            ("2 * 3 ^ 2", "18"),
+            ("10 - 7 % 4", "7"),
+            ("1 + 2 == 3", "VERDADE"),
+            ("1 < 2 == VERDADE", "VERDADE"),
+            ("(1 < 2) == (3 < 2)", "MENTIRA"),
+            ("VERDADE OU MENTIRA E MENTIRA", "VERDADE"),
+            ("X > 1 E X < 3 OU X == 10", "VERDADE"),
+            // Parenthesis
+            ("(1 + 2) * 3", "9"),
+            ("1 - (2 * 3)", "-5"),
+            ("2 * (3 + 4) - 1", "13"),
+            ("((X))", "2"),
+            ("(2 ^ 3) ^ 2", "64"),
+            // Unary operators
+            ("-X ^ 2", "-4"),
+            ("-X * 3", "-6"),
+            ("2 ^ -1", "0.5"),
+            ("3 - -X", "5"),
+            ("NAO X == 2", "MENTIRA"),
+            ("NAO MENTIRA E MENTIRA", "MENTIRA"),
+            ("NAO (MENTIRA E MENTIRA)", "VERDADE"),
+            // Indexes apply before any operator
+            ("-[1, 2][1] * 3", "-6"),
+        ];
+
+        for &(expr, expected) in cases.iter() {
+            assert_eq!(eval(expr), expected, "Resultado errado pra {}", expr);
+        }
+
+        // Exponents that don't fit an i32 aren't cut
+        assert_eq!(eval("2 ^ (0 - 4294967297)"), "0");
+
+        let errors = [
+            "7 % 0",
+            "7.0 % 0",
+            "7 % 0.0",
+            "7.5 % 0.0",
+            "(-9223372036854775807 - 1) % -1",
+            "1 / 0",
+            "1.5 / 0",
+            "(-9223372036854775807 - 1) / -1",
+            "9223372036854775807 + 1",
+            "-9223372036854775807 - 2",
+            "9223372036854775807 * 2",
+        ];
+
+        // Errors, not panics, and they point to the line that failed
+        for expr in errors.iter() {
+            let mut ctx = Context::new();
+            ctx.set_stdout(Some(Box::new(io::sink())));
+
+            let src = format!("JAULA SHOW\nCE QUER VER: {}\nSAINDO DA JAULA", expr);
+
+            if let Err(e) = ctx.add_source_string(src) {
+                panic!("Erro compilando {} : {}", expr, e);
+            }
+
+            match ctx.start_program() {
+                Ok(_) => panic!("{} deveria falhar", expr),
+                Err(e) => assert_eq!(e.span.line, Some(2), "{}", expr),
+            }
+        }
+    }
+
+    #[test]
+    fn boolean_operators() {
+        use context::Context;
+
+        use std::cell::RefCell;
+        use std::io::{ self, Write };
+        use std::rc::Rc;
+
+        struct Output(Rc<RefCell<Vec<u8>>>);
+
+        impl Write for Output {
+            fn write(&mut self, buf : &[u8]) -> io::Result<usize> {
+                self.0.borrow_mut().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        // F prints its name when it's called, so the output tells which sides were evaluated
+        fn run(body : &str) -> Result<String, String> {
+            let output = Rc::new(RefCell::new(vec![]));
+
+            let mut ctx = Context::new();
+            ctx.set_stdout(Some(Box::new(Output(output.clone()))));
+
+            let src = format!("JAULA F(V : BIRLEANO)\nCE QUER VER: \"F\"\nBIRL: V\nSAINDO DA JAULA\n\
+                               JAULA SHOW\nVEM: X, VERDADE\nVEM: Y, MENTIRA\n{}\nSAINDO DA JAULA", body);
+
+            if let Err(e) = ctx.add_source_string(src) {
+                panic!("Erro compilando {} : {}", body, e);
+            }
+
+            let result = ctx.start_program();
+            let printed = String::from_utf8(output.borrow().clone()).unwrap();
+
+            match result {
+                Ok(_) => Ok(printed),
+                Err(e) => Err(format!("{}", e)),
+            }
+        }
+
+        let cases = [
+            ("X E X", "VERDADE"),
+            ("X E Y", "MENTIRA"),
+            ("Y E X", "MENTIRA"),
+            ("Y E Y", "MENTIRA"),
+            ("X OU X", "VERDADE"),
+            ("X OU Y", "VERDADE"),
+            ("Y OU X", "VERDADE"),
+            ("Y OU Y", "MENTIRA"),
+            ("NAO X", "MENTIRA"),
+            ("NÃO Y", "VERDADE"),
+            ("NAO NAO X", "VERDADE"),
+            // E before the words of a key phrase is still the operator
+            ("X E VERDADE", "VERDADE"),
+            ("Y OU X E MENTIRA", "MENTIRA"),
+            ("1 < 2 E 2 < 3", "VERDADE"),
+            ("1 == 1 OU 1 / 1 == 0", "VERDADE"),
+        ];
+
+        for &(expr, expected) in cases.iter() {
+            assert_eq!(run(&format!("CE QUER VER: {}", expr)), Ok(expected.to_owned()), "Resultado errado pra {}", expr);
+        }
+
+        // Both sides are always evaluated. There's no short-circuit, so calls on the right run and
+        // errors on the right are reported even when the left side already decides the result
+        assert_eq!(run("CE QUER VER: Y E F(X)"), Ok("FMENTIRA".to_owned()));
+        assert_eq!(run("CE QUER VER: X OU F(Y)"), Ok("FVERDADE".to_owned()));
+        assert!(run("CE QUER VER: Y E 1 / 0 == 1").is_err());
+        assert!(run("CE QUER VER: X OU 1 / 0 == 1").is_err());
+
+        // The operands must be booleans. Ints and text aren't true or false
+        let errors = [
+            "1 E X",
+            "X OU 0",
+            "NAO 1",
+            "X E \"VERDADE\"",
+            "[X, 1][1] OU X",
+        ];
+
+        for expr in errors.iter() {
+            match run(&format!("CE QUER VER: {}", expr)) {
+                Ok(printed) => panic!("{} deveria falhar, mas mostrou {}", expr, printed),
+                Err(e) => assert!(e.contains("BIRLEANO"), "Erro errado pra {} : {}", expr, e),
+            }
+        }
+
+        // And conditions can be made of them
+        let condition = "É VERDADE: X E NAO Y\n\
+                         \x20   CE QUER VER: \"SIM\"\n\
+                         FIM\n\
+                         É VERDADE: X E Y OU NAO X\n\
+                         \x20   CE QUER VER: \"NAO\"\n\
+                         FIM";
+
+        assert_eq!(run(condition), Ok("SIM".to_owned()));
+    }
+
+    #[test]
+    fn loops() {
+        use context::Context;
+        use vm::ExecutionLimits;
+
+        use std::cell::RefCell;
+        use std::io::{ self, Write };
+        use std::rc::Rc;
+
+        struct Output(Rc<RefCell<Vec<u8>>>);
+
+        impl Write for Output {
+            fn write(&mut self, buf : &[u8]) -> io::Result<usize> {
+                self.0.borrow_mut().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        // More iterations than the main stack has room for, so anything left on it by the body of
+        // the loop makes the program fail. The second loop never runs, and the blocks inside it
+        // must not end the skipping, or the program never ends
+        let source = "VEM: N, 0\n\
+                      REPETE: 300\n\
+                      \x20   VEM: Y, N + 1\n\
+                      \x20   CE QUER VER: Y % 2\n\
+                      \x20   BORA: N, Y\n\
+                      FIM\n\
+                      ENQUANTO FOR VERDADE: N < 0\n\
+                      \x20   E VERDADE: VERDADE\n\
+                      \x20       CE QUER VER: \"X\"\n\
+                      \x20   FIM\n\
+                      \x20   CE QUER VER: \"X\"\n\
+                      FIM\n\
+                      CE QUER VER: \"|\", N";
+
+        let output = Rc::new(RefCell::new(vec![]));
+
+        let mut ctx = Context::new();
+        ctx.set_stdout(Some(Box::new(Output(output.clone()))));
+        ctx.set_limits(ExecutionLimits { max_instructions : Some(100_000), .. ExecutionLimits::default() });
+
+        if let Err(e) = ctx.add_source_string(source.to_owned()) {
+            panic!("Erro compilando o código : {}", e);
+        }
+
+        if let Err(e) = ctx.start_program() {
+            panic!("Erro executando o código : {}", e);
+        }
+
+        let printed = String::from_utf8(output.borrow().clone()).unwrap();
+
+        assert_eq!(printed, format!("{}|300", "10".repeat(150)));
+    }
+
+    #[test]
+    fn else_blocks() {
+        use context::Context;
+        use vm::ExecutionLimits;
+
+        use std::cell::RefCell;
+        use std::io::{ self, Write };
+        use std::rc::Rc;
+
+        struct Output(Rc<RefCell<Vec<u8>>>);
+
+        impl Write for Output {
+            fn write(&mut self, buf : &[u8]) -> io::Result<usize> {
+                self.0.borrow_mut().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        fn run(source : &str) -> String {
+            let output = Rc::new(RefCell::new(vec![]));
+
+            let mut ctx = Context::new();
+            ctx.set_stdout(Some(Box::new(Output(output.clone()))));
+            ctx.set_limits(ExecutionLimits { max_instructions : Some(100_000), .. ExecutionLimits::default() });
+
+            if let Err(e) = ctx.add_source_string(source.to_owned()) {
+                panic!("Erro compilando o código : {}", e);
+            }
+
+            if let Err(e) = ctx.start_program() {
+                panic!("Erro executando o código : {}", e);
+            }
+
+            let printed = String::from_utf8(output.borrow().clone()).unwrap();
+
+            printed
+        }
+
+        // Only one of the branches runs, and the code after the block always does
+        let branches = "E VERDADE: X\n\
+                        \x20   CE QUER VER: \"A\"\n\
+                        SENÃO\n\
+                        \x20   CE QUER VER: \"B\"\n\
+                        FIM\n\
+                        CE QUER VER: \"|\"";
+
+        assert_eq!(run(&format!("VEM: X, VERDADE\n{}", branches)), "A|");
+        assert_eq!(run(&format!("VEM: X, MENTIRA\n{}", branches)), "B|");
+
+        // Blocks inside a branch that's skipped, with or without their own else, must not end the
+        // skipping early, and the else of an inner block only flips the inner one
+        let nested = "E VERDADE: X\n\
+                      \x20   E VERDADE: Y\n\
+                      \x20       CE QUER VER: \"A\"\n\
+                      \x20   SENAO\n\
+                      \x20       CE QUER VER: \"B\"\n\
+                      \x20   FIM\n\
+                      \x20   E VERDADE: VERDADE\n\
+                      \x20       CE QUER VER: \"C\"\n\
+                      \x20   FIM\n\
+                      SENAO\n\
+                      \x20   E VERDADE: Y\n\
+                      \x20       CE QUER VER: \"D\"\n\
+                      \x20   SENAO\n\
+                      \x20       CE QUER VER: \"E\"\n\
+                      \x20   FIM\n\
+                      FIM\n\
+                      CE QUER VER: \"|\"";
+
+        assert_eq!(run(&format!("VEM: X, VERDADE\nVEM: Y, VERDADE\n{}", nested)), "AC|");
+        assert_eq!(run(&format!("VEM: X, VERDADE\nVEM: Y, MENTIRA\n{}", nested)), "BC|");
+        assert_eq!(run(&format!("VEM: X, MENTIRA\nVEM: Y, VERDADE\n{}", nested)), "D|");
+        assert_eq!(run(&format!("VEM: X, MENTIRA\nVEM: Y, MENTIRA\n{}", nested)), "E|");
+
+        // Inside loops the choice is made again on every iteration, including after a previous
+        // iteration skipped the other branch
+        let looped = "VEM: N, 0\n\
+                      REPETE: 6\n\
+                      \x20   E VERDADE: N % 3 == 0\n\
+                      \x20       CE QUER VER: \"T\"\n\
+                      \x20   SENÃO\n\
+                      \x20       CE QUER VER: \"F\"\n\
+                      \x20   FIM\n\
+                      \x20   BORA: N, N + 1\n\
+                      FIM\n\
+                      ENQUANTO FOR VERDADE: N > 0\n\
+                      \x20   E VERDADE: N > 3\n\
+                      \x20       BORA: N, N - 2\n\
+                      \x20   SENÃO\n\
+                      \x20       BORA: N, N - 1\n\
+                      \x20   FIM\n\
+                      \x20   CE QUER VER: N\n\
+                      FIM";
+
+        assert_eq!(run(looped), "TFFTFF4210");
+    }
+}
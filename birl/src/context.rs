@@ -1,13 +1,17 @@
 //! Hosts the runtime for the birlscript language
 
-// BIG TODO : Add default variables
-
-use vm::{ Instruction, VirtualMachine, ExecutionStatus };
-use parser::{ parse_line, FunctionParameter, ParserResult, IntegerType, FunctionDeclaration };
+use vm::{ Instruction, VirtualMachine, ExecutionStatus, ExecutionLimits };
+use parser::{ parse_line, FunctionParameter, ParserResult, IntegerType, FunctionDeclaration, Command, CommandKind, TypeKind };
 use compiler::{ Compiler, Variable, CompilerHint };
+use error::{ Error, CompileError, RuntimeError, IoError, BytecodeError, BacktraceFrame, Span };
+use ast::{ self, Program, Item, Statement, Block };
+use checker;
+use bytecode::{ self, CompiledProgram };
 
-use std::io::{ BufRead, BufReader, Write };
-use std::fs::File;
+use std::io::{ BufRead, Write };
+use std::fs;
+use std::env;
+use std::fmt;
 
 pub const BIRL_COPYRIGHT : &'static str 
     = "© 2016 - 2018 Rafael Rodrigues Nakano <lazpeng@gmail.com>";
@@ -20,6 +24,19 @@ pub const BIRL_MAIN_FUNCTION_ID     : u64 = 1;
 pub const BIRL_GLOBAL_FUNCTION_ID   : u64 = 0;
 pub const BIRL_RET_VAL_VAR_ID       : u64 = 0;
 
+/// Name of the constant holding the name of the current function
+pub const BIRL_FUNCTION_NAME_CONSTANT : &str = "JAULA";
+
+/// Name of the constant holding the name of the user running the program
+pub const BIRL_USER_NAME_CONSTANT : &str = "CUMPADE";
+
+/// The instructions from `start` until the start of the next entry were compiled from `line`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineEntry {
+    pub start : usize,
+    pub line : usize,
+}
+
 #[derive(Debug, Clone)]
 pub struct FunctionEntry {
     pub name : String,
@@ -28,6 +45,13 @@ pub struct FunctionEntry {
     pub params : Vec<FunctionParameter>,
     pub vars : Vec<Variable>,
     pub next_var_id : u64,
+    // Every variable ever declared, since the ones in `vars` go away when their scope ends
+    pub declared_vars : Vec<Variable>,
+    pub lines : Vec<LineEntry>,
+    // File the function was compiled from, if it came from one
+    pub file : Option<String>,
+    // Type of what the function returns, if it was declared
+    pub return_kind : Option<TypeKind>,
 }
 impl FunctionEntry {
 	pub fn get_id_for(&self, var : &str) -> Option<u64> {
@@ -48,21 +72,105 @@ impl FunctionEntry {
             vars : vec![Variable {
                 name : "TREZE".to_owned(),
                 id : BIRL_RET_VAL_VAR_ID,
-                writeable : true
+                writeable : true,
+                kind : None,
             }],
             next_var_id : 1,
+            declared_vars : vec![Variable {
+                name : "TREZE".to_owned(),
+                id : BIRL_RET_VAL_VAR_ID,
+                writeable : true,
+                kind : None,
+            }],
+            lines : vec![],
+            file : None,
+            return_kind : None,
+        }
+    }
+
+    /// Source line the instruction at `pc` was compiled from
+    pub fn line_at(&self, pc : usize) -> Option<usize> {
+        self.lines.iter().rposition(|l| l.start <= pc).map(|i| self.lines[i].line)
+    }
+
+    /// Whether the function is implemented in Rust, registered with `Context::register_native`
+    pub fn is_native(&self) -> bool {
+        matches!(self.body.first(), Some(Instruction::CallNative(_)))
+    }
+
+    /// Whether the instruction at `pc` is the first one of a line
+    pub fn starts_line(&self, pc : usize) -> bool {
+        pc < self.body.len() && self.lines.iter().any(|l| l.start == pc)
+    }
+
+    /// Marks the instructions added to the body from now on as coming from `line`
+    fn add_line(&mut self, line : usize) {
+        let start = self.body.len();
+
+        self.lines.push(LineEntry { start, line });
+    }
+
+    /// Registers a non-writeable variable and adds the instructions that give it its value to the
+    /// body, so it's set as soon as the function starts.
+    pub fn add_constant(&mut self, name : &str, value : Instruction) -> Result<u64, CompileError> {
+        // Knowing the type lets the compiler reject constants given where another type is expected
+        let kind = match value {
+            Instruction::PushMainInt(_) => Some(TypeKind::Integer),
+            Instruction::PushMainNum(_) => Some(TypeKind::Number),
+            Instruction::PushMainStr(_) => Some(TypeKind::Text),
+            Instruction::PushMainBool(_) => Some(TypeKind::Boolean),
+            _ => None,
+        };
+
+        let id = self.add_typed_var(name.to_owned(), false, kind)?;
+
+        self.body.push(value);
+        self.body.push(Instruction::CreateVarWithId(id));
+
+        if self.id == BIRL_GLOBAL_FUNCTION_ID {
+            self.body.push(Instruction::WriteToGlobalVarWithId(id));
+        } else {
+            self.body.push(Instruction::WriteToVarWithId(id));
+        }
+
+        Ok(id)
+    }
+
+    /// Changes the value a constant added by `add_constant` is set to. Returns false if the
+    /// function has no such constant
+    fn set_constant(&mut self, name : &str, value : Instruction) -> bool {
+        let id = match self.vars.iter().find(|v| v.name == name && !v.writeable) {
+            Some(v) => v.id,
+            None => return false,
+        };
+
+        // The value is pushed right before the variable is created
+        let create = self.body.iter().position(|i| matches!(i, Instruction::CreateVarWithId(v) if *v == id));
+
+        match create {
+            Some(pos) if pos > 0 => {
+                self.body[pos - 1] = value;
+                true
+            }
+            _ => false,
         }
     }
 
-    pub fn add_var(&mut self, name : String, writeable : bool) -> Result<u64, String> {
+    pub fn add_var(&mut self, name : String, writeable : bool) -> Result<u64, CompileError> {
+        self.add_typed_var(name, writeable, None)
+    }
+
+    /// Registers a variable that only takes values of `kind`, if there's one
+    pub fn add_typed_var(&mut self, name : String, writeable : bool, kind : Option<TypeKind>) -> Result<u64, CompileError> {
         for v in &self.vars {
             if name == v.name.as_str() {
-                return Err(format!("A variável {} já está declarada.", name.as_str()));
+                return Err(CompileError::new(format!("A variável {} já está declarada.", name.as_str())));
             }
         }
 
         let id = self.next_var_id;
-        self.vars.push(Variable { name, id, writeable });
+        self.declared_vars.push(Variable { name : name.clone(), id, writeable, kind });
+        self.vars.push(Variable { name, id, writeable, kind });
         self.next_var_id += 1;
 
         Ok(id)
@@ -75,10 +183,54 @@ enum Scope {
     Function,
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum RawValue {
     Text(String),
     Integer(IntegerType),
-    Number(f64)
+    Number(f64),
+    Boolean(bool),
+    List(Vec<RawValue>),
+    Null,
+}
+
+impl RawValue {
+    /// Whether the value can go where `kind` is expected. Same as the VM, an Int is also a Num
+    pub fn fits(&self, kind : TypeKind) -> bool {
+        matches!((self, kind),
+            (RawValue::Text(_), TypeKind::Text) |
+            (RawValue::Integer(_), TypeKind::Integer) |
+            (RawValue::Integer(_), TypeKind::Number) |
+            (RawValue::Number(_), TypeKind::Number) |
+            (RawValue::Boolean(_), TypeKind::Boolean) |
+            (RawValue::List(_), TypeKind::List))
+    }
+}
+
+/// Shows the value the way the debugger does, with text in quotes
+impl fmt::Display for RawValue {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RawValue::Text(t) => write!(f, "\"{}\"", t),
+            RawValue::Integer(i) => write!(f, "{}", i),
+            RawValue::Number(n) => write!(f, "{}", n),
+            RawValue::Boolean(true) => write!(f, "VERDADE"),
+            RawValue::Boolean(false) => write!(f, "MENTIRA"),
+            RawValue::Null => write!(f, "<Null>"),
+            RawValue::List(items) => {
+                write!(f, "[")?;
+
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    write!(f, "{}", item)?;
+                }
+
+                write!(f, "]")
+            }
+        }
+    }
 }
 
 pub struct Context {
@@ -90,15 +242,55 @@ pub struct Context {
     global_scope : Vec<ScopeManager>,
     function_scope : Vec<ScopeManager>,
     last_function_id : u64,
+    // Line being compiled, counted by process_line
+    line : usize,
+    // File being compiled, if the code comes from one
+    file : Option<String>,
+    // Functions declared ahead by declare_functions whose declaration wasn't compiled yet
+    pending_functions : Vec<u64>,
+}
+
+/// A function in the callstack, as seen from outside the VM
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameInfo {
+    pub function_id : u64,
+    pub function : String,
+    pub program_counter : usize,
+    pub file : Option<String>,
+    pub line : Option<usize>,
+    // Calls still getting their arguments aren't ready and haven't run yet
+    pub ready : bool,
 }
 
 struct ScopeManager {
-    ids : Vec<u64>
+    ids : Vec<u64>,
+    // Address the end of the scope jumps back to, if the scope is a loop
+    loop_start : Option<usize>,
+    // Whether the scope is the alternative block of a conditional
+    is_else : bool,
 }
 impl ScopeManager {
     fn empty() -> ScopeManager {
         ScopeManager {
-            ids : vec![]
+            ids : vec![],
+            loop_start : None,
+            is_else : false,
+        }
+    }
+
+    fn for_loop(start : usize) -> ScopeManager {
+        ScopeManager {
+            ids : vec![],
+            loop_start : Some(start),
+            is_else : false,
+        }
+    }
+
+    fn for_else() -> ScopeManager {
+        ScopeManager {
+            ids : vec![],
+            loop_start : None,
+            is_else : true,
         }
     }
 
@@ -124,8 +316,34 @@ impl Context {
     pub fn set_stdin(&mut self, read: Option<Box<BufRead>>) -> Option<Box<BufRead>>{
         self.vm.set_stdin(read)
     }
+
+    /// Alias for vm.set_limits().
+    pub fn set_limits(&mut self, limits : ExecutionLimits) {
+        self.vm.set_limits(limits)
+    }
+    fn user_name() -> String {
+        match env::var("USER").or_else(|_| env::var("USERNAME")) {
+            Ok(name) => name,
+            Err(_) => "CUMPADE".to_owned(),
+        }
+    }
+
     fn new_global() -> FunctionEntry {
-        FunctionEntry::from("__global__".to_owned(), BIRL_GLOBAL_FUNCTION_ID, vec![])
+        let mut global = FunctionEntry::from("__global__".to_owned(), BIRL_GLOBAL_FUNCTION_ID, vec![]);
+
+        // Default constants, available everywhere
+        global.add_constant(BIRL_USER_NAME_CONSTANT, Instruction::PushMainStr(Context::user_name()))
+            .expect("Fatal error: Could not declare CUMPADE");
+        global.add_constant("UM", Instruction::PushMainInt(1))
+            .expect("Fatal error: Could not declare UM");
+        global.add_constant("BODYBUILDER", Instruction::PushMainStr("BAMBAM".to_owned()))
+            .expect("Fatal error: Could not declare BODYBUILDER");
+        global.add_constant("VERDADE", Instruction::PushMainBool(true))
+            .expect("Fatal error: Could not declare VERDADE");
+        global.add_constant("MENTIRA", Instruction::PushMainBool(false))
+            .expect("Fatal error: Could not declare MENTIRA");
+
+        global
     }
 
     pub fn new() -> Context {
@@ -138,10 +356,26 @@ impl Context {
             global_scope : vec![ScopeManager::empty()],
             function_scope : vec![],
             last_function_id : 0,
+            line : 0,
+            file : None,
+            pending_functions : vec![],
         }
     }
 
-    fn get_entry_by_id(&self, id : u64) -> Option<&FunctionEntry> {
+    /// The function being declared, while its body is compiled
+    pub fn current_function(&self) -> Option<&FunctionEntry> {
+        match self.scope {
+            Scope::Function => self.get_entry_by_id(self.last_function_id),
+            Scope::Global => None,
+        }
+    }
+
+    /// Every function known, including the global one
+    pub fn functions(&self) -> &[FunctionEntry] {
+        &self.functions
+    }
+
+    pub fn get_entry_by_id(&self, id : u64) -> Option<&FunctionEntry> {
         for e in &self.functions {
             if e.id == id {
                 return Some(e);
@@ -151,6 +385,10 @@ impl Context {
         None
     }
 
+    pub fn get_function_by_name(&self, name : &str) -> Option<&FunctionEntry> {
+        self.functions.iter().find(|e| e.name == name)
+    }
+
     fn get_entry_by_id_mut(&mut self, id : u64) -> Option<&mut FunctionEntry> {
         for e in &mut self.functions {
             if e.id == id {
@@ -161,37 +399,227 @@ impl Context {
         None
     } 
 
-    fn add_function(&mut self, f : FunctionDeclaration) -> Result<u64, String> {
+    /// Entry for a declared function, with its parameters registered as variables
+    fn new_entry(f : FunctionDeclaration, id : u64) -> Result<FunctionEntry, CompileError> {
+        let mut entry = FunctionEntry::from(f.name, id, f.arguments.clone());
+
+        entry.return_kind = f.return_kind;
+
+        // Parameters keep the type they were declared with
+        for arg in f.arguments {
+            match entry.add_typed_var(arg.name, true, Some(arg.kind)) {
+                Ok(_) => {}
+                Err(e) => return Err(e)
+            }
+        }
+
+        Ok(entry)
+    }
+
+    /// Registers every function of the program before any of it is compiled, so calls can go to
+    /// functions declared further down
+    fn declare_functions(&mut self, program : &Program) {
+        for function in program.functions() {
+            let f = function.declaration.clone();
+
+            // Same as when they're compiled, the first function with a name is the one called
+            if self.get_function_by_name(f.name.as_str()).is_some() {
+                continue;
+            }
+
+            let id = if f.name == BIRL_MAIN_FUNCTION {
+                // Invalid main functions are reported by add_function
+                if !f.arguments.is_empty() {
+                    continue;
+                }
+
+                BIRL_MAIN_FUNCTION_ID
+            } else {
+                let tmp = self.next_function_id;
+
+                self.next_function_id += 1;
+
+                tmp
+            };
+
+            let entry = match Context::new_entry(f, id) {
+                Ok(e) => e,
+                Err(_) => continue,
+            };
+
+            self.functions.push(entry);
+            self.pending_functions.push(id);
+        }
+    }
+
+    /// Compiles a whole program, declaring its functions first. Blocks typed line by line must
+    /// be closed before, since the program can't end them
+    pub fn add_program(&mut self, program : &Program) -> Result<(), Error> {
+        if self.open_blocks() > 0 {
+            return Err(CompileError::new("Erro : Termine o bloco aberto antes de adicionar um programa").into());
+        }
+
+        self.declare_functions(program);
+
+        let mut result = Ok(());
+
+        for item in &program.items {
+            result = match item {
+                Item::Function(f) => self.compile_function(f),
+                Item::Statement(s) => self.compile_statement(s),
+            };
+
+            if result.is_err() {
+                break;
+            }
+        }
+
+        // Functions whose declaration wasn't reached because of an error have no body
+        if !self.pending_functions.is_empty() {
+            let pending_functions = &self.pending_functions;
+
+            self.functions.retain(|f| !pending_functions.contains(&f.id));
+            self.pending_functions.clear();
+        }
+
+        result
+    }
+
+    /// Looks for type errors in the program without compiling it. Functions and constants already in
+    /// the context are taken into account
+    pub fn check_program(&self, program : &Program) -> Vec<Error> {
+        checker::check(program, &self.functions)
+    }
+
+    fn compile_function(&mut self, function : &ast::Function) -> Result<(), Error> {
+        self.compile_at(&function.span, ParserResult::FunctionStart(function.declaration.clone()))?;
+
+        for statement in &function.body {
+            self.compile_statement(statement)?;
+        }
+
+        self.compile_at(&function.end, ParserResult::FunctionEnd)
+    }
+
+    fn compile_statement(&mut self, statement : &Statement) -> Result<(), Error> {
+        match statement {
+            Statement::Command(cmd, span) => self.compile_at(span, ParserResult::Command(cmd.clone())),
+            Statement::Block(block) => self.compile_block(block),
+        }
+    }
+
+    fn compile_block(&mut self, block : &Block) -> Result<(), Error> {
+        self.compile_at(&block.span, ParserResult::Command(block.header.clone()))?;
+
+        for statement in &block.body {
+            self.compile_statement(statement)?;
+        }
+
+        if let Some((span, alternative)) = &block.alternative {
+            self.compile_at(span, ParserResult::Command(Command { kind : CommandKind::Else, arguments : vec![] }))?;
+
+            for statement in alternative {
+                self.compile_statement(statement)?;
+            }
+        }
+
+        self.compile_at(&block.end, ParserResult::Command(Command { kind : CommandKind::EndExecuteIf, arguments : vec![] }))
+    }
+
+    /// Compiles something that came from the place pointed by `span`, so the line table and the
+    /// errors point there too
+    fn compile_at(&mut self, span : &Span, result : ParserResult) -> Result<(), Error> {
+        self.line = match span.line {
+            Some(line) => line,
+            None => self.line + 1,
+        };
+
+        let previous_file = self.file.clone();
+
+        if span.file.is_some() {
+            self.file = span.file.clone();
+        }
+
+        let result = self.compile_result(result);
+
+        self.file = previous_file;
+
+        result.map_err(|mut e| {
+            if let Some(column) = span.column {
+                e = e.with_column(column);
+            }
+
+            if let Some(line) = span.line {
+                e = e.with_line(line);
+            }
+
+            match &span.file {
+                Some(file) => e.with_file(file),
+                None => e,
+            }
+        })
+    }
+
+    fn add_function(&mut self, f : FunctionDeclaration) -> Result<u64, CompileError> {
         let is_main = f.name == BIRL_MAIN_FUNCTION;
         if is_main {
             if self.has_main {
-                return Err("Erro: Múltipla declaração da função principal".to_owned());
+                return Err(CompileError::new("Erro: Múltipla declaração da função principal"));
             }
             if f.arguments.len() != 0 {
-                return Err("Erro : Declaração da função principal inválida : A função principal não deve pedir argumentos".to_owned());
+                return Err(CompileError::new("Erro : Declaração da função principal inválida : A função principal não deve pedir argumentos"));
             }
             self.has_main = true;
         }
 
-        let id = if is_main {
-            BIRL_MAIN_FUNCTION_ID
-        } else {
-            let tmp = self.next_function_id;
+        if let Some(native) = self.get_function_by_name(f.name.as_str()) {
+            if native.is_native() {
+                return Err(CompileError::new(format!("Erro : A função {} já está registrada como nativa", f.name)));
+            }
+        }
 
-            self.next_function_id += 1;
+        // Declared ahead, so it was already called by the code before it. Only the body is missing
+        let pending = {
+            let pending_functions = &self.pending_functions;
 
-            tmp
+            self.functions.iter().position(|e| e.name == f.name && pending_functions.contains(&e.id))
         };
-        let mut entry = FunctionEntry::from(f.name, id, f.arguments.clone());
 
-        // Register all parameters as variables inside the function stack
-        for arg in f.arguments {
-            match entry.add_var(arg.name, true) {
-                Ok(_) => {}
-                Err(e) => return Err(e)
+        let mut entry = match pending {
+            Some(index) => {
+                let entry = self.functions.remove(index);
+                self.pending_functions.retain(|&id| id != entry.id);
+
+                entry
+            }
+            None => {
+                let id = if is_main {
+                    BIRL_MAIN_FUNCTION_ID
+                } else {
+                    let tmp = self.next_function_id;
+
+                    self.next_function_id += 1;
+
+                    tmp
+                };
+
+                Context::new_entry(f, id)?
             }
+        };
+
+        // The constants at the start of the body belong to the declaration
+        entry.add_line(self.line);
+        entry.file = self.file.clone();
+
+        let name = entry.name.clone();
+
+        match entry.add_constant(BIRL_FUNCTION_NAME_CONSTANT, Instruction::PushMainStr(name)) {
+            Ok(_) => {}
+            Err(e) => return Err(e)
         }
 
+        let id = entry.id;
+
         self.functions.push(entry);
         self.scope = Scope::Function;
         self.last_function_id = id;
@@ -199,31 +627,116 @@ impl Context {
         Ok(id)
     }
     
-    pub fn end_function(&mut self) -> Result<(), String>{
+    /// Registers a function implemented in Rust, which scripts call like any other. The arguments
+    /// are type checked before getting to `function` and what it returns goes to TREZE.
+    /// Programs loaded from bytecode keep the functions they were compiled with, so registering
+    /// one of them again (with the same parameters) just gives it back its implementation.
+    pub fn register_native<F>(&mut self, name : &str, params : Vec<FunctionParameter>, function : F)
+        -> Result<u64, CompileError>
+        where F : FnMut(Vec<RawValue>) -> Result<RawValue, String> + 'static {
+        if self.scope != Scope::Global {
+            return Err(CompileError::new("Erro : Funções nativas só podem ser registradas fora de uma JAULA"));
+        }
+
+        if name == BIRL_MAIN_FUNCTION {
+            return Err(CompileError::new("Erro : A função principal não pode ser nativa"));
+        }
+
+        let existing = match self.get_function_by_name(name) {
+            Some(f) => {
+                if !f.is_native() {
+                    return Err(CompileError::new(format!("Erro : A função {} já está declarada", name)));
+                }
+
+                if f.params != params {
+                    return Err(CompileError::new(format!("Erro : A função nativa {} foi declarada com outros parâmetros", name)));
+                }
+
+                Some(f.clone())
+            }
+            None => None,
+        };
+
+        let entry = match existing {
+            Some(f) => f,
+            None => {
+                let id = self.next_function_id;
+                self.next_function_id += 1;
+
+                let mut entry = FunctionEntry::from(name.to_owned(), id, params.clone());
+
+                for p in &params {
+                    match entry.add_var(p.name.clone(), true) {
+                        Ok(_) => {}
+                        Err(e) => return Err(e)
+                    }
+                }
+
+                // The value pushed by CallNative is what Return gives back to the caller
+                entry.body.push(Instruction::CallNative(id));
+                entry.body.push(Instruction::Return);
+
+                self.functions.push(entry.clone());
+
+                entry
+            }
+        };
+
+        let mut ids = vec![];
+
+        for p in &params {
+            match entry.get_id_for(p.name.as_str()) {
+                Some(id) => ids.push(id),
+                None => return Err(CompileError::new(format!("Erro interno : O parâmetro {} não está registrado como variável", p.name))),
+            }
+        }
+
+        self.vm.set_native(entry.id, ids, Box::new(function));
+
+        Ok(entry.id)
+    }
+
+    /// How many blocks (the function being declared, conditionals and loops) are still waiting for
+    /// their end. Code only makes sense to run once they're all closed.
+    pub fn open_blocks(&self) -> usize {
+        self.global_scope.len() - 1 + self.function_scope.len()
+    }
+
+    pub fn end_function(&mut self) -> Result<(), CompileError>{
         if self.scope != Scope::Function {
-            return Err("Erro : Fim de função fora de uma função".to_owned());
+            return Err(CompileError::new("Erro : Fim de função fora de uma função"));
         }
 
         if self.function_scope.len() > 1 {
-            return Err("Erro : Feche todos os scopes antes de terminar a função".to_owned());
+            return Err(CompileError::new("Erro : Feche todos os scopes antes de terminar a função"));
         } else if self.function_scope.is_empty() {
-            return Err("Erro fatal : Scopes tá vazio".to_owned());
+            return Err(CompileError::new("Erro fatal : Scopes tá vazio"));
         }
 
         let last_scope = self.function_scope.remove(0);
         let id = self.last_function_id;
+        let line = self.line;
         match self.get_entry_by_id_mut(id) {
-            Some(f) => last_scope.at_end(f),
-            None => return Err(format!("Erro fatal : Nenhuma função com ID {}", id))
+            Some(f) => {
+                last_scope.at_end(f);
+
+                // Getting to the end without a BIRL returns nothing, which isn't what was declared
+                if let Some(kind) = f.return_kind {
+                    f.add_line(line);
+                    f.body.push(Instruction::PushNull);
+                    f.body.push(Instruction::AssertMainTopTypeCompatible(kind));
+                }
+            }
+            None => return Err(CompileError::new(format!("Erro fatal : Nenhuma função com ID {}", id)))
         }
 
         self.scope = Scope::Global;
         Ok(())
     }
 
-    pub fn start_function(&mut self, func: FunctionDeclaration) -> Result<(), String>{
+    pub fn start_function(&mut self, func: FunctionDeclaration) -> Result<(), CompileError>{
         if self.scope != Scope::Global {
-            return Err("Erro : Declaração de função fora do escopo global".to_owned());
+            return Err(CompileError::new("Erro : Declaração de função fora do escopo global"));
         }
 
         match self.add_function(func) {
@@ -236,14 +749,27 @@ impl Context {
         Ok(())
     }
 
-    pub fn process_line(&mut self, line : &str) -> Result<(), String> {
-        let mut instructions = vec![];
+    pub fn process_line(&mut self, line : &str) -> Result<(), Error> {
+        // Errors found after the line is parsed point to the start of the command
+        let column = line.chars().take_while(|c| c.is_whitespace()).count() + 1;
+
+        self.line += 1;
+
+        self.compile_line(line).map_err(|e| e.with_column(column))
+    }
 
+    fn compile_line(&mut self, line : &str) -> Result<(), Error> {
         let result = match parse_line(line) {
             Ok(r) => r,
             Err(e) => return Err(e)
         };
 
+        self.compile_result(result)
+    }
+
+    fn compile_result(&mut self, result : ParserResult) -> Result<(), Error> {
+        let mut instructions = vec![];
+
         match result {
             ParserResult::Command(cmd) => {
                 let hint = {
@@ -253,20 +779,20 @@ impl Context {
                         Scope::Global => {
                             match self.get_entry_by_id(BIRL_GLOBAL_FUNCTION_ID) {
                                 Some(g) => (None, g),
-                                None => return Err(format!("Erro fatal : Função global não registrada"))
+                                None => return Err(CompileError::new("Erro fatal : Função global não registrada").into())
                             }
                         }
                         Scope::Function => {
                             let global = match self.get_entry_by_id(BIRL_GLOBAL_FUNCTION_ID) {
                                 Some(g) => Some(g),
-                                None => return Err(format!("Erro fatal : Função global não registrada"))
+                                None => return Err(CompileError::new("Erro fatal : Função global não registrada").into())
                             };
 
                             let id = self.last_function_id;
 
                             let func = match self.get_entry_by_id(id) {
                                 Some(f) => f,
-                                None => return Err(format!("Não foi encontrada função com ID {}", id))
+                                None => return Err(CompileError::new(format!("Não foi encontrada função com ID {}", id)).into())
                             };
 
                             (global, func)
@@ -275,7 +801,7 @@ impl Context {
 
                     match Compiler::compile_command(cmd, current, &global, funcs, &mut instructions) {
                         Ok(hint) => hint,
-                        Err(e) => return Err(e)
+                        Err(e) => return Err(e.into())
                     }
                 };
 
@@ -287,35 +813,32 @@ impl Context {
                                     Scope::Global => {
                                         match self.get_entry_by_id_mut(BIRL_GLOBAL_FUNCTION_ID) {
                                             Some(f) => f,
-                                            None => return Err("Erro fatal : Nenhuma função global".to_owned())
+                                            None => return Err(CompileError::new("Erro fatal : Nenhuma função global").into())
                                         }
                                     }
                                     Scope::Function => {
                                         let id = self.last_function_id;
                                         match self.get_entry_by_id_mut(id) {
                                             Some(f) => f,
-                                            None => return Err(format!("Erro fatal : Nenhuma função com ID {}", id)),
+                                            None => return Err(CompileError::new(format!("Erro fatal : Nenhuma função com ID {}", id)).into()),
                                         }
                                     }
                                 };
 
-                                match entry.add_var(var.name, var.writeable) {
-                                    Ok(id) => id,
-                                    Err(e) => return Err(e)
-                                }
+                                entry.add_typed_var(var.name, var.writeable, var.kind)?
                             };
 
                             let scope = match self.scope {
                                 Scope::Global => {
                                     if self.global_scope.is_empty() {
-                                        return Err("Erro fatal : Scopes tá vazio".to_owned());
+                                        return Err(CompileError::new("Erro fatal : Scopes tá vazio").into());
                                     }
 
                                     self.global_scope.last_mut().unwrap()
                                 }
                                 Scope::Function => {
                                     if self.function_scope.is_empty() {
-                                        return Err("Erro fatal : Scopes tá vazio".to_owned());
+                                        return Err(CompileError::new("Erro fatal : Scopes tá vazio").into());
                                     }
 
                                     self.function_scope.last_mut().unwrap()
@@ -332,27 +855,74 @@ impl Context {
 
                             scope.push(ScopeManager::empty());
                         }
-                        CompilerHint::ScopeEnd => {
+                        CompilerHint::LoopStart(start, counter) => {
+                            let mut loop_scope = ScopeManager::for_loop(start);
+
+                            if let Some(var) = counter {
+                                let entry = match self.scope {
+                                    Scope::Global => {
+                                        match self.get_entry_by_id_mut(BIRL_GLOBAL_FUNCTION_ID) {
+                                            Some(f) => f,
+                                            None => return Err(CompileError::new("Erro fatal : Nenhuma função global").into())
+                                        }
+                                    }
+                                    Scope::Function => {
+                                        let id = self.last_function_id;
+                                        match self.get_entry_by_id_mut(id) {
+                                            Some(f) => f,
+                                            None => return Err(CompileError::new(format!("Erro fatal : Nenhuma função com ID {}", id)).into()),
+                                        }
+                                    }
+                                };
+
+                                loop_scope.ids.push(entry.add_var(var.name, var.writeable)?);
+                            }
+
+                            let scope = match self.scope {
+                                Scope::Global => &mut self.global_scope,
+                                Scope::Function => &mut self.function_scope,
+                            };
+
+                            scope.push(loop_scope);
+                        }
+                        CompilerHint::ScopeElse => {
                             let scope = {
                                 let scope = match self.scope {
                                     Scope::Global => &mut self.global_scope,
                                     Scope::Function => &mut self.function_scope,
                                 };
 
-                                if scope.is_empty() {
-                                    return Err("Erro fatal : Scope vazio".to_owned());
+                                // The first scope is the one of the function itself
+                                if scope.len() < 2 {
+                                    return Err(CompileError::new("Erro : SENÃO fora de um bloco condicional").into());
+                                }
+
+                                {
+                                    let last = scope.last().unwrap();
+
+                                    if last.loop_start.is_some() {
+                                        return Err(CompileError::new("Erro : SENÃO dentro de uma repetição").into());
+                                    }
+
+                                    if last.is_else {
+                                        return Err(CompileError::new("Erro : O bloco condicional já tem um SENÃO").into());
+                                    }
                                 }
 
                                 let index = scope.len() - 1;
+                                let old = scope.remove(index);
 
-                                scope.remove(index)
+                                scope.push(ScopeManager::for_else());
+
+                                old
                             };
 
+                            // Variables declared before the SENÃO don't exist after it
                             let func = match self.scope {
                                 Scope::Global => {
                                     match self.get_entry_by_id_mut(BIRL_GLOBAL_FUNCTION_ID) {
                                         Some(f) => f,
-                                        None => return Err("Erro fatal : Não encontrada função global".to_owned()),
+                                        None => return Err(CompileError::new("Erro fatal : Não encontrada função global").into()),
                                     }
                                 }
                                 Scope::Function => {
@@ -360,34 +930,87 @@ impl Context {
 
                                     match self.get_entry_by_id_mut(id) {
                                         Some(f) => f,
-                                        None => return Err(format!("Erro fatal : Nenhuma função com ID {}", id))
+                                        None => return Err(CompileError::new(format!("Erro fatal : Nenhuma função com ID {}", id)).into())
                                     }
                                 }
                             };
 
                             scope.at_end(func);
                         }
-                    }
-                }
+                        CompilerHint::ScopeEnd => {
+                            let scope = {
+                                let scope = match self.scope {
+                                    Scope::Global => &mut self.global_scope,
+                                    Scope::Function => &mut self.function_scope,
+                                };
 
-                let body = match self.scope {
-                    Scope::Function => {
-                        let id = self.last_function_id;
-                        match self.get_entry_by_id_mut(id) {
-                            Some(f) => &mut f.body,
-                            None => return Err(format!("Erro fatal : Nenhuma função com ID {}", id)),
-                        }
-                    }
-                    Scope::Global => {
-                        match self.get_entry_by_id_mut(BIRL_GLOBAL_FUNCTION_ID) {
-                            Some(f) => &mut f.body,
-                            None => return Err(format!("Erro fatal : Nenhuma função global"))
-                        }
+                                if scope.is_empty() {
+                                    return Err(CompileError::new("Erro fatal : Scope vazio").into());
+                                }
+
+                                let index = scope.len() - 1;
+
+                                scope.remove(index)
+                            };
+
+                            let func = match self.scope {
+                                Scope::Global => {
+                                    match self.get_entry_by_id_mut(BIRL_GLOBAL_FUNCTION_ID) {
+                                        Some(f) => f,
+                                        None => return Err(CompileError::new("Erro fatal : Não encontrada função global").into()),
+                                    }
+                                }
+                                Scope::Function => {
+                                    let id = self.last_function_id;
+
+                                    match self.get_entry_by_id_mut(id) {
+                                        Some(f) => f,
+                                        None => return Err(CompileError::new(format!("Erro fatal : Nenhuma função com ID {}", id)).into())
+                                    }
+                                }
+                            };
+
+                            // The end of a loop goes back to its condition, which either runs the
+                            // body once more or skips right past the end of the scope
+                            if let Some(start) = scope.loop_start {
+                                instructions.insert(0, Instruction::Jump(start));
+                            }
+
+                            scope.at_end(func);
+                        }
+                    }
+                }
+
+                let line = self.line;
+                let file = self.file.clone();
+
+                let func = match self.scope {
+                    Scope::Function => {
+                        let id = self.last_function_id;
+                        match self.get_entry_by_id_mut(id) {
+                            Some(f) => f,
+                            None => return Err(CompileError::new(format!("Erro fatal : Nenhuma função com ID {}", id)).into()),
+                        }
+                    }
+                    Scope::Global => {
+                        match self.get_entry_by_id_mut(BIRL_GLOBAL_FUNCTION_ID) {
+                            Some(f) => f,
+                            None => return Err(CompileError::new("Erro fatal : Nenhuma função global").into())
+                        }
                     }
                 };
 
+                if !instructions.is_empty() {
+                    func.add_line(line);
+
+                    // The global code doesn't have a declaration, so it's from the first file that adds to it
+                    if func.file.is_none() {
+                        func.file = file;
+                    }
+                }
+
                 for i in instructions {
-                    body.push(i);
+                    func.body.push(i);
                 }
             }
             ParserResult::FunctionEnd => self.end_function()?,
@@ -398,59 +1021,117 @@ impl Context {
         Ok(())
     }
 
-    pub fn add_source_string(&mut self, string : String) -> Result<(), String> {
-        let reader = BufReader::new(string.as_bytes());
+    pub fn add_source_string(&mut self, string : String) -> Result<(), Error> {
+        let program = ast::parse_program(string.as_str())?;
 
-        for line in reader.lines() {
-            match line {
-                Ok(line) => {
-                    match self.process_line(line.as_str()) {
-                        Ok(_) => {}
-                        Err(e) => return Err(e)
-                    }
-                }
-                Err(e) => return Err(format!("{:?}", e))
-            }
+        self.add_program(&program)
+    }
+
+    pub fn add_file(&mut self, filename : &str) -> Result<(), Error> {
+        let program = ast::parse_file(filename)?;
+
+        self.add_program(&program)
+    }
+
+    /// Serializes the function table, so the program can be loaded later without being parsed
+    pub fn to_bytecode(&self) -> Result<Vec<u8>, CompileError> {
+        if self.scope != Scope::Global {
+            return Err(CompileError::new("Erro : Termine a função antes de compilar o programa"));
+        }
+
+        if self.global_scope.len() > 1 {
+            return Err(CompileError::new("Erro : Feche todos os scopes antes de compilar o programa"));
+        }
+
+        let mut functions = self.functions.clone();
+
+        // The name of whoever compiled the program stays out of the file. It's set to the name of
+        // whoever runs it when it's loaded
+        if let Some(global) = functions.iter_mut().find(|f| f.id == BIRL_GLOBAL_FUNCTION_ID) {
+            global.set_constant(BIRL_USER_NAME_CONSTANT, Instruction::PushMainStr(String::new()));
+        }
+
+        let program = CompiledProgram {
+            functions,
+            has_main : self.has_main,
+            next_function_id : self.next_function_id,
+        };
+
+        Ok(bytecode::encode(&program))
+    }
+
+    /// Replaces the function table with the one from a compiled program. Only works on a context
+    /// that hasn't compiled anything yet, since the ids in the bytecode would clash with its own.
+    pub fn load_bytecode(&mut self, bytes : &[u8]) -> Result<(), Error> {
+        if self.scope != Scope::Global || self.functions.len() > 1 || self.global_scope.len() > 1 {
+            return Err(Error::from(BytecodeError::new("O bytecode só pode ser carregado num contexto vazio")));
+        }
+
+        let program = match bytecode::decode(bytes) {
+            Ok(p) => p,
+            Err(e) => return Err(Error::from(e))
+        };
+
+        self.functions = program.functions;
+        self.has_main = program.has_main;
+        self.next_function_id = program.next_function_id;
+
+        if let Some(global) = self.functions.iter_mut().find(|f| f.id == BIRL_GLOBAL_FUNCTION_ID) {
+            global.set_constant(BIRL_USER_NAME_CONSTANT, Instruction::PushMainStr(Context::user_name()));
         }
 
         Ok(())
     }
 
-    pub fn add_file(&mut self, filename : &str) -> Result<(), String> {
-        let file = match File::open(filename) {
-            Ok(f) => f,
-            Err(e) => return Err(format!("{:?}", e)),
+    pub fn save_bytecode_file(&self, filename : &str) -> Result<(), Error> {
+        let bytes = match self.to_bytecode() {
+            Ok(b) => b,
+            Err(e) => return Err(Error::from(e).with_file(filename))
         };
 
-        let mut line_num = 0usize;
+        match fs::write(filename, bytes) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(Error::from(IoError::new(format!("{:?}", e))).with_file(filename))
+        }
+    }
 
-        let reader = BufReader::new(file);
+    pub fn add_bytecode_file(&mut self, filename : &str) -> Result<(), Error> {
+        let bytes = match fs::read(filename) {
+            Ok(b) => b,
+            Err(e) => return Err(Error::from(IoError::new(format!("{:?}", e))).with_file(filename))
+        };
 
-        for line in reader.lines() {
-            line_num += 1;
-            match line {
-                Ok(line) => {
-                    match self.process_line(line.as_str()) {
-                        Ok(_) => {}
-                        Err(e) => return Err(format!("(Linha {}) : {:?}", line_num, e))
-                    }
+        self.load_bytecode(bytes.as_slice()).map_err(|e| e.with_file(filename))
+    }
+
+    fn push_raw_value(value : RawValue, instructions : &mut Vec<Instruction>) {
+        match value {
+            RawValue::Text(t) => instructions.push(Instruction::PushMainStr(t)),
+            RawValue::Number(n) => instructions.push(Instruction::PushMainNum(n)),
+            RawValue::Integer(i) => instructions.push(Instruction::PushMainInt(i)),
+            RawValue::Boolean(b) => instructions.push(Instruction::PushMainBool(b)),
+            RawValue::Null => instructions.push(Instruction::PushNull),
+            RawValue::List(items) => {
+                let len = items.len();
+
+                for item in items {
+                    Context::push_raw_value(item, instructions);
                 }
-                Err(e) => return Err(format!("(Linha {}) : {:?}", line_num, e))
+
+                instructions.push(Instruction::MakeList(len));
             }
         }
-
-        Ok(())
     }
 
-    pub fn call_function_by_id(&mut self, id : u64, mut args : Vec<RawValue>) -> Result<(), String> {
+    pub fn call_function_by_id(&mut self, id : u64, mut args : Vec<RawValue>) -> Result<(), RuntimeError> {
         let mut instructions = vec![];
 
         for f in &self.functions {
             if f.id == id {
 
                 if f.params.len() != args.len() {
-                    return Err(format!("A função {} espera {} argumentos, mas {} foram passados",
-                                       f.name, f.params.len(), args.len()));
+                    return Err(RuntimeError::new(format!("A função {} espera {} argumentos, mas {} foram passados",
+                                       f.name, f.params.len(), args.len())));
                 }
 
                 instructions.push(Instruction::MakeNewFrame(id));
@@ -471,20 +1152,17 @@ impl Context {
                     }
 
                     if let None = eid {
-                        return Err(format!("Erro interno : O argumento {} não tá registrado como variável", arg_name));
+                        return Err(RuntimeError::new(format!("Erro interno : O argumento {} não tá registrado como variável", arg_name)));
                     }
 
                     let val = args.remove(0);
 
-                    match val {
-                        RawValue::Text(t) => instructions.push(Instruction::PushMainStr(t)),
-                        RawValue::Number(n) => instructions.push(Instruction::PushMainNum(n)),
-                        RawValue::Integer(i) => instructions.push(Instruction::PushMainInt(i)),
-                    }
+                    Context::push_raw_value(val, &mut instructions);
 
                     instructions.push(Instruction::AssertMainTopTypeCompatible(exp));
 
-                    instructions.push(Instruction::WriteToVarWithId(eid.unwrap()));
+                    // The new frame isn't ready yet, so it must be addressed directly
+                    instructions.push(Instruction::WriteToLastFrameVarWithId(eid.unwrap()));
                 }
 
                 instructions.push(Instruction::SetLastFrameReady);
@@ -492,7 +1170,7 @@ impl Context {
         }
 
         if instructions.is_empty() {
-            return Err(format!("Não encontrada a função com ID {}", id));
+            return Err(RuntimeError::new(format!("Não encontrada a função com ID {}", id)));
         }
 
         for i in instructions {
@@ -505,7 +1183,7 @@ impl Context {
         Ok(())
     }
 
-    pub fn call_function_by_name(&mut self, name : &str, args : Vec<RawValue>) -> Result<(), String> {
+    pub fn call_function_by_name(&mut self, name : &str, args : Vec<RawValue>) -> Result<(), RuntimeError> {
         let mut id = None;
 
         for f in &self.functions {
@@ -519,20 +1197,77 @@ impl Context {
         if let Some(id) = id {
             self.call_function_by_id(id, args)
         } else {
-            Err(format!("Função {} não encontrada.", name))
+            Err(RuntimeError::new(format!("Função {} não encontrada.", name)))
+        }
+    }
+
+    /// Runs the function until it returns and gives back what it returned. The global code runs
+    /// first if it hasn't yet. Can be used in the middle of another execution, like from the
+    /// debugger, and the VM is left the way it was even if the function fails or quits.
+    pub fn invoke_by_id(&mut self, id : u64, args : Vec<RawValue>) -> Result<RawValue, RuntimeError> {
+        if self.vm.get_callstack().is_empty() {
+            match self.run_global() {
+                Ok(_) => {}
+                Err(e) => return Err(e)
+            }
+        }
+
+        if self.vm.get_last_ready_index().is_none() {
+            return Err(RuntimeError::new("Nenhuma função em execução pra receber o retorno"));
+        }
+
+        let checkpoint = self.vm.checkpoint();
+        let result = self.run_invoked(id, args);
+
+        self.vm.restore(checkpoint);
+
+        result
+    }
+
+    pub fn invoke(&mut self, name : &str, args : Vec<RawValue>) -> Result<RawValue, RuntimeError> {
+        let id = match self.get_function_by_name(name) {
+            Some(f) => f.id,
+            None => return Err(RuntimeError::new(format!("Função {} não encontrada.", name))),
+        };
+
+        self.invoke_by_id(id, args)
+    }
+
+    fn run_invoked(&mut self, id : u64, args : Vec<RawValue>) -> Result<RawValue, RuntimeError> {
+        let depth = self.vm.get_callstack().len();
+
+        match self.call_function_by_id(id, args) {
+            Ok(_) => {}
+            Err(e) => return Err(e)
         }
+
+        loop {
+            match self.execute_next_instruction() {
+                Ok(ExecutionStatus::Returned) => {
+                    if self.vm.get_callstack().len() == depth {
+                        break;
+                    }
+                }
+                Ok(ExecutionStatus::Normal) => {}
+                // The program was told to quit before the function returned anything
+                Ok(ExecutionStatus::Quit) => return Ok(RawValue::Null),
+                Err(e) => return Err(e)
+            }
+        }
+
+        self.vm.read_main_top()
     }
 
-    pub fn execute_next_instruction(&mut self) -> Result<ExecutionStatus, String> {
+    pub fn execute_next_instruction(&mut self) -> Result<ExecutionStatus, RuntimeError> {
 
         let pc = match self.vm.get_current_pc() {
             Some(p) => p,
-            None => return Err("Erro recebendo PC : Nenhuma função em execução".to_owned())
+            None => return Err(RuntimeError::new("Erro recebendo PC : Nenhuma função em execução"))
         };
 
         let id = match self.vm.get_current_id() {
             Some(id) => id,
-            None => return Err("Erro recebendo ID atual : Nenhuma função em execução".to_owned())
+            None => return Err(RuntimeError::new("Erro recebendo ID atual : Nenhuma função em execução"))
         };
 
         let instruction = match self.get_entry_by_id(id) {
@@ -546,7 +1281,7 @@ impl Context {
                     e.body[pc].clone()
                 }
             }
-            None => return Err(format!("Nenhuma função com ID {}", id))
+            None => return Err(RuntimeError::new(format!("Nenhuma função com ID {}", id)))
         }; 
 		
         match self.vm.increment_pc() {
@@ -556,7 +1291,7 @@ impl Context {
         
         let status = match self.vm.run(&instruction) {
             Ok(status) => status,
-            Err(e) => return Err(e)
+            Err(e) => return Err(self.locate_error(e, id, pc))
         };
 
         /* We REEEALY don't want to increment the counter if we're
@@ -578,44 +1313,900 @@ impl Context {
         Ok(status)
     }
 
-    pub fn start_program(&mut self) -> Result<(), String> {
-        match self.call_function_by_id(BIRL_GLOBAL_FUNCTION_ID, vec![]) {
-            Ok(_) => {}
-            Err(e) => return Err(e)
+    /// Points the error to the line of the instruction that failed and records the functions
+    /// that led to it
+    fn locate_error(&self, mut error : RuntimeError, id : u64, pc : usize) -> RuntimeError {
+        if let Some(f) = self.get_entry_by_id(id) {
+            if error.span.line.is_none() {
+                error.span.line = f.line_at(pc);
+            }
+
+            if error.span.file.is_none() {
+                error.span.file = f.file.clone();
+            }
+
+            if error.span.function.is_none() {
+                error.span.function = Some(f.name.clone());
+            }
+        }
+
+        if !error.details.backtrace.is_empty() {
+            return error;
         }
 
+        for frame in self.callstack().iter().rev().filter(|f| f.ready) {
+            // The callers already moved past the call, but the one that failed didn't run yet
+            let address = if error.details.backtrace.is_empty() { pc } else { frame.program_counter.saturating_sub(1) };
+
+            let line = match self.get_entry_by_id(frame.function_id) {
+                Some(f) => f.line_at(address),
+                None => None,
+            };
+
+            error.details.backtrace.push(BacktraceFrame {
+                function_id : frame.function_id,
+                function : frame.function.clone(),
+                program_counter : address,
+                file : frame.file.clone(),
+                line,
+            });
+        }
+
+        error
+    }
+
+    fn run_until_quit(&mut self) -> Result<(), RuntimeError> {
         loop {
             match self.execute_next_instruction() {
                 Ok(ExecutionStatus::Normal) => {}
-                Ok(ExecutionStatus::Returned) => {},
+                Ok(ExecutionStatus::Returned) => {}
                 Ok(ExecutionStatus::Quit) => break,
                 Err(e) => return Err(e)
             }
         }
 
+        Ok(())
+    }
+
+    /// Runs the global code. Only needed to get to the globals before calling anything, since
+    /// start_program and invoke already do it.
+    pub fn run_global(&mut self) -> Result<(), RuntimeError> {
+        match self.call_function_by_id(BIRL_GLOBAL_FUNCTION_ID, vec![]) {
+            Ok(_) => {}
+            Err(e) => return Err(e)
+        }
+
+        match self.run_until_quit() {
+            Ok(_) => {}
+            Err(e) => return Err(e)
+        }
+
         self.vm.unset_quit();
 
+        Ok(())
+    }
+
+    pub fn start_program(&mut self) -> Result<(), RuntimeError> {
+        match self.run_global() {
+            Ok(_) => {}
+            Err(e) => return Err(e)
+        }
+
         if self.has_main {
             match self.call_function_by_name(BIRL_MAIN_FUNCTION, vec![]) {
                 Ok(_) => {}
                 Err(e) => return Err(e)
             }
 
-            loop {
-                match self.execute_next_instruction() {
-                    Ok(ExecutionStatus::Normal) => {}
-                    Ok(ExecutionStatus::Returned) => {}
-                    Ok(ExecutionStatus::Quit) => break,
-                    Err(e) => return Err(e)
-                }
+            match self.run_until_quit() {
+                Ok(_) => {}
+                Err(e) => return Err(e)
             }
         }
 
         Ok(())
-    } 
-    
+    }
+
+    /// Runs the global code and then the given function, instead of the main one.
+    pub fn start_program_from(&mut self, entry : &str, args : Vec<RawValue>) -> Result<(), RuntimeError> {
+        match self.run_global() {
+            Ok(_) => {}
+            Err(e) => return Err(e)
+        }
+
+        match self.call_function_by_name(entry, args) {
+            Ok(_) => {}
+            Err(e) => return Err(e)
+        }
+
+        self.run_until_quit()
+    }
+
+    fn global_var_id(&self, name : &str) -> Result<Option<u64>, RuntimeError> {
+        // The global frame stays at the bottom of the callstack once the global code runs
+        match self.vm.get_callstack().first() {
+            Some(f) if f.get_id() == BIRL_GLOBAL_FUNCTION_ID => {}
+            _ => return Err(RuntimeError::new("O código global ainda não rodou")),
+        }
+
+        match self.get_entry_by_id(BIRL_GLOBAL_FUNCTION_ID) {
+            Some(global) => Ok(global.vars.iter().find(|v| v.name == name).map(|v| v.id)),
+            None => Err(RuntimeError::new("Erro interno : A função global não existe")),
+        }
+    }
+
+    /// Value of a global variable. None if there's no global with that name or the code declaring
+    /// it didn't run yet.
+    pub fn get_global(&self, name : &str) -> Result<Option<RawValue>, RuntimeError> {
+        match self.global_var_id(name) {
+            Ok(Some(id)) => self.vm.read_var(0, id),
+            Ok(None) => Ok(None),
+            Err(e) => Err(e)
+        }
+    }
+
+    /// Gives a global variable a new value. The variable must be declared in the global code and
+    /// not be a constant.
+    pub fn set_global(&mut self, name : &str, value : RawValue) -> Result<(), RuntimeError> {
+        let id = match self.global_var_id(name) {
+            Ok(Some(id)) => id,
+            Ok(None) => return Err(RuntimeError::new(format!("A variável global {} não existe", name))),
+            Err(e) => return Err(e)
+        };
+
+        let (writeable, kind) = self.get_entry_by_id(BIRL_GLOBAL_FUNCTION_ID)
+            .and_then(|g| g.vars.iter().find(|v| v.id == id))
+            .map_or((false, None), |v| (v.writeable, v.kind));
+
+        if !writeable {
+            return Err(RuntimeError::new(format!("A variável {} é constante", name)));
+        }
+
+        if let Some(kind) = kind {
+            if !value.fits(kind) {
+                return Err(RuntimeError::new(format!("A variável {} é {}, mas recebeu {}", name, kind, value)));
+            }
+        }
+
+        self.vm.write_var(0, id, value)
+    }
+
+    /// Variables of the function running right now that have a value, in the order they were
+    /// declared. Variables that went out of scope keep their last value.
+    pub fn locals(&self) -> Result<Vec<(String, RawValue)>, RuntimeError> {
+        let index = match self.vm.get_last_ready_index() {
+            Some(i) => i,
+            None => return Err(RuntimeError::new("Nenhuma função em execução")),
+        };
+
+        let id = self.vm.get_callstack()[index].get_id();
+
+        let entry = match self.get_entry_by_id(id) {
+            Some(e) => e,
+            None => return Err(RuntimeError::new(format!("Nenhuma função com ID {}", id))),
+        };
+
+        let mut locals = vec![];
+
+        for var in &entry.declared_vars {
+            match self.vm.read_var(index, var.id) {
+                Ok(Some(value)) => locals.push((var.name.clone(), value)),
+                Ok(None) => {}
+                Err(e) => return Err(e)
+            }
+        }
+
+        Ok(locals)
+    }
+
+    /// Lists the functions in the callstack, starting with the oldest one
+    pub fn callstack(&self) -> Vec<FrameInfo> {
+        let mut frames = vec![];
+
+        let callstack = self.vm.get_callstack();
+        let current = callstack.iter().rposition(|f| f.is_ready());
+
+        for (i, frame) in callstack.iter().enumerate() {
+            let id = frame.get_id();
+            let pc = frame.get_program_counter();
+
+            // The callers already moved past the instruction that made the call
+            let line_pc = if frame.is_ready() && Some(i) != current && pc > 0 { pc - 1 } else { pc };
+
+            let (name, file, line) = match self.get_entry_by_id(id) {
+                Some(f) => (f.name.clone(), f.file.clone(), f.line_at(line_pc)),
+                None => (format!("<ID {}>", id), None, None),
+            };
+
+            frames.push(FrameInfo {
+                function_id : id,
+                function : name,
+                program_counter : pc,
+                file,
+                line,
+                ready : frame.is_ready(),
+            });
+        }
+
+        frames
+    }
+
+    /// Shows the value of a variable of the frame at `index` in the callstack. Variables declared
+    /// in blocks that already ended may share the name of others, so the newest one wins.
+    pub fn read_variable(&self, index : usize, name : &str) -> Result<Option<String>, RuntimeError> {
+        let id = match self.vm.get_callstack().get(index) {
+            Some(f) => f.get_id(),
+            None => return Err(RuntimeError::new(format!("Index inválido : {}", index))),
+        };
+
+        let func = match self.get_entry_by_id(id) {
+            Some(f) => f,
+            None => return Err(RuntimeError::new(format!("Nenhuma função com ID {}", id))),
+        };
+
+        for var in func.declared_vars.iter().rev() {
+            if var.name != name {
+                continue;
+            }
+
+            match self.vm.describe_var(index, var.id) {
+                Ok(Some(value)) => return Ok(Some(value)),
+                Ok(None) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(None)
+    }
+
     pub fn print_version() {
         println!("{}", BIRL_VERSION);
         println!("{}", BIRL_COPYRIGHT);
     }
 }
+
+mod tests {
+    #[test]
+    fn runtime_error_location() {
+        use context::Context;
+
+        let source = "JAULA DIVIDE(A : BATATA DOCE, B : BATATA DOCE)\n\
+                      VEM: R, A % B\n\
+                      BIRL: R\n\
+                      SAINDO DA JAULA\n\
+                      JAULA MEIO(N : BATATA DOCE)\n\
+                      E HORA DO: DIVIDE, N, 0\n\
+                      BIRL: TREZE\n\
+                      SAINDO DA JAULA\n\
+                      JAULA SHOW\n\
+                      CE QUER VER: MEIO(3)\n\
+                      SAINDO DA JAULA";
+
+        let mut context = Context::new();
+
+        if let Err(e) = context.add_source_string(source.to_owned()) {
+            panic!("Erro compilando o código : {}", e);
+        }
+
+        let error = match context.start_program() {
+            Ok(_) => panic!("O programa deveria ter falhado"),
+            Err(e) => e,
+        };
+
+        assert_eq!(error.span.line, Some(2));
+        assert_eq!(error.span.function, Some(String::from("DIVIDE")));
+        assert_eq!(error.span.file, None);
+
+        let trace : Vec<(&str, Option<usize>)> = error.details.backtrace.iter()
+            .map(|f| (f.function.as_str(), f.line))
+            .collect();
+        assert_eq!(trace, vec![("DIVIDE", Some(2)), ("MEIO", Some(6)), ("SHOW", Some(10)), ("__global__", None)]);
+
+        let divide = context.get_function_by_name("DIVIDE").expect("DIVIDE não encontrada");
+        assert_eq!(error.details.backtrace[0].function_id, divide.id);
+        assert_eq!(divide.line_at(error.details.backtrace[0].program_counter), Some(2));
+
+        let text = format!("{}", error);
+        let callers : Vec<&str> = text.lines().skip(1).map(|l| l.trim()).collect();
+        assert_eq!(callers, vec!["chamada por MEIO (Linha 6)", "chamada por SHOW (Linha 10)"]);
+    }
+
+    #[test]
+    fn backtrace() {
+        use context::Context;
+        use vm::ExecutionLimits;
+        use error::Limit;
+
+        let source = "JAULA R(N : BATATA DOCE)\n\
+                      BIRL: R(N + 1)\n\
+                      SAINDO DA JAULA\n\
+                      JAULA SHOW\n\
+                      CE QUER VER: R(1)\n\
+                      SAINDO DA JAULA";
+
+        let mut context = Context::new();
+        context.set_limits(ExecutionLimits { max_call_depth : Some(16), .. ExecutionLimits::default() });
+
+        if let Err(e) = context.add_source_string(source.to_owned()) {
+            panic!("Erro compilando o código : {}", e);
+        }
+
+        let error = match context.start_program() {
+            Ok(_) => panic!("O programa deveria ter falhado"),
+            Err(e) => e,
+        };
+
+        assert_eq!(error.details.limit, Some(Limit::CallDepth));
+
+        // Every frame on the callstack, from where the error happened to the global code
+        let trace : Vec<(&str, Option<usize>)> = error.details.backtrace.iter()
+            .map(|f| (f.function.as_str(), f.line))
+            .collect();
+
+        let mut expected = vec![("R", Some(2)); 14];
+        expected.push(("SHOW", Some(5)));
+        expected.push(("__global__", None));
+
+        assert_eq!(trace, expected);
+
+        // Only the closest callers are printed, the rest are counted
+        let text = format!("{}", error);
+        let lines : Vec<&str> = text.lines().skip(1).map(|l| l.trim()).collect();
+
+        assert_eq!(lines.len(), 11);
+        assert!(lines[.. 10].iter().all(|l| *l == "chamada por R (Linha 2)"), "{}", text);
+        assert_eq!(lines[10], "... e mais 4 chamadas");
+    }
+
+    #[test]
+    fn execution_limits() {
+        use context::Context;
+        use vm::ExecutionLimits;
+        use error::{ Limit, RuntimeError };
+
+        fn run(source : &str, limits : ExecutionLimits) -> Result<(), RuntimeError> {
+            let mut context = Context::new();
+
+            context.set_stdout(Some(Box::new(::std::io::sink())));
+            context.set_stdin(Some(Box::new(::std::io::Cursor::new("1\n".repeat(100)))));
+            context.set_limits(limits);
+
+            if let Err(e) = context.add_source_string(source.to_owned()) {
+                panic!("Erro compilando o código : {}", e);
+            }
+
+            context.start_program()
+        }
+
+        let recursion = "JAULA R(N : BATATA DOCE)\n\
+                         BIRL: R(N + 1)\n\
+                         SAINDO DA JAULA\n\
+                         CE QUER VER: R(1)";
+        let strings = "VEM: S, \"x\"\n\
+                       ENQUANTO FOR VERDADE: VERDADE\n\
+                       BORA: S, S + S\n\
+                       FIM";
+        let reads = "VEM: S, \"\"\n\
+                     ENQUANTO FOR VERDADE: VERDADE\n\
+                     FALA AI: S\n\
+                     FIM";
+        let lists = "ENQUANTO FOR VERDADE: VERDADE\n\
+                     VEM: L, [1, 2, 3]\n\
+                     FIM";
+        let list_text = "VEM: L, []\n\
+                         ENQUANTO FOR VERDADE: VERDADE\n\
+                         BOTA NA MARMITA: L, \"xxxxxxxx\"\n\
+                         FIM";
+
+        let cases = [
+            (recursion, ExecutionLimits { max_call_depth : Some(10), .. ExecutionLimits::default() }, Limit::CallDepth),
+            (recursion, ExecutionLimits { max_instructions : Some(500), .. ExecutionLimits::default() }, Limit::Instructions),
+            (strings, ExecutionLimits { max_string_bytes : Some(4096), .. ExecutionLimits::default() }, Limit::StringBytes),
+            (reads, ExecutionLimits { max_input_reads : Some(5), .. ExecutionLimits::default() }, Limit::InputReads),
+            (lists, ExecutionLimits { max_list_items : Some(100), .. ExecutionLimits::default() }, Limit::ListItems),
+            (list_text, ExecutionLimits { max_string_bytes : Some(1024), .. ExecutionLimits::default() }, Limit::StringBytes),
+        ];
+
+        for &(source, limits, expected) in cases.iter() {
+            match run(source, limits) {
+                Ok(_) => panic!("O limite {:?} não foi aplicado", expected),
+                Err(e) => assert_eq!(e.details.limit, Some(expected), "Erro inesperado : {}", e),
+            }
+        }
+
+        // Programs that stay under the limits aren't affected
+        let limits = ExecutionLimits {
+            max_instructions : Some(1000),
+            max_call_depth : Some(10),
+            max_string_bytes : Some(1024),
+            max_input_reads : Some(1),
+            max_list_items : Some(10),
+        };
+
+        assert_eq!(run("VEM: S, \"oi\"\nFALA AI: S\nCE QUER VER: S + \"!\"", limits), Ok(()));
+
+        // Only the text still in use counts, so printing or copying text over and over is fine
+        let text_limit = ExecutionLimits { max_string_bytes : Some(1000), .. ExecutionLimits::default() };
+        let text_loops = [
+            "REPETE: 2000\nCE QUER VER: \"X\"\nFIM",
+            "VEM: S, \"ABCDEFGH\"\nREPETE: 2000\nBORA: S, S\nFIM",
+            "VEM: S, \"ABCDEFGH\"\nREPETE: 2000\nVEM: T, S + \"!\"\nE ELE QUE A GENTE QUER: T, S\nFIM",
+        ];
+
+        for source in text_loops.iter() {
+            assert_eq!(run(source, text_limit), Ok(()), "{}", source);
+        }
+
+        // Taking elements out of a list frees room for others
+        let popping = "VEM: L, []\n\
+                       VEM: X, 0\n\
+                       REPETE: 100\n\
+                       BOTA NA MARMITA: L, \"xxxxxxxx\"\n\
+                       TIRA DA MARMITA: L, X\n\
+                       FIM";
+        let list_limit = ExecutionLimits { max_list_items : Some(5), max_string_bytes : Some(100), .. ExecutionLimits::default() };
+
+        assert_eq!(run(popping, list_limit), Ok(()));
+
+        // Errors of the program itself aren't limit errors
+        match run("CE QUER VER: 1 % 0", limits) {
+            Ok(_) => panic!("O programa deveria ter falhado"),
+            Err(e) => assert!(!e.is_limit_exceeded()),
+        }
+    }
+
+    #[test]
+    fn native_functions() {
+        use context::{ Context, RawValue };
+        use parser::{ FunctionParameter, TypeKind };
+        use std::rc::Rc;
+        use std::cell::RefCell;
+
+        fn param(name : &str, kind : TypeKind) -> FunctionParameter {
+            FunctionParameter { name : name.to_owned(), kind }
+        }
+
+        // Registers the natives used below. GUARDA and ANOTA keep what they get, so the test can see it
+        fn register(context : &mut Context, kept : &Rc<RefCell<Vec<RawValue>>>) {
+            context.register_native("DOBRA", vec![param("N", TypeKind::Integer)], |args| {
+                match args[0] {
+                    RawValue::Integer(n) => Ok(RawValue::Integer(n * 2)),
+                    _ => Err(String::from("DOBRA espera um inteiro")),
+                }
+            }).expect("Erro registrando DOBRA");
+
+            context.register_native("JUNTA", vec![param("A", TypeKind::Text), param("B", TypeKind::Text)], |args| {
+                match (&args[0], &args[1]) {
+                    (RawValue::Text(a), RawValue::Text(b)) => Ok(RawValue::Text(format!("{}{}", a, b))),
+                    _ => Err(String::from("JUNTA espera dois textos")),
+                }
+            }).expect("Erro registrando JUNTA");
+
+            for &(name, kind) in &[("GUARDA", TypeKind::Integer), ("ANOTA", TypeKind::Text)] {
+                let kept = kept.clone();
+
+                context.register_native(name, vec![param("V", kind)], move |mut args| {
+                    let value = args.remove(0);
+                    kept.borrow_mut().push(value.clone());
+                    Ok(value)
+                }).expect("Erro registrando função");
+            }
+
+            context.register_native("FALHA", vec![], |_| Err(String::from("Deu ruim")))
+                .expect("Erro registrando FALHA");
+        }
+
+        let source = "E HORA DO: DOBRA, 21\n\
+                      E HORA DO: GUARDA, TREZE\n\
+                      E HORA DO: ANOTA, JUNTA(\"BIR\", \"L\")\n\
+                      E HORA DO: GUARDA, DOBRA(DOBRA(1)) + 1";
+        let expected = vec![RawValue::Integer(42), RawValue::Text(String::from("BIRL")), RawValue::Integer(5)];
+
+        let kept = Rc::new(RefCell::new(vec![]));
+        let mut context = Context::new();
+        register(&mut context, &kept);
+
+        assert!(context.register_native("DOBRA", vec![], |_| Ok(RawValue::Boolean(true))).is_err());
+        assert!(context.add_source_string("JAULA DOBRA\nSAINDO DA JAULA".to_owned()).is_err());
+
+        let mut context = Context::new();
+        register(&mut context, &kept);
+
+        if let Err(e) = context.add_source_string(source.to_owned()) {
+            panic!("Erro compilando o código : {}", e);
+        }
+
+        if let Err(e) = context.start_program() {
+            panic!("Erro rodando o código : {}", e);
+        }
+
+        assert_eq!(*kept.borrow(), expected);
+
+        // The natives are kept in the bytecode, but their implementation has to be registered again
+        let bytes = context.to_bytecode().expect("Erro gerando o bytecode");
+
+        let mut loaded = Context::new();
+        loaded.load_bytecode(&bytes).expect("Erro carregando o bytecode");
+        assert!(loaded.start_program().is_err());
+
+        kept.borrow_mut().clear();
+
+        let mut loaded = Context::new();
+        loaded.load_bytecode(&bytes).expect("Erro carregando o bytecode");
+        register(&mut loaded, &kept);
+
+        if let Err(e) = loaded.start_program() {
+            panic!("Erro rodando o bytecode : {}", e);
+        }
+
+        assert_eq!(*kept.borrow(), expected);
+
+        for &(source, message) in &[("E HORA DO: DOBRA, JUNTA(\"2\", \"1\")", "Tipo incompatível : Texto"), ("E HORA DO: FALHA", "Deu ruim")] {
+            let mut context = Context::new();
+            register(&mut context, &kept);
+
+            if let Err(e) = context.add_source_string(source.to_owned()) {
+                panic!("Erro compilando o código : {}", e);
+            }
+
+            match context.start_program() {
+                Ok(_) => panic!("O programa deveria ter falhado"),
+                Err(e) => assert_eq!(e.message, message),
+            }
+        }
+    }
+
+    #[test]
+    fn native_argument_types() {
+        use context::Context;
+        use parser::{ FunctionParameter, TypeKind };
+
+        let mut context = Context::new();
+        let param = FunctionParameter { name : "N".to_owned(), kind : TypeKind::Integer };
+
+        context.register_native("DOBRA", vec![param], |args| Ok(args[0].clone()))
+            .expect("Erro registrando DOBRA");
+
+        // The type of a literal is already known, so the call doesn't even compile
+        match context.add_source_string("E HORA DO: DOBRA, \"21\"".to_owned()) {
+            Ok(_) => panic!("O código deveria ter sido rejeitado"),
+            Err(e) => assert!(format!("{}", e).contains("Esperado BATATA DOCE, encontrado FIBRA"), "{}", e),
+        }
+    }
+
+    #[test]
+    fn invoke() {
+        use context::{ Context, RawValue, BIRL_GLOBAL_FUNCTION_ID, BIRL_RET_VAL_VAR_ID };
+
+        let source = "VEM: G, 10\n\
+                      JAULA SOMA(A : BATATA DOCE, B : BATATA DOCE)\n\
+                      BIRL: A + B\n\
+                      SAINDO DA JAULA\n\
+                      JAULA RESTO(A : BATATA DOCE, B : BATATA DOCE)\n\
+                      BIRL: SOMA(A % B, 0)\n\
+                      SAINDO DA JAULA\n\
+                      JAULA NADA\n\
+                      SAINDO DA JAULA\n\
+                      JAULA OI(NOME : FIBRA)\n\
+                      BIRL: \"OI \" + NOME\n\
+                      SAINDO DA JAULA\n\
+                      JAULA PEGA\n\
+                      BIRL: G\n\
+                      SAINDO DA JAULA\n\
+                      E HORA DO: SOMA, 1, 1";
+
+        let mut context = Context::new();
+
+        if let Err(e) = context.add_source_string(source.to_owned()) {
+            panic!("Erro compilando o código : {}", e);
+        }
+
+        // The global code runs before the first call
+        assert_eq!(context.invoke("PEGA", vec![]), Ok(RawValue::Integer(10)));
+        assert_eq!(context.invoke("SOMA", vec![RawValue::Integer(2), RawValue::Integer(3)]), Ok(RawValue::Integer(5)));
+        assert_eq!(context.invoke("OI", vec![RawValue::Text(String::from("BIRL"))]),
+                   Ok(RawValue::Text(String::from("OI BIRL"))));
+        assert_eq!(context.invoke("NADA", vec![]), Ok(RawValue::Null));
+
+        assert!(context.invoke("RESTO", vec![RawValue::Integer(1), RawValue::Integer(0)]).is_err());
+        assert!(context.invoke("SOMA", vec![RawValue::Text(String::from("1")), RawValue::Integer(1)]).is_err());
+        assert!(context.invoke("SOMA", vec![RawValue::Integer(1)]).is_err());
+        assert!(context.invoke("NAO EXISTE", vec![]).is_err());
+
+        // Failed calls don't leave anything behind, and the caller keeps its own return value
+        assert_eq!(context.vm.get_callstack().len(), 1);
+        assert_eq!(context.vm.read_var(BIRL_GLOBAL_FUNCTION_ID as usize, BIRL_RET_VAL_VAR_ID), Ok(Some(RawValue::Integer(2))));
+        assert_eq!(context.invoke("RESTO", vec![RawValue::Integer(9), RawValue::Integer(4)]), Ok(RawValue::Integer(1)));
+    }
+
+    #[test]
+    fn call_results() {
+        use context::{ Context, RawValue };
+
+        let source = "JAULA UM\n\
+                      BIRL: 1\n\
+                      SAINDO DA JAULA\n\
+                      JAULA DOIS\n\
+                      BIRL: 2\n\
+                      SAINDO DA JAULA\n\
+                      JAULA SOMA(A : BATATA DOCE, B : BATATA DOCE)\n\
+                      BIRL: A + B\n\
+                      SAINDO DA JAULA\n\
+                      JAULA SHOW\n\
+                      E HORA DO: UM\n\
+                      VEM: Y, DOIS() * 10 + SOMA(DOIS(), SOMA(UM(), DOIS())) * 100\n\
+                      BIRL: Y + TREZE\n\
+                      SAINDO DA JAULA\n\
+                      JAULA NOVE\n\
+                      E HORA DO: SOMA, 3, 4\n\
+                      BIRL: DOIS() + TREZE\n\
+                      SAINDO DA JAULA";
+
+        let mut context = Context::new();
+
+        if let Err(e) = context.add_source_string(source.to_owned()) {
+            panic!("Erro compilando o código : {}", e);
+        }
+
+        // Calls inside expressions give their result to the expression and leave TREZE alone
+        assert_eq!(context.invoke("SHOW", vec![]), Ok(RawValue::Integer(521)));
+        assert_eq!(context.invoke("NOVE", vec![]), Ok(RawValue::Integer(9)));
+    }
+
+    #[test]
+    fn host_variables() {
+        use context::{ Context, RawValue };
+        use debugger::{ Debugger, Breakpoint, StopReason };
+
+        let source = "VEM: ENTRADA, 0\n\
+                      VEM: SAIDA, 0\n\
+                      VEM: NOMES, [\"A\", [\"B\"]]\n\
+                      JAULA DOBRA(N : BATATA DOCE)\n\
+                      VEM: R, N * 2\n\
+                      BORA: SAIDA, R + ENTRADA\n\
+                      BIRL: R\n\
+                      SAINDO DA JAULA\n\
+                      JAULA PRIMEIRO(L : MARMITA)\n\
+                      BIRL: L[0]\n\
+                      SAINDO DA JAULA";
+
+        fn text(t : &str) -> RawValue {
+            RawValue::Text(t.to_owned())
+        }
+
+        let mut context = Context::new();
+
+        if let Err(e) = context.add_source_string(source.to_owned()) {
+            panic!("Erro compilando o código : {}", e);
+        }
+
+        assert!(context.get_global("ENTRADA").is_err());
+
+        if let Err(e) = context.run_global() {
+            panic!("Erro rodando o código global : {}", e);
+        }
+
+        assert_eq!(context.get_global("ENTRADA"), Ok(Some(RawValue::Integer(0))));
+        assert_eq!(context.get_global("NOMES"), Ok(Some(RawValue::List(vec![text("A"), RawValue::List(vec![text("B")])]))));
+        assert_eq!(context.get_global("NAO EXISTE"), Ok(None));
+
+        assert_eq!(context.set_global("ENTRADA", RawValue::Integer(5)), Ok(()));
+        assert_eq!(context.invoke("DOBRA", vec![RawValue::Integer(10)]), Ok(RawValue::Integer(20)));
+        assert_eq!(context.get_global("SAIDA"), Ok(Some(RawValue::Integer(25))));
+
+        assert!(context.set_global("VERDADE", RawValue::Boolean(false)).is_err());
+        assert!(context.set_global("NAO EXISTE", RawValue::Null).is_err());
+
+        assert_eq!(context.set_global("NOMES", RawValue::List(vec![text("X")])), Ok(()));
+        let nomes = context.get_global("NOMES").unwrap().unwrap();
+        assert_eq!(context.invoke("PRIMEIRO", vec![nomes]), Ok(text("X")));
+        assert_eq!(context.invoke("PRIMEIRO", vec![RawValue::List(vec![RawValue::List(vec![])])]),
+                   Ok(RawValue::List(vec![])));
+
+        let mut debugger = Debugger::new(Context::new());
+
+        if let Err(e) = debugger.context_mut().add_source_string(source.to_owned()) {
+            panic!("Erro compilando o código : {}", e);
+        }
+
+        debugger.add_breakpoint(Breakpoint::Line(7));
+
+        if let Err(e) = debugger.start_from("DOBRA", vec![RawValue::Integer(3)]) {
+            panic!("Erro iniciando o depurador : {}", e);
+        }
+
+        match debugger.resume() {
+            Ok(StopReason::Breakpoint(_)) => {}
+            other => panic!("Era esperado um ponto de parada, recebido {:?}", other),
+        }
+
+        assert_eq!(debugger.context().locals(), Ok(vec![
+            (String::from("N"), RawValue::Integer(3)),
+            (String::from("JAULA"), text("DOBRA")),
+            (String::from("R"), RawValue::Integer(6)),
+        ]));
+    }
+
+    #[test]
+    fn forward_references() {
+        use context::{ Context, RawValue };
+
+        let source = "JAULA SHOW\n\
+                      BIRL: PAR(10)\n\
+                      SAINDO DA JAULA\n\
+                      JAULA PAR(N : BATATA DOCE)\n\
+                      E VERDADE: N == 0\n\
+                      BIRL: VERDADE\n\
+                      FIM\n\
+                      BIRL: IMPAR(N - 1)\n\
+                      SAINDO DA JAULA\n\
+                      JAULA IMPAR(N : BATATA DOCE)\n\
+                      E VERDADE: N == 0\n\
+                      BIRL: MENTIRA\n\
+                      FIM\n\
+                      BIRL: PAR(N - 1)\n\
+                      SAINDO DA JAULA";
+
+        let mut context = Context::new();
+
+        if let Err(e) = context.add_source_string(source.to_owned()) {
+            panic!("Erro compilando o código : {}", e);
+        }
+
+        assert_eq!(context.invoke("SHOW", vec![]), Ok(RawValue::Boolean(true)));
+        assert_eq!(context.invoke("IMPAR", vec![RawValue::Integer(7)]), Ok(RawValue::Boolean(true)));
+
+        let par = context.get_function_by_name("PAR").expect("PAR não encontrada");
+        assert_eq!(par.line_at(0), Some(4));
+
+        // Functions declared after an error don't stay around without a body
+        let mut context = Context::new();
+        let error = context.add_source_string("E HORA DO: F, 1, 2\nJAULA F(X : BATATA DOCE)\nSAINDO DA JAULA".to_owned());
+
+        assert_eq!(error.map_err(|e| e.span().line), Err(Some(1)));
+        assert!(context.get_function_by_name("F").is_none());
+    }
+
+    #[test]
+    fn type_annotations() {
+        use context::{ Context, RawValue };
+        use parser::TypeKind;
+
+        let source = "VEM: N : BATATA DOCE, 1\n\
+                      VEM: T : TRAPEZIO DESCENDENTE, 2\n\
+                      JAULA METADE(X : TRAPEZIO DESCENDENTE) : TRAPEZIO DESCENDENTE\n\
+                      BIRL: X / 2\n\
+                      SAINDO DA JAULA\n\
+                      JAULA NOME(X : BATATA DOCE) : FIBRA\n\
+                      E VERDADE: X > 0\n\
+                      BIRL: \"POSITIVO\"\n\
+                      FIM\n\
+                      E VERDADE: X < 0\n\
+                      BIRL: X * 2\n\
+                      FIM\n\
+                      SAINDO DA JAULA\n\
+                      JAULA MUDA(V : TRAPEZIO DESCENDENTE)\n\
+                      BORA: N, V\n\
+                      SAINDO DA JAULA";
+
+        let mut context = Context::new();
+
+        if let Err(e) = context.add_source_string(source.to_owned()) {
+            panic!("Erro compilando o código : {}", e);
+        }
+
+        if let Err(e) = context.run_global() {
+            panic!("Erro rodando o código global : {}", e);
+        }
+
+        let metade = context.get_function_by_name("METADE").expect("METADE não encontrada");
+        assert_eq!(metade.return_kind, Some(TypeKind::Number));
+
+        // An Int is also a Num
+        assert_eq!(context.get_global("T"), Ok(Some(RawValue::Integer(2))));
+        assert_eq!(context.invoke("METADE", vec![RawValue::Integer(3)]), Ok(RawValue::Integer(1)));
+        assert_eq!(context.invoke("NOME", vec![RawValue::Integer(1)]), Ok(RawValue::Text("POSITIVO".to_owned())));
+
+        // Returning the wrong type, or not returning at all
+        assert!(context.invoke("NOME", vec![RawValue::Integer(-1)]).is_err());
+        assert!(context.invoke("NOME", vec![RawValue::Integer(0)]).is_err());
+
+        // Writes to a typed variable are checked when running, from the program or from outside
+        assert_eq!(context.invoke("MUDA", vec![RawValue::Integer(5)]), Ok(RawValue::Null));
+        assert!(context.invoke("MUDA", vec![RawValue::Number(0.5)]).is_err());
+        assert!(context.set_global("N", RawValue::Text("A".to_owned())).is_err());
+        assert_eq!(context.get_global("N"), Ok(Some(RawValue::Integer(5))));
+
+        // Values whose type is known are checked when compiling
+        let rejected = [
+            "VEM: X : BATATA DOCE, 1.5",
+            "VEM: X : FIBRA, \"A\"\nBORA: X, 2",
+            "VEM: X : BIRLEANO, CUMPADE",
+            "VEM: X : FIBRA, \"A\"\nVEM: Y : BATATA DOCE, X",
+            "JAULA F(X : FIBRA)\nSAINDO DA JAULA\nE HORA DO: F, [1]",
+            "JAULA F() : FIBRA\nBIRL: UM\nSAINDO DA JAULA",
+            "JAULA F() : FIBRA\nBIRL\nSAINDO DA JAULA",
+        ];
+
+        for source in rejected.iter() {
+            let mut context = Context::new();
+
+            assert!(context.add_source_string(source.to_string()).is_err(), "{}", source);
+        }
+
+        let failing = [
+            "VEM: X : BATATA DOCE, 1.5 + 1",
+            "VEM: X : FIBRA, \"A\"\nBORA: X, \"B\"\nBORA: X, 1 + 1",
+        ];
+
+        for source in failing.iter() {
+            let mut context = Context::new();
+
+            context.set_stdout(Some(Box::new(::std::io::sink())));
+
+            if let Err(e) = context.add_source_string(source.to_string()) {
+                panic!("Erro compilando o código : {}", e);
+            }
+
+            assert!(context.run_global().is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn default_constants() {
+        use context::{ Context, RawValue, BIRL_GLOBAL_FUNCTION_ID };
+        use vm::Instruction;
+        use bytecode;
+
+        fn text(t : &str) -> RawValue {
+            RawValue::Text(t.to_owned())
+        }
+
+        let source = "JAULA SHOW\n\
+                      BIRL: [CUMPADE, UM, BODYBUILDER, JAULA, F()]\n\
+                      SAINDO DA JAULA\n\
+                      JAULA F\n\
+                      BIRL: JAULA\n\
+                      SAINDO DA JAULA";
+
+        let mut context = Context::new();
+
+        if let Err(e) = context.add_source_string(source.to_owned()) {
+            panic!("Erro compilando o código : {}", e);
+        }
+
+        // JAULA has the name of the function it's read in
+        let expected = RawValue::List(vec![
+            text(Context::user_name().as_str()),
+            RawValue::Integer(1),
+            text("BAMBAM"),
+            text("SHOW"),
+            text("F"),
+        ]);
+
+        assert_eq!(context.invoke("SHOW", vec![]), Ok(expected.clone()));
+
+        // The name of the user isn't saved in the bytecode, but it's there again once it's loaded
+        let bytes = context.to_bytecode().unwrap();
+        let program = bytecode::decode(bytes.as_slice()).unwrap();
+        let global = program.functions.iter().find(|f| f.id == BIRL_GLOBAL_FUNCTION_ID).unwrap();
+
+        assert!(matches!(global.body.first(), Some(Instruction::PushMainStr(ref s)) if s.is_empty()), "{:?}", global.body);
+
+        let mut loaded = Context::new();
+
+        if let Err(e) = loaded.load_bytecode(bytes.as_slice()) {
+            panic!("Erro carregando o bytecode : {}", e);
+        }
+
+        assert_eq!(loaded.invoke("SHOW", vec![]), Ok(expected));
+
+        // None of them can be written
+        for name in ["CUMPADE", "UM", "BODYBUILDER", "JAULA"].iter() {
+            let mut context = Context::new();
+            let source = format!("JAULA SHOW\nBORA: {}, 2\nSAINDO DA JAULA", name);
+
+            assert!(context.add_source_string(source).is_err(), "{}", name);
+        }
+    }
+}
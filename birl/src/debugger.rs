@@ -0,0 +1,399 @@
+//! Step-through debugging, built on top of Context::execute_next_instruction
+//!
+//! The debugger only stops right before the first instruction of a source line, so stepping goes
+//! a line at a time. Lines inside blocks that are being skipped never count as stops.
+
+use context::{ Context, RawValue, FrameInfo, BIRL_GLOBAL_FUNCTION_ID, BIRL_MAIN_FUNCTION };
+use vm::ExecutionStatus;
+use error::RuntimeError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Breakpoint {
+    /// Stops when the function with this name is called
+    Function(String),
+    /// Stops before running the code of this line
+    Line(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopReason {
+    /// Hit the breakpoint with this ID
+    Breakpoint(u32),
+    /// The step finished
+    Step,
+    /// The program ended
+    Finished,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    NotStarted,
+    Global,
+    Entry,
+    Finished,
+}
+
+pub struct Debugger {
+    context : Context,
+    breakpoints : Vec<(u32, Breakpoint)>,
+    next_breakpoint_id : u32,
+    phase : Phase,
+    // Function that runs after the global code, with its arguments
+    entry : Option<(String, Vec<RawValue>)>,
+}
+
+impl Debugger {
+    pub fn new(context : Context) -> Debugger {
+        Debugger {
+            context,
+            breakpoints : vec![],
+            next_breakpoint_id : 1,
+            phase : Phase::NotStarted,
+            entry : None,
+        }
+    }
+
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    pub fn context_mut(&mut self) -> &mut Context {
+        &mut self.context
+    }
+
+    pub fn into_context(self) -> Context {
+        self.context
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint : Breakpoint) -> u32 {
+        let id = self.next_breakpoint_id;
+        self.next_breakpoint_id += 1;
+
+        self.breakpoints.push((id, breakpoint));
+
+        id
+    }
+
+    pub fn remove_breakpoint(&mut self, id : u32) -> bool {
+        match self.breakpoints.iter().position(|&(bid, _)| bid == id) {
+            Some(i) => {
+                self.breakpoints.remove(i);
+
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn breakpoints(&self) -> &[(u32, Breakpoint)] {
+        &self.breakpoints
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.phase != Phase::NotStarted
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.phase == Phase::Finished
+    }
+
+    /// Prepares the global code and then the main function to run, like Context::start_program.
+    /// Nothing runs until one of the step functions or resume is called.
+    pub fn start(&mut self) -> Result<(), RuntimeError> {
+        let entry = self.context.get_function_by_name(BIRL_MAIN_FUNCTION)
+            .map(|_| (BIRL_MAIN_FUNCTION.to_owned(), vec![]));
+
+        self.start_with(entry)
+    }
+
+    /// Same as start, but runs the given function instead of the main one
+    pub fn start_from(&mut self, entry : &str, args : Vec<RawValue>) -> Result<(), RuntimeError> {
+        if self.context.get_function_by_name(entry).is_none() {
+            return Err(RuntimeError::new(format!("Função {} não encontrada.", entry)));
+        }
+
+        self.start_with(Some((entry.to_owned(), args)))
+    }
+
+    fn start_with(&mut self, entry : Option<(String, Vec<RawValue>)>) -> Result<(), RuntimeError> {
+        if self.phase != Phase::NotStarted {
+            return Err(RuntimeError::new("O programa já foi iniciado"));
+        }
+
+        match self.context.call_function_by_id(BIRL_GLOBAL_FUNCTION_ID, vec![]) {
+            Ok(_) => {}
+            Err(e) => return Err(e)
+        }
+
+        self.entry = entry;
+        self.phase = Phase::Global;
+
+        Ok(())
+    }
+
+    /// Runs until the next line, entering functions that get called
+    pub fn step_in(&mut self) -> Result<StopReason, RuntimeError> {
+        self.run_until(|_| true)
+    }
+
+    /// Runs until the next line of the current function, or of a caller if it returns
+    pub fn step_over(&mut self) -> Result<StopReason, RuntimeError> {
+        let depth = self.context.vm.get_depth();
+
+        self.run_until(|d| d <= depth)
+    }
+
+    /// Runs until the current function returns and stops at the next line of the caller
+    pub fn step_out(&mut self) -> Result<StopReason, RuntimeError> {
+        let depth = self.context.vm.get_depth();
+
+        self.run_until(|d| d < depth)
+    }
+
+    /// Runs until a breakpoint is hit or the program ends
+    pub fn resume(&mut self) -> Result<StopReason, RuntimeError> {
+        self.run_until(|_| false)
+    }
+
+    /// Where the program is stopped, or None if it isn't running
+    pub fn location(&self) -> Option<FrameInfo> {
+        if self.phase == Phase::NotStarted || self.phase == Phase::Finished {
+            return None;
+        }
+
+        self.context.callstack().into_iter().rev().find(|f| f.ready)
+    }
+
+    pub fn callstack(&self) -> Vec<FrameInfo> {
+        self.context.callstack()
+    }
+
+    /// Reads a variable of the current function, or a global one if the function doesn't have it
+    pub fn read_variable(&self, name : &str) -> Result<Option<String>, RuntimeError> {
+        let callstack = self.context.callstack();
+
+        if let Some(index) = callstack.iter().rposition(|f| f.ready) {
+            match self.context.read_variable(index, name) {
+                Ok(Some(value)) => return Ok(Some(value)),
+                Ok(None) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        match callstack.iter().position(|f| f.function_id == BIRL_GLOBAL_FUNCTION_ID) {
+            Some(index) => self.context.read_variable(index, name),
+            None => Ok(None),
+        }
+    }
+
+    fn run_until<F : Fn(usize) -> bool>(&mut self, should_stop : F) -> Result<StopReason, RuntimeError> {
+        if self.phase == Phase::NotStarted {
+            return Err(RuntimeError::new("O programa ainda não foi iniciado"));
+        }
+
+        // Going from the global code to the entry function counts as the global code returning, so
+        // every kind of step stops at the first line of the entry function
+        let mut entered = false;
+
+        loop {
+            if self.phase == Phase::Finished {
+                return Ok(StopReason::Finished);
+            }
+
+            let phase = self.phase;
+
+            match self.execute() {
+                Ok(_) => {}
+                Err(e) => {
+                    self.phase = Phase::Finished;
+
+                    return Err(e);
+                }
+            }
+
+            if phase == Phase::Global && self.phase == Phase::Entry {
+                entered = true;
+            }
+
+            if !self.is_at_line_start() {
+                continue;
+            }
+
+            if let Some(id) = self.hit_breakpoint() {
+                return Ok(StopReason::Breakpoint(id));
+            }
+
+            let depth = if entered { 0 } else { self.context.vm.get_depth() };
+
+            if should_stop(depth) {
+                return Ok(StopReason::Step);
+            }
+        }
+    }
+
+    fn execute(&mut self) -> Result<(), RuntimeError> {
+        let status = self.context.execute_next_instruction()?;
+
+        if let ExecutionStatus::Quit = status {
+            if self.phase == Phase::Global {
+                self.context.vm.unset_quit();
+
+                match self.entry.take() {
+                    Some((name, args)) => {
+                        match self.context.call_function_by_name(name.as_str(), args) {
+                            Ok(_) => {}
+                            Err(e) => return Err(e)
+                        }
+
+                        self.phase = Phase::Entry;
+                    }
+                    None => self.phase = Phase::Finished,
+                }
+            } else {
+                self.phase = Phase::Finished;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_at_line_start(&self) -> bool {
+        if self.phase == Phase::Finished || self.context.vm.get_current_skip_level() > 0 {
+            return false;
+        }
+
+        let (id, pc) = match (self.context.vm.get_current_id(), self.context.vm.get_current_pc()) {
+            (Some(id), Some(pc)) => (id, pc),
+            _ => return false,
+        };
+
+        match self.context.get_entry_by_id(id) {
+            Some(f) => f.starts_line(pc),
+            None => false,
+        }
+    }
+
+    fn hit_breakpoint(&self) -> Option<u32> {
+        let (id, pc) = match (self.context.vm.get_current_id(), self.context.vm.get_current_pc()) {
+            (Some(id), Some(pc)) => (id, pc),
+            _ => return None,
+        };
+
+        let func = self.context.get_entry_by_id(id)?;
+
+        for &(bid, ref breakpoint) in &self.breakpoints {
+            let hit = match breakpoint {
+                // The first line of a function is its declaration
+                Breakpoint::Function(name) => pc == 0 && func.name == *name,
+                Breakpoint::Line(line) => func.line_at(pc) == Some(*line),
+            };
+
+            if hit {
+                return Some(bid);
+            }
+        }
+
+        None
+    }
+}
+
+mod tests {
+    #[test]
+    fn stepping_and_breakpoints() {
+        use debugger::*;
+        use context::Context;
+
+        let source = "VEM: G, 10\n\
+                      JAULA DOBRO(N : TRAPEZIO DESCENDENTE)\n\
+                      VEM: R, N * 2\n\
+                      BIRL: R\n\
+                      SAINDO DA JAULA\n\
+                      JAULA SHOW\n\
+                      VEM: X, 1\n\
+                      E HORA DO: DOBRO, X\n\
+                      BORA: X, TREZE + G\n\
+                      E VERDADE: X > 100\n\
+                      BORA: X, 0\n\
+                      FIM\n\
+                      CE QUER VER: X\n\
+                      SAINDO DA JAULA";
+
+        let mut context = Context::new();
+        context.set_stdout(Some(Box::new(::std::io::sink())));
+
+        if let Err(e) = context.add_source_string(source.to_owned()) {
+            panic!("Erro compilando o código : {}", e);
+        }
+
+        let mut debugger = Debugger::new(context);
+
+        let line = |d : &Debugger| d.location().and_then(|f| f.line);
+
+        assert!(debugger.step_in().is_err(), "Passo antes de iniciar o programa");
+
+        debugger.start().expect("Erro iniciando o programa");
+
+        assert_eq!(debugger.step_in(), Ok(StopReason::Step));
+        assert_eq!(line(&debugger), Some(1));
+
+        // From the global code to the main function
+        assert_eq!(debugger.step_over(), Ok(StopReason::Step));
+        assert_eq!(line(&debugger), Some(6));
+        assert_eq!(debugger.step_in(), Ok(StopReason::Step));
+        assert_eq!(line(&debugger), Some(7));
+
+        assert_eq!(debugger.step_in(), Ok(StopReason::Step));
+        assert_eq!(line(&debugger), Some(8));
+        assert_eq!(debugger.read_variable("X"), Ok(Some(String::from("1"))));
+        assert_eq!(debugger.read_variable("G"), Ok(Some(String::from("10"))));
+        assert_eq!(debugger.read_variable("NADA"), Ok(None));
+
+        // Into the call
+        assert_eq!(debugger.step_in(), Ok(StopReason::Step));
+        assert_eq!(line(&debugger), Some(2));
+        assert_eq!(debugger.step_in(), Ok(StopReason::Step));
+        assert_eq!(line(&debugger), Some(3));
+        assert_eq!(debugger.read_variable("N"), Ok(Some(String::from("1"))));
+
+        let names : Vec<String> = debugger.callstack().into_iter()
+            .filter(|f| f.ready)
+            .map(|f| f.function)
+            .collect();
+        assert_eq!(names, vec!["__global__", "SHOW", "DOBRO"]);
+
+        assert_eq!(debugger.step_out(), Ok(StopReason::Step));
+        assert_eq!(line(&debugger), Some(9));
+        assert_eq!(debugger.read_variable("TREZE"), Ok(Some(String::from("2"))));
+
+        // The block is skipped, so its lines aren't stops
+        assert_eq!(debugger.step_over(), Ok(StopReason::Step));
+        assert_eq!(line(&debugger), Some(10));
+        assert_eq!(debugger.step_over(), Ok(StopReason::Step));
+        assert_eq!(line(&debugger), Some(13));
+        assert_eq!(debugger.read_variable("X"), Ok(Some(String::from("12"))));
+
+        assert_eq!(debugger.resume(), Ok(StopReason::Finished));
+        assert!(debugger.is_finished());
+        assert_eq!(debugger.location(), None);
+
+        // Breakpoints from the start
+        let mut context = Context::new();
+        context.set_stdout(Some(Box::new(::std::io::sink())));
+        context.add_source_string(source.to_owned()).expect("Erro compilando o código");
+
+        let mut debugger = Debugger::new(context);
+
+        let on_function = debugger.add_breakpoint(Breakpoint::Function(String::from("DOBRO")));
+        let on_line = debugger.add_breakpoint(Breakpoint::Line(13));
+        let removed = debugger.add_breakpoint(Breakpoint::Line(9));
+        assert!(debugger.remove_breakpoint(removed));
+        assert!(!debugger.remove_breakpoint(removed));
+
+        debugger.start().expect("Erro iniciando o programa");
+
+        assert_eq!(debugger.resume(), Ok(StopReason::Breakpoint(on_function)));
+        assert_eq!(line(&debugger), Some(2));
+        assert_eq!(debugger.resume(), Ok(StopReason::Breakpoint(on_line)));
+        assert_eq!(debugger.resume(), Ok(StopReason::Finished));
+    }
+}
@@ -0,0 +1,352 @@
+//! Errors reported by each stage of the interpreter
+
+use std::fmt::{ Display, self };
+use std::error::Error as StdError;
+
+/// Where in the source an error happened. Each stage fills in what it knows: the lexer and the
+/// parser only see a single line, so they know the column, while the context knows the line and
+/// the file. Errors while running also know the function they happened in.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Span {
+    pub file : Option<String>,
+    pub line : Option<usize>,
+    pub column : Option<usize>,
+    pub function : Option<String>,
+}
+
+impl Span {
+    pub fn at_column(column : usize) -> Span {
+        Span {
+            file : None,
+            line : None,
+            column : Some(column),
+            function : None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.file.is_none() && self.line.is_none() && self.column.is_none() && self.function.is_none()
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = vec![];
+
+        if let Some(ref file) = self.file {
+            parts.push(file.clone());
+        }
+
+        if let Some(line) = self.line {
+            parts.push(format!("Linha {}", line));
+        }
+
+        if let Some(column) = self.column {
+            parts.push(format!("Coluna {}", column));
+        }
+
+        if let Some(ref function) = self.function {
+            parts.push(format!("JAULA {}", function));
+        }
+
+        write!(f, "({})", parts.join(", "))
+    }
+}
+
+/// A function that was running when a runtime error happened
+#[derive(Debug, Clone, PartialEq)]
+pub struct BacktraceFrame {
+    pub function_id : u64,
+    pub function : String,
+    // Address of the instruction that was running: the one that failed or the call to the next frame
+    pub program_counter : usize,
+    pub file : Option<String>,
+    pub line : Option<usize>,
+}
+
+// Deep recursion would make runtime errors huge, so only the closest callers are printed
+const MAX_PRINTED_CALLERS : usize = 10;
+
+macro_rules! error_type {
+    ($(#[$attr:meta])* $name:ident, $variant:ident) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct $name {
+            pub message : String,
+            pub span : Span,
+        }
+
+        impl $name {
+            pub fn new<S : Into<String>>(message : S) -> $name {
+                $name {
+                    message : message.into(),
+                    span : Span::default(),
+                }
+            }
+
+            pub fn at_column<S : Into<String>>(message : S, column : usize) -> $name {
+                $name {
+                    message : message.into(),
+                    span : Span::at_column(column),
+                }
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+                if self.span.is_empty() {
+                    write!(f, "{}", self.message)
+                } else {
+                    write!(f, "{} : {}", self.span, self.message)
+                }
+            }
+        }
+
+        impl StdError for $name {}
+
+        error_conversion!($name, $variant);
+    }
+}
+
+macro_rules! error_conversion {
+    ($name:ident, $variant:ident) => {
+        impl From<$name> for Error {
+            fn from(e : $name) -> Error {
+                Error::$variant(e)
+            }
+        }
+    }
+}
+
+error_type!(
+    /// Invalid characters or literals while splitting a line into tokens
+    LexError, Lex);
+error_type!(
+    /// Tokens that don't make up a valid line
+    ParseError, Parse);
+error_type!(
+    /// Valid lines that can't be turned into instructions, like unknown variables or functions
+    CompileError, Compile);
+error_type!(
+    /// Values of the wrong type for an operator, a command or a parameter, found before running
+    TypeError, Type);
+/// Which of the execution limits a program went over
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    Instructions,
+    CallDepth,
+    StringBytes,
+    InputReads,
+    ListItems,
+}
+
+/// What only runtime errors know about
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RuntimeDetails {
+    /// Functions that were running when the error happened, starting with the one it happened in
+    pub backtrace : Vec<BacktraceFrame>,
+    /// Set when the program was stopped for going over a limit, instead of failing by itself
+    pub limit : Option<Limit>,
+}
+
+/// Failures while running instructions, like incompatible types or stack overflows
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub message : String,
+    pub span : Span,
+    /// Boxed, since every instruction returns a Result with this error, and keeping it small keeps
+    /// those small too
+    pub details : Box<RuntimeDetails>,
+}
+
+impl RuntimeError {
+    pub fn new<S : Into<String>>(message : S) -> RuntimeError {
+        RuntimeError {
+            message : message.into(),
+            span : Span::default(),
+            details : Box::default(),
+        }
+    }
+
+    pub fn at_column<S : Into<String>>(message : S, column : usize) -> RuntimeError {
+        RuntimeError {
+            message : message.into(),
+            span : Span::at_column(column),
+            details : Box::default(),
+        }
+    }
+
+    pub fn limit_exceeded<S : Into<String>>(limit : Limit, message : S) -> RuntimeError {
+        RuntimeError {
+            message : message.into(),
+            span : Span::default(),
+            details : Box::new(RuntimeDetails { backtrace : vec![], limit : Some(limit) }),
+        }
+    }
+
+    pub fn is_limit_exceeded(&self) -> bool {
+        self.details.limit.is_some()
+    }
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        if self.span.is_empty() {
+            write!(f, "{}", self.message)?;
+        } else {
+            write!(f, "{} : {}", self.span, self.message)?;
+        }
+
+        // The first frame is where the error happened, which the span already shows. Frames
+        // without a line are the global code after it ended, which isn't really a caller
+        let callers : Vec<&BacktraceFrame> = self.details.backtrace.iter()
+            .skip(1)
+            .filter(|frame| frame.line.is_some())
+            .collect();
+
+        for (i, caller) in callers.iter().enumerate() {
+            if i == MAX_PRINTED_CALLERS {
+                write!(f, "\n    ... e mais {} chamadas", callers.len() - i)?;
+
+                break;
+            }
+
+            let span = Span {
+                file : caller.file.clone(),
+                line : caller.line,
+                column : None,
+                function : None,
+            };
+
+            write!(f, "\n    chamada por {} {}", caller.function, span)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl StdError for RuntimeError {}
+
+error_conversion!(RuntimeError, Runtime);
+error_type!(
+    /// Failures reading the source
+    IoError, Io);
+error_type!(
+    /// Compiled programs that are corrupted or were made by an incompatible version
+    BytecodeError, Bytecode);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Lex(LexError),
+    Parse(ParseError),
+    Compile(CompileError),
+    Type(TypeError),
+    Runtime(RuntimeError),
+    Io(IoError),
+    Bytecode(BytecodeError),
+}
+
+impl Error {
+    pub fn message(&self) -> &str {
+        match self {
+            Error::Lex(e) => e.message.as_str(),
+            Error::Parse(e) => e.message.as_str(),
+            Error::Compile(e) => e.message.as_str(),
+            Error::Type(e) => e.message.as_str(),
+            Error::Runtime(e) => e.message.as_str(),
+            Error::Io(e) => e.message.as_str(),
+            Error::Bytecode(e) => e.message.as_str(),
+        }
+    }
+
+    pub fn span(&self) -> &Span {
+        match self {
+            Error::Lex(e) => &e.span,
+            Error::Parse(e) => &e.span,
+            Error::Compile(e) => &e.span,
+            Error::Type(e) => &e.span,
+            Error::Runtime(e) => &e.span,
+            Error::Io(e) => &e.span,
+            Error::Bytecode(e) => &e.span,
+        }
+    }
+
+    pub fn span_mut(&mut self) -> &mut Span {
+        match self {
+            Error::Lex(e) => &mut e.span,
+            Error::Parse(e) => &mut e.span,
+            Error::Compile(e) => &mut e.span,
+            Error::Type(e) => &mut e.span,
+            Error::Runtime(e) => &mut e.span,
+            Error::Io(e) => &mut e.span,
+            Error::Bytecode(e) => &mut e.span,
+        }
+    }
+
+    /// Sets the line the error happened on, keeping the one that's already there, if any.
+    pub fn with_line(mut self, line : usize) -> Error {
+        {
+            let span = self.span_mut();
+
+            if span.line.is_none() {
+                span.line = Some(line);
+            }
+        }
+
+        self
+    }
+
+    /// Sets the column the error happened on, keeping the one that's already there, if any.
+    pub fn with_column(mut self, column : usize) -> Error {
+        {
+            let span = self.span_mut();
+
+            if span.column.is_none() {
+                span.column = Some(column);
+            }
+        }
+
+        self
+    }
+
+    /// Sets the file the error happened in, keeping the one that's already there, if any.
+    pub fn with_file(mut self, file : &str) -> Error {
+        {
+            let span = self.span_mut();
+
+            if span.file.is_none() {
+                span.file = Some(file.to_owned());
+            }
+        }
+
+        self
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Lex(e) => e.fmt(f),
+            Error::Parse(e) => e.fmt(f),
+            Error::Compile(e) => e.fmt(f),
+            Error::Type(e) => e.fmt(f),
+            Error::Runtime(e) => e.fmt(f),
+            Error::Io(e) => e.fmt(f),
+            Error::Bytecode(e) => e.fmt(f),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Lex(e) => Some(e),
+            Error::Parse(e) => Some(e),
+            Error::Compile(e) => Some(e),
+            Error::Type(e) => Some(e),
+            Error::Runtime(e) => Some(e),
+            Error::Io(e) => Some(e),
+            Error::Bytecode(e) => Some(e),
+        }
+    }
+}
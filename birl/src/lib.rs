@@ -1,4 +1,9 @@
 pub mod parser;
 pub mod context;
 pub mod vm;
-pub mod compiler;
\ No newline at end of file
+pub mod compiler;
+pub mod error;
+pub mod bytecode;
+pub mod debugger;
+pub mod ast;
+pub mod checker;
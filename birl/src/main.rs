@@ -0,0 +1,477 @@
+//! Runs BirlScript files from the command line
+
+extern crate birl;
+
+use birl::context::{ Context, RawValue, BIRL_MAIN_FUNCTION };
+use birl::parser::{ TypeKind, IntegerType };
+use birl::bytecode::BYTECODE_EXTENSION;
+use birl::vm::ExecutionLimits;
+use birl::ast;
+
+use std::env;
+use std::io::{ self, BufRead, Write };
+use std::path::Path;
+use std::process;
+use std::str::FromStr;
+
+const EXIT_SUCCESS : i32 = 0;
+// The program failed while running
+const EXIT_RUNTIME_ERROR : i32 = 1;
+// Invalid flags or arguments on the command line
+const EXIT_USAGE_ERROR : i32 = 2;
+// The file couldn't be read or compiled
+const EXIT_LOAD_ERROR : i32 = 3;
+// The program was stopped for going over one of the limits
+const EXIT_LIMIT_ERROR : i32 = 4;
+
+struct Options {
+    file : Option<String>,
+    entry : Option<String>,
+    args : Vec<String>,
+    // Compile the file to bytecode instead of running it
+    compile : bool,
+    // Look for type errors before running
+    check_types : bool,
+    output : Option<String>,
+    limits : ExecutionLimits,
+    show_help : bool,
+    show_version : bool,
+}
+
+fn print_help() {
+    println!("Uso: birl [opções] arquivo.birl [argumentos...]");
+    println!("     birl -c arquivo.birl [-s saida.{}]", BYTECODE_EXTENSION);
+    println!();
+    println!("Opções:");
+    println!("    -a, --ajuda-o-maluco-ta-doente    Mostra essa mensagem e sai");
+    println!("    -v, --versao                      Mostra a versão do interpretador e sai");
+    println!("    -j, --jaula NOME                  Executa a JAULA NOME no lugar da {}", BIRL_MAIN_FUNCTION);
+    println!("    -c, --compila                     Compila o arquivo pra bytecode no lugar de executar");
+    println!("    -t, --tipos                       Procura erros de tipo no arquivo todo antes de executar");
+    println!("    -s, --saida ARQUIVO               Onde salvar o bytecode. O padrão é o nome do arquivo");
+    println!("                                      com a extensão .{}", BYTECODE_EXTENSION);
+    println!("    --max-instrucoes N                Para o programa depois de N instruções");
+    println!("    --max-chamadas N                  Limita a profundidade da pilha de chamadas");
+    println!("    --max-texto N                     Limita a memória usada por texto, em bytes");
+    println!("    --max-leituras N                  Limita quantas vezes o programa pode ler a entrada");
+    println!("    --max-itens N                     Limita quantos elementos as listas podem ter, somadas");
+    println!("    --                                Tudo depois disso é tratado como arquivo e argumentos");
+    println!();
+    println!("Os argumentos depois do arquivo são passados pra JAULA executada, convertidos pro tipo");
+    println!("de cada parâmetro. Arquivos .{} são carregados direto, sem passar pelo parser.", BYTECODE_EXTENSION);
+}
+
+fn parse_options(raw : Vec<String>) -> Result<Options, String> {
+    let mut options = Options {
+        file : None,
+        entry : None,
+        args : vec![],
+        compile : false,
+        check_types : false,
+        output : None,
+        limits : ExecutionLimits::default(),
+        show_help : false,
+        show_version : false,
+    };
+
+    let mut iter = raw.into_iter();
+    let mut only_positional = false;
+
+    while let Some(arg) = iter.next() {
+        if options.file.is_some() {
+            // Everything after the file belongs to the script
+            options.args.push(arg);
+            continue;
+        }
+
+        if only_positional || !arg.starts_with('-') {
+            options.file = Some(arg);
+            continue;
+        }
+
+        match arg.as_str() {
+            "-a" | "--ajuda-o-maluco-ta-doente" => options.show_help = true,
+            "-v" | "--versao" | "--versão" => options.show_version = true,
+            "-j" | "--jaula" => {
+                match iter.next() {
+                    Some(name) => options.entry = Some(name),
+                    None => return Err(format!("A opção {} espera o nome de uma JAULA", arg)),
+                }
+            }
+            "-c" | "--compila" => options.compile = true,
+            "-t" | "--tipos" => options.check_types = true,
+            "-s" | "--saida" | "--saída" => {
+                match iter.next() {
+                    Some(name) => options.output = Some(name),
+                    None => return Err(format!("A opção {} espera o nome de um arquivo", arg)),
+                }
+            }
+            "--max-instrucoes" | "--max-instruções" => options.limits.max_instructions = Some(parse_limit(&arg, iter.next())?),
+            "--max-chamadas" => options.limits.max_call_depth = Some(parse_limit(&arg, iter.next())?),
+            "--max-texto" => options.limits.max_string_bytes = Some(parse_limit(&arg, iter.next())?),
+            "--max-leituras" => options.limits.max_input_reads = Some(parse_limit(&arg, iter.next())?),
+            "--max-itens" => options.limits.max_list_items = Some(parse_limit(&arg, iter.next())?),
+            "--" => only_positional = true,
+            _ => return Err(format!("Opção desconhecida : {}", arg)),
+        }
+    }
+
+    if options.output.is_some() && !options.compile {
+        return Err(String::from("A opção -s só pode ser usada junto com -c"));
+    }
+
+    Ok(options)
+}
+
+fn parse_limit<T : FromStr>(option : &str, value : Option<String>) -> Result<T, String> {
+    match value {
+        Some(v) => match v.parse::<T>() {
+            Ok(n) => Ok(n),
+            Err(_) => Err(format!("A opção {} espera um número, mas recebeu \"{}\"", option, v)),
+        },
+        None => Err(format!("A opção {} espera um número", option)),
+    }
+}
+
+fn is_bytecode_file(file : &str) -> bool {
+    match Path::new(file).extension() {
+        Some(ext) => ext == BYTECODE_EXTENSION,
+        None => false,
+    }
+}
+
+fn compile(context : &Context, file : &str, output : Option<String>) -> i32 {
+    let output = match output {
+        Some(o) => o,
+        None => Path::new(file).with_extension(BYTECODE_EXTENSION).to_string_lossy().into_owned(),
+    };
+
+    if output == file {
+        eprintln!("Erro : O bytecode sobrescreveria o próprio arquivo. Use -s pra escolher outro nome.");
+        return EXIT_USAGE_ERROR;
+    }
+
+    match context.save_bytecode_file(output.as_str()) {
+        Ok(_) => EXIT_SUCCESS,
+        Err(e) => {
+            eprintln!("Erro salvando o bytecode : {}", e);
+            EXIT_LOAD_ERROR
+        }
+    }
+}
+
+fn convert_argument(arg : String, kind : TypeKind) -> Result<RawValue, String> {
+    match kind {
+        TypeKind::Text => Ok(RawValue::Text(arg)),
+        TypeKind::Integer => {
+            match arg.parse::<IntegerType>() {
+                Ok(i) => Ok(RawValue::Integer(i)),
+                Err(_) => Err(format!("Não foi possível converter \"{}\" pra Int", arg)),
+            }
+        }
+        TypeKind::Number => {
+            match arg.parse::<f64>() {
+                Ok(n) => Ok(RawValue::Number(n)),
+                Err(_) => Err(format!("Não foi possível converter \"{}\" pra Num", arg)),
+            }
+        }
+        TypeKind::Boolean => {
+            match arg.as_str() {
+                "VERDADE" => Ok(RawValue::Boolean(true)),
+                "MENTIRA" => Ok(RawValue::Boolean(false)),
+                _ => Err(format!("Não foi possível converter \"{}\" pra BIRLEANO. Use VERDADE ou MENTIRA", arg)),
+            }
+        }
+        TypeKind::List => Err(String::from("Não é possível passar uma MARMITA pela linha de comando")),
+    }
+}
+
+// Runs the file with the given input and output and returns the exit code
+fn run(options : Options, stdin : Box<dyn BufRead>, stdout : Box<dyn Write>) -> i32 {
+    let file = match options.file {
+        Some(f) => f,
+        None => {
+            eprintln!("Erro : Nenhum arquivo foi passado. Use -a pra ver as opções.");
+            return EXIT_USAGE_ERROR;
+        }
+    };
+
+    let mut context = Context::new();
+
+    context.set_stdin(Some(stdin));
+    context.set_stdout(Some(stdout));
+    context.set_limits(options.limits);
+
+    let loaded = if is_bytecode_file(file.as_str()) {
+        if options.check_types {
+            eprintln!("Erro : A opção -t precisa do código fonte, não de bytecode");
+            return EXIT_USAGE_ERROR;
+        }
+
+        context.add_bytecode_file(file.as_str())
+    } else if options.check_types {
+        let program = match ast::parse_file(file.as_str()) {
+            Ok(p) => p,
+            Err(e) => {
+                eprintln!("Erro carregando o arquivo : {}", e);
+                return EXIT_LOAD_ERROR;
+            }
+        };
+
+        let errors = context.check_program(&program);
+
+        if !errors.is_empty() {
+            for e in &errors {
+                eprintln!("{}", e);
+            }
+
+            eprintln!("{} erros de tipo encontrados", errors.len());
+            return EXIT_LOAD_ERROR;
+        }
+
+        context.add_program(&program)
+    } else {
+        context.add_file(file.as_str())
+    };
+
+    if let Err(e) = loaded {
+        eprintln!("Erro carregando o arquivo : {}", e);
+        return EXIT_LOAD_ERROR;
+    }
+
+    if options.compile {
+        if !options.args.is_empty() || options.entry.is_some() {
+            eprintln!("Erro : A opção -c não executa o programa, então não aceita -j nem argumentos");
+            return EXIT_USAGE_ERROR;
+        }
+
+        return compile(&context, file.as_str(), options.output);
+    }
+
+    let result = match options.entry {
+        Some(entry) => {
+            let params = match context.get_function_by_name(entry.as_str()) {
+                Some(f) => f.params.clone(),
+                None => {
+                    eprintln!("Erro : A JAULA {} não foi encontrada", entry);
+                    return EXIT_USAGE_ERROR;
+                }
+            };
+
+            if params.len() != options.args.len() {
+                eprintln!("Erro : A JAULA {} espera {} argumentos, mas {} foram passados",
+                          entry, params.len(), options.args.len());
+                return EXIT_USAGE_ERROR;
+            }
+
+            let mut args = vec![];
+
+            for (arg, param) in options.args.into_iter().zip(params.iter()) {
+                match convert_argument(arg, param.kind) {
+                    Ok(v) => args.push(v),
+                    Err(e) => {
+                        eprintln!("Erro no argumento {} : {}", param.name, e);
+                        return EXIT_USAGE_ERROR;
+                    }
+                }
+            }
+
+            context.start_program_from(entry.as_str(), args)
+        }
+        None => {
+            if !options.args.is_empty() {
+                eprintln!("Erro : A JAULA {} não recebe argumentos. Use -j pra escolher outra JAULA.",
+                          BIRL_MAIN_FUNCTION);
+                return EXIT_USAGE_ERROR;
+            }
+
+            context.start_program()
+        }
+    };
+
+    // Make sure everything the program printed gets out before any error message
+    if let Some(mut out) = context.set_stdout(None) {
+        let _ = out.flush();
+    }
+
+    match result {
+        Ok(_) => EXIT_SUCCESS,
+        Err(e) => {
+            eprintln!("Erro durante a execução : {}", e);
+
+            if e.is_limit_exceeded() {
+                EXIT_LIMIT_ERROR
+            } else {
+                EXIT_RUNTIME_ERROR
+            }
+        }
+    }
+}
+
+// Does everything main does, from the arguments to the exit code, so it can be tested
+fn start(raw : Vec<String>, stdin : Box<dyn BufRead>, stdout : Box<dyn Write>) -> i32 {
+    let options = match parse_options(raw) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("Erro : {}", e);
+            return EXIT_USAGE_ERROR;
+        }
+    };
+
+    if options.show_help {
+        print_help();
+        return EXIT_SUCCESS;
+    }
+
+    if options.show_version {
+        Context::print_version();
+        return EXIT_SUCCESS;
+    }
+
+    run(options, stdin, stdout)
+}
+
+fn main() {
+    let stdin = Box::new(io::BufReader::new(io::stdin()));
+    let stdout = Box::new(io::stdout());
+
+    process::exit(start(env::args().skip(1).collect(), stdin, stdout));
+}
+
+mod tests {
+    #[test]
+    fn options() {
+        use parse_options;
+
+        fn parse(args : &[&str]) -> Result<::Options, String> {
+            parse_options(args.iter().map(|a| a.to_string()).collect())
+        }
+
+        let o = parse(&["-t", "--max-instrucoes", "10", "--max-itens", "3", "-j", "F", "a.birl", "-c", "1"]).unwrap();
+
+        assert!(o.check_types);
+        assert!(!o.compile);
+        assert_eq!(o.entry, Some("F".to_owned()));
+        assert_eq!(o.file, Some("a.birl".to_owned()));
+        // Flags after the file are arguments of the script
+        assert_eq!(o.args, vec!["-c".to_owned(), "1".to_owned()]);
+        assert_eq!(o.limits.max_instructions, Some(10));
+        assert_eq!(o.limits.max_list_items, Some(3));
+        assert_eq!(o.limits.max_call_depth, None);
+
+        let o = parse(&["-c", "-s", "b.birlc", "--", "-a.birl"]).unwrap();
+
+        assert!(o.compile);
+        assert!(!o.show_help);
+        assert_eq!(o.output, Some("b.birlc".to_owned()));
+        assert_eq!(o.file, Some("-a.birl".to_owned()));
+
+        assert!(parse(&["-a"]).unwrap().show_help);
+        assert!(parse(&["--versao"]).unwrap().show_version);
+
+        let errors : &[&[&str]] = &[
+            &["-x", "a.birl"],
+            &["-j"],
+            &["-c", "-s"],
+            &["-s", "b.birlc", "a.birl"],
+            &["--max-chamadas"],
+            &["--max-texto", "muito", "a.birl"],
+            &["--max-leituras", "-1", "a.birl"],
+        ];
+
+        for args in errors {
+            assert!(parse(args).is_err(), "{:?} deveria falhar", args);
+        }
+    }
+
+    #[test]
+    fn exit_codes() {
+        use { start, EXIT_SUCCESS, EXIT_RUNTIME_ERROR, EXIT_USAGE_ERROR, EXIT_LOAD_ERROR, EXIT_LIMIT_ERROR };
+
+        use std::cell::RefCell;
+        use std::env;
+        use std::fs;
+        use std::io::{ self, Write };
+        use std::process;
+        use std::rc::Rc;
+
+        struct Output(Rc<RefCell<Vec<u8>>>);
+
+        impl Write for Output {
+            fn write(&mut self, buf : &[u8]) -> io::Result<usize> {
+                self.0.borrow_mut().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        // Writes the source to a file of its own in the temporary directory and returns its path
+        fn source_file(name : &str, source : &str) -> String {
+            let path = env::temp_dir().join(format!("birl_{}_{}", process::id(), name));
+
+            fs::write(&path, source).unwrap();
+
+            path.to_string_lossy().into_owned()
+        }
+
+        // Returns the exit code and what the program printed
+        fn run(args : &[&str], input : &str) -> (i32, String) {
+            let output = Rc::new(RefCell::new(vec![]));
+
+            let stdin = Box::new(io::Cursor::new(input.as_bytes().to_vec()));
+            let stdout = Box::new(Output(output.clone()));
+
+            let code = start(args.iter().map(|a| a.to_string()).collect(), stdin, stdout);
+            let printed = String::from_utf8(output.borrow().clone()).unwrap();
+
+            (code, printed)
+        }
+
+        let hello = source_file("ok.birl", "JAULA SHOW\nVEM: S, \"\"\nFALA AI: S\nCE QUER VER: \"OI \", S\nSAINDO DA JAULA\n\
+                                            JAULA DOBRA(N : BATATA DOCE)\nCE QUER VER: N * 2\nSAINDO DA JAULA");
+        let broken = source_file("syntax.birl", "JAULA SHOW\nCE QUER VER: (1 +\nSAINDO DA JAULA");
+        let typed = source_file("types.birl", "JAULA SHOW\nSAINDO DA JAULA\n\
+                                               JAULA F\nVEM: T, \"A\"\nCE QUER VER: T - 1\nSAINDO DA JAULA");
+        let failing = source_file("runtime.birl", "JAULA SHOW\nCE QUER VER: \"A\"\nCE QUER VER: 1 / 0\nSAINDO DA JAULA");
+        let endless = source_file("endless.birl", "JAULA SHOW\nENQUANTO FOR VERDADE: VERDADE\nFIM\nSAINDO DA JAULA");
+        let bytecode = env::temp_dir().join(format!("birl_{}_ok.birlc", process::id())).to_string_lossy().into_owned();
+
+        assert_eq!(run(&[hello.as_str()], "BIRL\n"), (EXIT_SUCCESS, "OI BIRL".to_owned()));
+        assert_eq!(run(&["-t", "-j", "DOBRA", hello.as_str(), "21"], ""), (EXIT_SUCCESS, "42".to_owned()));
+        assert_eq!(run(&["-a"], "").0, EXIT_SUCCESS);
+        assert_eq!(run(&["-v"], "").0, EXIT_SUCCESS);
+
+        // Bytecode runs the same as the source it came from, but can't be checked for types
+        assert_eq!(run(&["-c", "-s", bytecode.as_str(), hello.as_str()], "").0, EXIT_SUCCESS);
+        assert_eq!(run(&[bytecode.as_str()], "BIRL\n"), (EXIT_SUCCESS, "OI BIRL".to_owned()));
+        assert_eq!(run(&["-t", bytecode.as_str()], "").0, EXIT_USAGE_ERROR);
+
+        // Bad flags, a missing file and arguments that don't fit the JAULA
+        assert_eq!(run(&["-x", hello.as_str()], "").0, EXIT_USAGE_ERROR);
+        assert_eq!(run(&["--max-texto", "muito", hello.as_str()], "").0, EXIT_USAGE_ERROR);
+        assert_eq!(run(&[], "").0, EXIT_USAGE_ERROR);
+        assert_eq!(run(&[hello.as_str(), "1"], "").0, EXIT_USAGE_ERROR);
+        assert_eq!(run(&["-j", "DOBRA", hello.as_str()], "").0, EXIT_USAGE_ERROR);
+        assert_eq!(run(&["-j", "DOBRA", hello.as_str(), "dois"], "").0, EXIT_USAGE_ERROR);
+        assert_eq!(run(&["-j", "TRIPLA", hello.as_str()], "").0, EXIT_USAGE_ERROR);
+
+        // Files that can't be loaded. Type errors are only found before running with -t
+        assert_eq!(run(&[broken.as_str()], "").0, EXIT_LOAD_ERROR);
+        assert_eq!(run(&[format!("{}.nada", hello).as_str()], "").0, EXIT_LOAD_ERROR);
+        assert_eq!(run(&["-t", typed.as_str()], "").0, EXIT_LOAD_ERROR);
+        assert_eq!(run(&[typed.as_str()], "").0, EXIT_SUCCESS);
+
+        // What was printed before the error still gets out
+        assert_eq!(run(&[failing.as_str()], ""), (EXIT_RUNTIME_ERROR, "A".to_owned()));
+
+        assert_eq!(run(&["--max-instrucoes", "1000", endless.as_str()], "").0, EXIT_LIMIT_ERROR);
+        assert_eq!(run(&["--max-leituras", "0", hello.as_str()], "BIRL\n").0, EXIT_LIMIT_ERROR);
+
+        for file in &[hello, broken, typed, failing, endless, bytecode] {
+            let _ = fs::remove_file(file);
+        }
+    }
+}
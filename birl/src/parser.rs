@@ -6,6 +6,10 @@ pub type IntegerType = i64;
 #[cfg(target_pointer_width = "32")]
 pub type IntegerType = i32;
 
+use error::{ Error, LexError, ParseError };
+
+use std::fmt::{ Display, self };
+
 const COMMENT_CHARACTER : char = '#';
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -27,6 +31,19 @@ pub enum KeyPhrase {
     ExecuteIfLess,
     ExecuteIfEqualOrGreater,
     ExecuteIfGreater,
+    ExecuteIfTrue,
+    Else,
+    ExecuteWhileEqual,
+    ExecuteWhileNotEqual,
+    ExecuteWhileEqualOrLess,
+    ExecuteWhileLess,
+    ExecuteWhileEqualOrGreater,
+    ExecuteWhileGreater,
+    ExecuteWhileTrue,
+    Repeat,
+    ListPush,
+    ListPop,
+    ListLength,
     Call,
     GetStringInput,
     GetNumberInput,
@@ -37,45 +54,89 @@ pub enum KeyPhrase {
     TypeInt,
     TypeNum,
     TypeStr,
+    TypeList,
+    TypeBoolean,
 }
 
+/// Every key phrase and what it means. Accented and unaccented spellings are both accepted
+const KEY_PHRASES : &[(&str, KeyPhrase)] = &[
+    ("JAULA",                    KeyPhrase::FunctionStart),
+    ("SAINDO DA JAULA",          KeyPhrase::FunctionEnd),
+    ("BIRL",                     KeyPhrase::Return),
+    ("NUM VAI DA NAO",           KeyPhrase::Quit),
+    ("NUM VAI DÁ NAO",           KeyPhrase::Quit),
+    ("NUM VAI DA NÃO",           KeyPhrase::Quit),
+    ("NUM VAI DÁ NÃO",           KeyPhrase::Quit),
+    ("CE QUER VER",              KeyPhrase::Print),
+    ("CÊ QUER VER",              KeyPhrase::Print),
+    ("CE QUER VER ISSO",         KeyPhrase::PrintLn),
+    ("CÊ QUER VER ISSO",         KeyPhrase::PrintLn),
+    ("VEM",                      KeyPhrase::Declare),
+    ("BORA",                     KeyPhrase::Set),
+    ("TRAPÉZIO DESCENDENTE",     KeyPhrase::TypeNum),
+    ("TRAPEZIO DESCENDENTE",     KeyPhrase::TypeNum),
+    ("FIBRA",                    KeyPhrase::TypeStr),
+    ("BATATA DOCE",              KeyPhrase::TypeInt),
+    ("MARMITA",                  KeyPhrase::TypeList),
+    ("BIRLEANO",                 KeyPhrase::TypeBoolean),
+    ("BOTA NA MARMITA",          KeyPhrase::ListPush),
+    ("TIRA DA MARMITA",          KeyPhrase::ListPop),
+    ("TAMANHO DA MARMITA",       KeyPhrase::ListLength),
+    ("E ELE QUE A GENTE QUER",   KeyPhrase::Compare),
+    ("É ELE QUE A GENTE QUER",   KeyPhrase::Compare),
+    ("FIM",                      KeyPhrase::EndExecuteIf),
+    ("E HORA DO",                KeyPhrase::Call),
+    ("É HORA DO",                KeyPhrase::Call),
+    ("E ELE MEMO",               KeyPhrase::ExecuteIfEqual),
+    ("É ELE MEMO",               KeyPhrase::ExecuteIfEqual),
+    ("NUM E ELE",                KeyPhrase::ExecuteIfNotEqual),
+    ("NUM É ELE",                KeyPhrase::ExecuteIfNotEqual),
+    ("E MAIOR",                  KeyPhrase::ExecuteIfGreater),
+    ("É MAIOR",                  KeyPhrase::ExecuteIfGreater),
+    ("É MENOR",                  KeyPhrase::ExecuteIfLess),
+    ("E MENOR",                  KeyPhrase::ExecuteIfLess),
+    ("MENOR OU E MEMO",          KeyPhrase::ExecuteIfEqualOrLess),
+    ("MENOR OU É MEMO",          KeyPhrase::ExecuteIfEqualOrLess),
+    ("MAIOR OU E MEMO",          KeyPhrase::ExecuteIfEqualOrGreater),
+    ("MAIOR OU É MEMO",          KeyPhrase::ExecuteIfEqualOrGreater),
+    ("E VERDADE",                KeyPhrase::ExecuteIfTrue),
+    ("É VERDADE",                KeyPhrase::ExecuteIfTrue),
+    ("SENAO",                    KeyPhrase::Else),
+    ("SENÃO",                    KeyPhrase::Else),
+    ("ENQUANTO E ELE MEMO",      KeyPhrase::ExecuteWhileEqual),
+    ("ENQUANTO É ELE MEMO",      KeyPhrase::ExecuteWhileEqual),
+    ("ENQUANTO NUM E ELE",       KeyPhrase::ExecuteWhileNotEqual),
+    ("ENQUANTO NUM É ELE",       KeyPhrase::ExecuteWhileNotEqual),
+    ("ENQUANTO E MAIOR",         KeyPhrase::ExecuteWhileGreater),
+    ("ENQUANTO É MAIOR",         KeyPhrase::ExecuteWhileGreater),
+    ("ENQUANTO E MENOR",         KeyPhrase::ExecuteWhileLess),
+    ("ENQUANTO É MENOR",         KeyPhrase::ExecuteWhileLess),
+    ("ENQUANTO MENOR OU E MEMO", KeyPhrase::ExecuteWhileEqualOrLess),
+    ("ENQUANTO MENOR OU É MEMO", KeyPhrase::ExecuteWhileEqualOrLess),
+    ("ENQUANTO MAIOR OU E MEMO", KeyPhrase::ExecuteWhileEqualOrGreater),
+    ("ENQUANTO MAIOR OU É MEMO", KeyPhrase::ExecuteWhileEqualOrGreater),
+    ("ENQUANTO FOR VERDADE",     KeyPhrase::ExecuteWhileTrue),
+    ("REPETE",                   KeyPhrase::Repeat),
+    ("FALA AI",                  KeyPhrase::GetStringInput),
+    ("FALA AÍ",                  KeyPhrase::GetStringInput),
+    ("FALA UM NÚMERO",           KeyPhrase::GetNumberInput),
+    ("FALA UM NUMERO",           KeyPhrase::GetNumberInput),
+    ("FALA AI UM INTEIRO",       KeyPhrase::GetIntegerInput),
+    ("FALA AÍ UM INTEIRO",       KeyPhrase::GetIntegerInput),
+    ("MUDA PRA TEXTO",           KeyPhrase::IntoString),
+    ("MUDA PRA NUMERO",          KeyPhrase::ConverToNum),
+    ("MUDA PRA NÚMERO",          KeyPhrase::ConverToNum),
+    ("MUDA PRA INTEIRO",         KeyPhrase::ConvertToInt),
+];
+
 impl KeyPhrase {
     pub fn matches(src : &str) -> Option<KeyPhrase> {
-        match src {
-            "JAULA" => Some(KeyPhrase::FunctionStart),
-            "SAINDO DA JAULA" => Some(KeyPhrase::FunctionEnd),
-            "BIRL" => Some(KeyPhrase::Return),
-            "NUM VAI DA NAO" |
-            "NUM VAI DÁ NAO" |
-            "NUM VAI DA NÃO" |
-            "NUM VAI DÁ NÃO" => Some(KeyPhrase::Quit),
-            "CE QUER VER" |
-            "CÊ QUER VER" => Some(KeyPhrase::Print),
-            "CE QUER VER ISSO" |
-            "CÊ QUER VER ISSO" => Some(KeyPhrase::PrintLn),
-            "VEM" => Some(KeyPhrase::Declare),
-            "BORA" => Some(KeyPhrase::Set),
-            "TRAPÉZIO DESCENDENTE" | "TRAPEZIO DESCENDENTE" => Some(KeyPhrase::TypeNum),
-            "FIBRA" => Some(KeyPhrase::TypeStr),
-            "BATATA DOCE" => Some(KeyPhrase::TypeInt),
-            "E ELE QUE A GENTE QUER" |
-            "É ELE QUE A GENTE QUER" => Some(KeyPhrase::Compare),
-            "FIM" => Some(KeyPhrase::EndExecuteIf),
-            "E HORA DO" | "É HORA DO" => Some(KeyPhrase::Call),
-            "E ELE MEMO" | "É ELE MEMO" => Some(KeyPhrase::ExecuteIfEqual),
-            "NUM E ELE" | "NUM É ELE" => Some(KeyPhrase::ExecuteIfNotEqual),
-            "E MAIOR" | "É MAIOR" => Some(KeyPhrase::ExecuteIfGreater),
-            "É MENOR" | "E MENOR" => Some(KeyPhrase::ExecuteIfLess),
-            "MENOR OU E MEMO" | "MENOR OU É MEMO" => Some(KeyPhrase::ExecuteIfEqualOrLess),
-            "MAIOR OU E MEMO" | "MAIOR OU É MEMO" => Some(KeyPhrase::ExecuteIfEqualOrGreater),
-            "FALA AI" | "FALA AÍ" => Some(KeyPhrase::GetStringInput),
-            "FALA UM NÚMERO" | "FALA UM NUMERO" => Some(KeyPhrase::GetNumberInput),
-            "FALA AI UM INTEIRO" | "FALA AÍ UM INTEIRO" => Some(KeyPhrase::GetIntegerInput),
-            "MUDA PRA TEXTO" => Some(KeyPhrase::IntoString),
-            "MUDA PRA NUMERO" | "MUDA PRA NÚMERO" => Some(KeyPhrase::ConverToNum),
-            "MUDA PRA INTEIRO" => Some(KeyPhrase::ConvertToInt),
-            _ => None,
-        }
+        KEY_PHRASES.iter().find(|&&(phrase, _)| phrase == src).map(|&(_, kp)| kp)
+    }
+
+    /// All the spellings `matches` accepts
+    pub fn phrases() -> Vec<&'static str> {
+        KEY_PHRASES.iter().map(|&(phrase, _)| phrase).collect()
     }
 }
 
@@ -85,14 +146,30 @@ pub enum MathOperator {
     Minus,
     Division,
     Multiplication,
+    Modulo,
+    Power,
+    // Unary minus. The lexer only gives Minus, the parser turns it into this when there's no value
+    // before it
+    Negate,
     ParenthesisLeft,
     ParenthesisRight,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    And,
+    Or,
+    Not,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PonctuationKind {
     Colon,
     Comma,
+    BracketLeft,
+    BracketRight,
 }
 
 #[derive(Debug, PartialEq)]
@@ -115,16 +192,55 @@ fn get_op(c : char) -> Option<MathOperator> {
         '-' => Some(MathOperator::Minus),
         '/' => Some(MathOperator::Division),
         '*' => Some(MathOperator::Multiplication),
+        '%' => Some(MathOperator::Modulo),
+        '^' => Some(MathOperator::Power),
         '(' => Some(MathOperator::ParenthesisLeft),
         ')' => Some(MathOperator::ParenthesisRight),
         _ => None,
     }
 }
 
+// Comparision operators can have two characters, so they need a look at the next one
+fn comparision_token(input : &[char], offset : &mut usize, first : char) -> Result<Token, LexError> {
+    let followed_by_equal = *offset < input.len() && input[*offset] == '=';
+
+    let op = match first {
+        '=' if followed_by_equal => MathOperator::Equal,
+        '!' if followed_by_equal => MathOperator::NotEqual,
+        '<' if followed_by_equal => MathOperator::LessOrEqual,
+        '>' if followed_by_equal => MathOperator::GreaterOrEqual,
+        '<' => MathOperator::Less,
+        '>' => MathOperator::Greater,
+        _ => return Err(LexError::at_column(format!("Operador inválido : {}. Use == ou != pra comparar", first), token_column(input, *offset - 1))),
+    };
+
+    if followed_by_equal {
+        *offset += 1;
+    }
+
+    Ok(Token::Operator(op))
+}
+
+fn is_comparision_start(c : char) -> bool {
+    matches!(c, '=' | '!' | '<' | '>')
+}
+
+// Words that work as logical operators inside expressions
+fn get_logical_op(word : &str) -> Option<MathOperator> {
+    match word {
+        "E" => Some(MathOperator::And),
+        "OU" => Some(MathOperator::Or),
+        "NAO" | "NÃO" => Some(MathOperator::Not),
+        _ => None,
+    }
+}
+
 fn get_ponct(c : char) -> Option<PonctuationKind> {
     match c {
         ':' => Some(PonctuationKind::Colon),
         ',' => Some(PonctuationKind::Comma),
+        '[' => Some(PonctuationKind::BracketLeft),
+        ']' => Some(PonctuationKind::BracketRight),
         _ => None,
     }
 }
@@ -141,7 +257,7 @@ fn get_digit(c : char) -> Option<u8> {
     }
 }
 
-fn number_token(input : &[char], offset : &mut usize, first : char) -> Result<Token, String> {
+fn number_token(input : &[char], offset : &mut usize, first : char) -> Result<Token, LexError> {
 
     let mut is_int = true;
     let mut int_val = 0 as IntegerType;
@@ -154,7 +270,7 @@ fn number_token(input : &[char], offset : &mut usize, first : char) -> Result<To
     } else {
         int_val = match get_digit(first) {
             Some(d) => d as IntegerType,
-            None => return Err("Internal error : First char to number_token is not a digit or a dot".to_owned()),
+            None => return Err(LexError::at_column("Internal error : First char to number_token is not a digit or a dot", token_column(input, *offset - 1))),
         };
     }
 
@@ -171,7 +287,7 @@ fn number_token(input : &[char], offset : &mut usize, first : char) -> Result<To
 
         if cur == '.' {
             if !is_int {
-                return Err(String::from("Dois pontos aparecem no literal de número"));
+                return Err(LexError::at_column("Dois pontos aparecem no literal de número", token_column(input, *offset)));
             } else {
                 is_int = false;
                 num_val = int_val as f64;
@@ -205,7 +321,7 @@ fn number_token(input : &[char], offset : &mut usize, first : char) -> Result<To
     }
 }
 
-fn text_token(input : &[char], offset : &mut usize) -> Result<Token, String> {
+fn text_token(input : &[char], offset : &mut usize) -> Result<Token, LexError> {
     let mut content = String::new();
 
     let mut last_was_escape = false;
@@ -241,7 +357,7 @@ fn text_token(input : &[char], offset : &mut usize) -> Result<Token, String> {
     Ok(Token::Text(content))
 }
 
-fn symbol_token(input : &[char], offset : &mut usize, first : char) -> Result<Token, String> {
+fn symbol_token(input : &[char], offset : &mut usize, first : char) -> Result<Token, LexError> {
     let mut result = String::new();
 
     result.push(first);
@@ -279,6 +395,10 @@ fn symbol_token(input : &[char], offset : &mut usize, first : char) -> Result<To
                 break;
             }
 
+            if is_comparision_start(cur) {
+                break;
+            }
+
             if let Some(_) = get_digit(cur) {
                 if first_char {
                     break;
@@ -312,17 +432,13 @@ fn symbol_token(input : &[char], offset : &mut usize, first : char) -> Result<To
     }
 }
 
-pub fn next_token(input : &[char], offset : &mut usize) -> Result<Token, String> {
-    if *offset >= input.len() {
-        return Ok(Token::None);
+pub fn next_token(input : &[char], offset : &mut usize) -> Result<Token, LexError> {
+    while *offset < input.len() && (input[*offset] == ' ' || input[*offset] == '\t') {
+        *offset += 1;
     }
 
-    loop {
-        if input[*offset] != ' ' && input[*offset] != '\t' {
-            break;
-        }
-
-        *offset += 1;
+    if *offset >= input.len() {
+        return Ok(Token::None);
     }
 
     let first_char = input[*offset];
@@ -340,6 +456,10 @@ pub fn next_token(input : &[char], offset : &mut usize) -> Result<Token, String>
         return Ok(Token::Operator(op));
     }
 
+    if is_comparision_start(first_char) {
+        return comparision_token(input, offset, first_char);
+    }
+
     if let Some(p) = get_ponct(first_char) {
         return Ok(Token::Ponctuation(p));
     }
@@ -359,11 +479,24 @@ pub fn next_token(input : &[char], offset : &mut usize) -> Result<Token, String>
     symbol_token(input, offset, first_char)
 }
 
+// Column, starting at 1, of the next token at the offset
+fn token_column(src : &[char], offset : usize) -> usize {
+    let mut column = offset;
+
+    while column < src.len() && (src[column] == ' ' || src[column] == '\t') {
+        column += 1;
+    }
+
+    column + 1
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TypeKind {
     Integer,
     Number,
     Text,
+    List,
+    Boolean,
 }
 
 impl TypeKind {
@@ -372,11 +505,25 @@ impl TypeKind {
             KeyPhrase::TypeInt => Some(TypeKind::Integer),
             KeyPhrase::TypeNum => Some(TypeKind::Number),
             KeyPhrase::TypeStr => Some(TypeKind::Text),
+            KeyPhrase::TypeList => Some(TypeKind::List),
+            KeyPhrase::TypeBoolean => Some(TypeKind::Boolean),
             _ => None,
         }
     }
 }
 
+impl Display for TypeKind {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TypeKind::Integer => write!(f, "BATATA DOCE"),
+            TypeKind::Number =>  write!(f, "TRAPEZIO DESCENDENTE"),
+            TypeKind::Text =>    write!(f, "FIBRA"),
+            TypeKind::List =>    write!(f, "MARMITA"),
+            TypeKind::Boolean => write!(f, "BIRLEANO"),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct FunctionParameter {
     pub name : String,
@@ -392,36 +539,45 @@ impl FunctionParameter {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct FunctionDeclaration {
     pub name : String,
     pub arguments : Vec<FunctionParameter>,
+    // Type of what the function returns, if it was declared
+    pub return_kind : Option<TypeKind>,
 }
 
 impl FunctionDeclaration {
     pub fn from(name : String) -> FunctionDeclaration {
         FunctionDeclaration {
             name,
-            arguments: vec![]
+            arguments: vec![],
+            return_kind: None,
         }
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum MathValue {
     Integer(IntegerType),
     Number(f64),
     Text(String),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum ExpressionNode {
     Value(MathValue),
     Symbol(String),
     Operator(MathOperator),
+    // A list literal, with an expression for each element
+    List(Vec<Expression>),
+    // Indexes the value right before it
+    Index(Expression),
+    // Calls the function with the name, with an expression for each argument
+    Call(String, Vec<Expression>),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Expression {
     pub nodes : Vec<ExpressionNode>,
     pub has_symbols : bool,
@@ -453,6 +609,19 @@ pub enum CommandKind {
     ExecuteIfLess,
     ExecuteIfEqualOrGreater,
     ExecuteIfGreater,
+    ExecuteIfTrue,
+    Else,
+    ExecuteWhileEqual,
+    ExecuteWhileNotEqual,
+    ExecuteWhileEqualOrLess,
+    ExecuteWhileLess,
+    ExecuteWhileEqualOrGreater,
+    ExecuteWhileGreater,
+    ExecuteWhileTrue,
+    Repeat,
+    ListPush,
+    ListPop,
+    ListLength,
     Call,
     GetStringInput,
     GetNumberInput,
@@ -463,6 +632,26 @@ pub enum CommandKind {
 }
 
 impl CommandKind {
+    /// Whether the command starts a conditional or a loop, which goes until a FIM
+    pub fn opens_block(&self) -> bool {
+        matches!(self,
+            CommandKind::ExecuteIfEqual |
+            CommandKind::ExecuteIfNotEqual |
+            CommandKind::ExecuteIfEqualOrLess |
+            CommandKind::ExecuteIfLess |
+            CommandKind::ExecuteIfEqualOrGreater |
+            CommandKind::ExecuteIfGreater |
+            CommandKind::ExecuteIfTrue |
+            CommandKind::ExecuteWhileEqual |
+            CommandKind::ExecuteWhileNotEqual |
+            CommandKind::ExecuteWhileEqualOrLess |
+            CommandKind::ExecuteWhileLess |
+            CommandKind::ExecuteWhileEqualOrGreater |
+            CommandKind::ExecuteWhileGreater |
+            CommandKind::ExecuteWhileTrue |
+            CommandKind::Repeat)
+    }
+
     fn from_kp(kp : KeyPhrase) -> Option<CommandKind> {
         match kp {
             KeyPhrase::Print => Some(CommandKind::Print),
@@ -480,6 +669,19 @@ impl CommandKind {
             KeyPhrase::ExecuteIfGreater => Some(CommandKind::ExecuteIfGreater),
             KeyPhrase::ExecuteIfEqualOrLess => Some(CommandKind::ExecuteIfEqualOrLess),
             KeyPhrase::ExecuteIfLess => Some(CommandKind::ExecuteIfLess),
+            KeyPhrase::ExecuteIfTrue => Some(CommandKind::ExecuteIfTrue),
+            KeyPhrase::Else => Some(CommandKind::Else),
+            KeyPhrase::ExecuteWhileEqual => Some(CommandKind::ExecuteWhileEqual),
+            KeyPhrase::ExecuteWhileNotEqual => Some(CommandKind::ExecuteWhileNotEqual),
+            KeyPhrase::ExecuteWhileEqualOrGreater => Some(CommandKind::ExecuteWhileEqualOrGreater),
+            KeyPhrase::ExecuteWhileGreater => Some(CommandKind::ExecuteWhileGreater),
+            KeyPhrase::ExecuteWhileEqualOrLess => Some(CommandKind::ExecuteWhileEqualOrLess),
+            KeyPhrase::ExecuteWhileLess => Some(CommandKind::ExecuteWhileLess),
+            KeyPhrase::ExecuteWhileTrue => Some(CommandKind::ExecuteWhileTrue),
+            KeyPhrase::Repeat => Some(CommandKind::Repeat),
+            KeyPhrase::ListPush => Some(CommandKind::ListPush),
+            KeyPhrase::ListPop => Some(CommandKind::ListPop),
+            KeyPhrase::ListLength => Some(CommandKind::ListLength),
             KeyPhrase::Call => Some(CommandKind::Call),
             KeyPhrase::GetStringInput => Some(CommandKind::GetStringInput),
             KeyPhrase::GetNumberInput => Some(CommandKind::GetNumberInput),
@@ -549,9 +751,30 @@ impl CommandInfo {
             CommandKind::ExecuteIfLess |
             CommandKind::ExecuteIfGreater |
             CommandKind::ExecuteIfEqualOrLess |
-            CommandKind::ExecuteIfEqualOrGreater => {
+            CommandKind::ExecuteIfEqualOrGreater |
+            CommandKind::Else => {
                 CommandInfo::from(0, 0, vec![])
             }
+            CommandKind::ExecuteWhileEqual |
+            CommandKind::ExecuteWhileNotEqual |
+            CommandKind::ExecuteWhileLess |
+            CommandKind::ExecuteWhileGreater |
+            CommandKind::ExecuteWhileEqualOrLess |
+            CommandKind::ExecuteWhileEqualOrGreater => {
+                CommandInfo::from(2, 2, vec![CommandArgumentKind::Expression,
+                                             CommandArgumentKind::Expression])
+            }
+            CommandKind::Repeat | CommandKind::ExecuteIfTrue | CommandKind::ExecuteWhileTrue => {
+                CommandInfo::from(1, 1, vec![CommandArgumentKind::Expression])
+            }
+            CommandKind::ListPush => {
+                CommandInfo::from(2, 2, vec![CommandArgumentKind::Expression,
+                                             CommandArgumentKind::Expression])
+            }
+            CommandKind::ListPop | CommandKind::ListLength => {
+                CommandInfo::from(2, 2, vec![CommandArgumentKind::Expression,
+                                             CommandArgumentKind::Name])
+            }
             CommandKind::GetStringInput | CommandKind::GetNumberInput | CommandKind::IntoString |
             CommandKind::ConvertToNum | CommandKind::ConvertToInt | CommandKind::GetIntegerInput => {
                 CommandInfo::from(1, 1, vec![CommandArgumentKind::Name])
@@ -560,13 +783,15 @@ impl CommandInfo {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum CommandArgument {
     Name(String),
     Expression(Expression),
+    // Type a variable was declared with, right after its name
+    Type(TypeKind),
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Command {
     pub kind : CommandKind,
     pub arguments : Vec<CommandArgument>,
@@ -580,34 +805,39 @@ pub enum ParserResult {
     Nothing,
 }
 
-fn parse_parameter(src : &[char], offset : &mut usize) -> Result<FunctionParameter, String> {
+// The type after the colon of a parameter, a variable or a function
+fn parse_type(src : &[char], offset : &mut usize, what : &str) -> Result<TypeKind, Error> {
+    match next_token(src, offset) {
+        Ok(Token::Command(kp)) => {
+            match TypeKind::from_kp(kp) {
+                Some(t) => Ok(t),
+                None => Err(ParseError::at_column(format!("Esperado um tipo pr{}, mas {:?} não existe", what, kp), token_column(src, *offset)).into()),
+            }
+        }
+        Ok(t) => Err(ParseError::at_column(format!("Esperado um tipo pr{}, encontrado {:?}", what, t), token_column(src, *offset)).into()),
+        Err(e) => Err(e.into())
+    }
+}
+
+fn parse_parameter(src : &[char], offset : &mut usize) -> Result<FunctionParameter, Error> {
     let name = match next_token(src, offset) {
         Ok(Token::Symbol(s)) => s,
-        Ok(t) => return Err(format!("Esperado um nome pro parâmetro, encontrado {:?}", t)),
-        Err(e) => return Err(e)
+        Ok(t) => return Err(ParseError::at_column(format!("Esperado um nome pro parâmetro, encontrado {:?}", t), token_column(src, *offset)).into()),
+        Err(e) => return Err(e.into())
     };
 
     match next_token(src, offset) {
         Ok(Token::Ponctuation(PonctuationKind::Colon)) => {} // OK,
-        Ok(t) => return Err(format!("Esperado um : depois do nome, encontrado {:?}", t)),
-        Err(e) => return Err(e)
+        Ok(t) => return Err(ParseError::at_column(format!("Esperado um : depois do nome, encontrado {:?}", t), token_column(src, *offset)).into()),
+        Err(e) => return Err(e.into())
     };
 
-    let kind = match next_token(src, offset) {
-        Ok(Token::Command(kp)) => {
-            match TypeKind::from_kp(kp) {
-                Some(t) => t,
-                None => return Err(format!("Esperado um tipo pro parâmetro, mas {:?} não existe", kp)),
-            }
-        }
-        Ok(t) => return Err(format!("Esperado um tipo pro parâmetro, encontrado {:?}", t)),
-        Err(e) => return Err(e)
-    };
+    let kind = parse_type(src, offset, "o parâmetro")?;
 
     Ok(FunctionParameter::from(name, kind))
 }
 
-fn parse_function(src : &[char], offset : &mut usize) -> Result<ParserResult, String> {
+fn parse_function(src : &[char], offset : &mut usize) -> Result<ParserResult, Error> {
 
     // Next token is the function name
 
@@ -615,62 +845,118 @@ fn parse_function(src : &[char], offset : &mut usize) -> Result<ParserResult, St
         Ok(t) => {
             match t {
                 Token::Symbol(name) => name,
-                _ => return Err(format!("Esperado um nome pra função, encontrado um {:?}", t))
+                _ => return Err(ParseError::at_column(format!("Esperado um nome pra função, encontrado um {:?}", t), token_column(src, *offset)).into())
             }
         }
-        Err(e) => return Err(e)
+        Err(e) => return Err(e.into())
     };
 
     let mut func = FunctionDeclaration::from(name);
 
+    let mut peek_offset = *offset;
+
+    if let Ok(Token::Operator(MathOperator::ParenthesisLeft)) = next_token(src, &mut peek_offset) {
+        *offset = peek_offset;
+
+        // Argument list. Empty parenthesis are the same as no list at all
+
+        if let Ok(Token::Operator(MathOperator::ParenthesisRight)) = next_token(src, &mut peek_offset) {
+            *offset = peek_offset;
+        } else {
+            loop {
+                if *offset >= src.len() {
+                    return Err(ParseError::at_column("A lista de argumentos acaba incompleta", token_column(src, *offset)).into());
+                }
+
+                let param = parse_parameter(src, offset)?;
+
+                func.arguments.push(param);
+
+                // Check next token
+
+                match next_token(src, offset) {
+                    Ok(Token::Ponctuation(PonctuationKind::Comma)) => {} // Ok
+                    Ok(Token::Operator(MathOperator::ParenthesisRight)) => break, // End
+                    Ok(t) => return Err(ParseError::at_column(format!("Esperado uma vírgula ou o fim da lista de parâmetros, encontrado {:?}", t), token_column(src, *offset)).into()),
+                    Err(e) => return Err(e.into()),
+                };
+            }
+        }
+    }
+
+    // What BIRL gives back, if the declaration says it
+
+    peek_offset = *offset;
+
+    if let Ok(Token::Ponctuation(PonctuationKind::Colon)) = next_token(src, &mut peek_offset) {
+        *offset = peek_offset;
+
+        func.return_kind = match parse_type(src, offset, "o retorno") {
+            Ok(k) => Some(k),
+            Err(e) => return Err(e)
+        };
+    }
+
     match next_token(src, offset) {
-        Ok(t) => {
-           match t {
-               Token::NewLine | Token::None => {}
-               Token::Operator(MathOperator::ParenthesisLeft) => {
-                   // Argument list
-
-                   loop {
-                       if *offset >= src.len() {
-                           return Err("A lista de argumentos acaba incompleta".to_owned());
-                       }
-
-                       let param = match parse_parameter(src, offset) {
-                           Ok(p) => p,
-                           Err(e) => return Err(e)
-                       };
-
-                       func.arguments.push(param);
-
-                       // Check next token
-
-                       match next_token(src, offset) {
-                           Ok(Token::Ponctuation(PonctuationKind::Comma)) => {} // Ok
-                           Ok(Token::Operator(MathOperator::ParenthesisRight)) => break, // End
-                           Ok(t) => return Err(format!("Esperado uma vírgula ou o fim da lista de parâmetros, encontrado {:?}", t)),
-                           Err(e) => return Err(e),
-                       };
-                   }
-               }
-               _ => return Err(format!("Esperado o fim da declaração ou uma lista de parâmetros, encontrado {:?}", t)),
-           }
+        Ok(Token::NewLine) | Ok(Token::None) | Ok(Token::Comment) => Ok(ParserResult::FunctionStart(func)),
+        Ok(t) => Err(ParseError::at_column(format!("Esperado o fim da declaração ou uma lista de parâmetros, encontrado {:?}", t), token_column(src, *offset)).into()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Inside expressions, JAULA is the constant with the name of the current function, not the start
+// of a declaration. Key phrases that start with E, like E VERDADE, are the operator followed by a
+// value there
+fn next_expression_token(src : &[char], offset : &mut usize) -> Result<Token, LexError> {
+    let start = token_column(src, *offset) - 1;
+
+    match next_token(src, offset) {
+        Ok(Token::Command(KeyPhrase::FunctionStart)) => Ok(Token::Symbol("JAULA".to_owned())),
+        Ok(Token::Command(kp)) => {
+            let read : String = src[start..*offset].iter().collect();
+
+            match read.split(' ').next().and_then(get_logical_op) {
+                Some(_) => Ok(split_logical_op(read.trim_end().to_owned(), start, offset)),
+                None => Ok(Token::Command(kp)),
+            }
+        }
+        Ok(Token::Symbol(s)) => Ok(split_logical_op(s, start, offset)),
+        other => other,
+    }
+}
+
+// The lexer joins words separated by a single space into one symbol, so "A E B" comes as a single
+// symbol. Return only the part before the first logical operator, or the operator itself, and move
+// the offset back so the rest is read by the next call
+fn split_logical_op(symbol : String, start : usize, offset : &mut usize) -> Token {
+    let words : Vec<&str> = symbol.split(' ').collect();
+
+    for (i, word) in words.iter().enumerate() {
+        if let Some(op) = get_logical_op(word) {
+            if i == 0 {
+                *offset = start + word.chars().count();
+
+                return Token::Operator(op);
+            }
+
+            let before = words[..i].join(" ");
+
+            *offset = start + before.chars().count();
+
+            return Token::Symbol(before);
         }
-        Err(e) => return Err(e),
     }
 
-    Ok(ParserResult::FunctionStart(func))
+    Token::Symbol(symbol)
 }
 
-fn parse_sub_expression(src : &[char], offset : &mut usize, expr : &mut Expression, root : bool) -> Result<(), String> {
+fn parse_sub_expression(src : &[char], offset : &mut usize, expr : &mut Expression, root : bool) -> Result<(), Error> {
 
     let mut last_was_value;
 
     let mut dummy_offset = *offset;
 
-    let first = match next_token(src, &mut dummy_offset) {
-        Ok(t) => t,
-        Err(e) => return Err(e),
-    };
+    let first = next_expression_token(src, &mut dummy_offset)?;
 
     match first {
         Token::Comment | Token::None => return Ok(()),
@@ -697,7 +983,10 @@ fn parse_sub_expression(src : &[char], offset : &mut usize, expr : &mut Expressi
                 expr.has_symbols = true;
             }
 
-            expr.nodes.push(ExpressionNode::Symbol(s));
+            match parse_symbol_or_call(s, src, &mut dummy_offset) {
+                Ok(node) => expr.nodes.push(node),
+                Err(e) => return Err(e)
+            }
         }
         Token::Operator(MathOperator::ParenthesisLeft) => {
             last_was_value = true;
@@ -718,16 +1007,22 @@ fn parse_sub_expression(src : &[char], offset : &mut usize, expr : &mut Expressi
         },
         Token::Operator(o) => {
             match o {
-                MathOperator::Plus | MathOperator::Minus => {
-                    // Add a zero before this
-                    expr.nodes.push(ExpressionNode::Value(MathValue::Integer(0)));
-                    expr.nodes.push(ExpressionNode::Operator(o));
-                },
-                _ => return Err(format!("Scope ou expressão começa com o operator unário inválido {:?}", o)),
+                // An unary plus doesn't change anything
+                MathOperator::Plus => {}
+                MathOperator::Minus => expr.nodes.push(ExpressionNode::Operator(MathOperator::Negate)),
+                MathOperator::Not => expr.nodes.push(ExpressionNode::Operator(o)),
+                _ => return Err(ParseError::at_column(format!("Scope ou expressão começa com o operator unário inválido {:?}", o), token_column(src, *offset)).into()),
             }
 
             last_was_value = false;
         }
+        Token::Ponctuation(PonctuationKind::BracketLeft) => {
+            last_was_value = true;
+
+            let elements = parse_list(src, &mut dummy_offset)?;
+
+            expr.nodes.push(ExpressionNode::List(elements));
+        }
         Token::Ponctuation(p) => {
             match p {
                 PonctuationKind::Comma if root => {
@@ -735,10 +1030,12 @@ fn parse_sub_expression(src : &[char], offset : &mut usize, expr : &mut Expressi
 
                     return Ok(());
                 }
-                _ => return Err(format!("A expressão deveria começar com um valor ou operador unário, mas começa com {:?}", p)),
+                // End of a list or index. Let the caller see it
+                PonctuationKind::BracketRight => return Ok(()),
+                _ => return Err(ParseError::at_column(format!("A expressão deveria começar com um valor ou operador unário, mas começa com {:?}", p), token_column(src, *offset)).into()),
             }
         }
-        _ => return Err(format!("Esperado um valor ou operador na expressão, encontrado {:?}", first)),
+        _ => return Err(ParseError::at_column(format!("Esperado um valor ou operador na expressão, encontrado {:?}", first), token_column(src, *offset)).into()),
     }
 
     *offset = dummy_offset;
@@ -748,16 +1045,13 @@ fn parse_sub_expression(src : &[char], offset : &mut usize, expr : &mut Expressi
             break;
         }
 
-        let current = match next_token(src, &mut dummy_offset) {
-            Ok(t) => t,
-            Err(e) => return Err(e)
-        };
+        let current = next_expression_token(src, &mut dummy_offset)?;
 
         match current {
             Token::None | Token::Comment => return Ok(()),
             Token::Integer(i) => {
                 if last_was_value {
-                    return Err("Dois valores seguidos na expressão".to_owned());
+                    return Err(ParseError::at_column("Dois valores seguidos na expressão", token_column(src, *offset)).into());
                 }
 
                 last_was_value = true;
@@ -766,7 +1060,7 @@ fn parse_sub_expression(src : &[char], offset : &mut usize, expr : &mut Expressi
             }
             Token::Number(n) => {
                 if last_was_value {
-                    return Err("Dois valores seguidos na expressão".to_owned());
+                    return Err(ParseError::at_column("Dois valores seguidos na expressão", token_column(src, *offset)).into());
                 }
 
                 last_was_value = true;
@@ -775,7 +1069,7 @@ fn parse_sub_expression(src : &[char], offset : &mut usize, expr : &mut Expressi
             }
             Token::Text(t) => {
                 if last_was_value {
-                    return Err("Dois valores seguidos na expressão".to_owned());
+                    return Err(ParseError::at_column("Dois valores seguidos na expressão", token_column(src, *offset)).into());
                 }
 
                 last_was_value = true;
@@ -784,7 +1078,7 @@ fn parse_sub_expression(src : &[char], offset : &mut usize, expr : &mut Expressi
             }
             Token::Symbol(s) => {
                 if last_was_value {
-                    return Err("Dois valores seguidos na expressão".to_owned());
+                    return Err(ParseError::at_column("Dois valores seguidos na expressão", token_column(src, *offset)).into());
                 }
 
                 last_was_value = true;
@@ -793,7 +1087,10 @@ fn parse_sub_expression(src : &[char], offset : &mut usize, expr : &mut Expressi
                     expr.has_symbols = true;
                 }
 
-                expr.nodes.push(ExpressionNode::Symbol(s));
+                match parse_symbol_or_call(s, src, &mut dummy_offset) {
+                    Ok(node) => expr.nodes.push(node),
+                    Err(e) => return Err(e)
+                }
             }
             Token::Operator(MathOperator::ParenthesisLeft) => {
                 last_was_value = true;
@@ -812,14 +1109,40 @@ fn parse_sub_expression(src : &[char], offset : &mut usize, expr : &mut Expressi
 
                 break
             },
+            Token::Operator(MathOperator::Not) => {
+                // NAO comes before a value, never after one
+                if last_was_value {
+                    return Err(ParseError::at_column("NAO depois de um valor na expressão", token_column(src, *offset)).into());
+                }
+
+                expr.nodes.push(ExpressionNode::Operator(MathOperator::Not));
+            }
             Token::Operator(o) => {
-                if !last_was_value {
-                    return Err("Dois operadores seguidos na expressão".to_owned());
+                if last_was_value {
+                    last_was_value = false;
+
+                    expr.nodes.push(ExpressionNode::Operator(o));
+                } else {
+                    // Only + and - also work as unary operators, like in 2 * -3
+                    match o {
+                        MathOperator::Plus => {}
+                        MathOperator::Minus => expr.nodes.push(ExpressionNode::Operator(MathOperator::Negate)),
+                        _ => return Err(ParseError::at_column("Dois operadores seguidos na expressão", token_column(src, *offset)).into()),
+                    }
                 }
+            }
+            Token::Ponctuation(PonctuationKind::BracketLeft) => {
+                if last_was_value {
+                    let index = parse_index(src, &mut dummy_offset)?;
+
+                    expr.nodes.push(ExpressionNode::Index(index));
+                } else {
+                    last_was_value = true;
 
-                last_was_value = false;
+                    let elements = parse_list(src, &mut dummy_offset)?;
 
-                expr.nodes.push(ExpressionNode::Operator(o));
+                    expr.nodes.push(ExpressionNode::List(elements));
+                }
             }
             Token::Ponctuation(p) => {
                 match p {
@@ -828,24 +1151,170 @@ fn parse_sub_expression(src : &[char], offset : &mut usize, expr : &mut Expressi
 
                         break;
                     }
-                    _ => return Err(format!("Erro: A expressão deveria começar com um valor ou operador unário, mas começa com {:?}", p)),
+                    // Same as above, the list or index being parsed ends here
+                    PonctuationKind::BracketRight => break,
+                    _ => return Err(ParseError::at_column(format!("Erro: A expressão deveria começar com um valor ou operador unário, mas começa com {:?}", p), token_column(src, *offset)).into()),
                 }
             }
             Token::NewLine => break,
-            _ => return Err(format!("Esperado um valor ou operador na expressão, encontrado {:?}", current)),
+            _ => return Err(ParseError::at_column(format!("Esperado um valor ou operador na expressão, encontrado {:?}", current), token_column(src, *offset)).into()),
         }
 
         *offset = dummy_offset;
     }
 
     if !last_was_value {
-        return Err("Expressão termina com um operador".to_owned());
+        return Err(ParseError::at_column("Expressão termina com um operador", token_column(src, *offset)).into());
     }
 
     Ok(())
 }
 
-fn parse_expression(src : &[char], offset : &mut usize) -> Result<Expression, String> {
+// Expects the next token to be a ], closing a list or an index
+fn expect_bracket_right(src : &[char], offset : &mut usize) -> Result<(), Error> {
+    match next_token(src, offset) {
+        Ok(Token::Ponctuation(PonctuationKind::BracketRight)) => Ok(()),
+        Ok(t) => Err(ParseError::at_column(format!("Esperado um ], encontrado {:?}", t), token_column(src, *offset)).into()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Parses the elements of a list literal, right after the [
+fn parse_list(src : &[char], offset : &mut usize) -> Result<Vec<Expression>, Error> {
+    let mut elements = vec![];
+
+    loop {
+        let mut element = Expression::new();
+
+        match parse_sub_expression(src, offset, &mut element, true) {
+            Ok(_) => {}
+            Err(e) => return Err(e)
+        }
+
+        let mut peek_offset = *offset;
+
+        match next_token(src, &mut peek_offset) {
+            Ok(Token::Ponctuation(PonctuationKind::Comma)) => {
+                if element.nodes.is_empty() {
+                    return Err(ParseError::at_column("Elemento vazio na lista", token_column(src, *offset)).into());
+                }
+
+                *offset = peek_offset;
+                elements.push(element);
+            }
+            Ok(Token::Ponctuation(PonctuationKind::BracketRight)) => {
+                *offset = peek_offset;
+
+                // [] is an empty list, but [1, ] isn't valid
+                if element.nodes.is_empty() {
+                    if !elements.is_empty() {
+                        return Err(ParseError::at_column("Elemento vazio na lista", token_column(src, *offset)).into());
+                    }
+                } else {
+                    elements.push(element);
+                }
+
+                break;
+            }
+            Ok(t) => return Err(ParseError::at_column(format!("Esperado uma vírgula ou o fim da lista, encontrado {:?}", t), token_column(src, *offset)).into()),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(elements)
+}
+
+// Parses the expression inside an index, right after the [
+fn parse_index(src : &[char], offset : &mut usize) -> Result<Expression, Error> {
+    let mut index = Expression::new();
+
+    match parse_sub_expression(src, offset, &mut index, false) {
+        Ok(_) => {}
+        Err(e) => return Err(e)
+    }
+
+    if index.nodes.is_empty() {
+        return Err(ParseError::at_column("Índice vazio", token_column(src, *offset)).into());
+    }
+
+    match expect_bracket_right(src, offset) {
+        Ok(_) => {}
+        Err(e) => return Err(e)
+    }
+
+    Ok(index)
+}
+
+// A symbol right before a ( is a function call
+fn parse_symbol_or_call(name : String, src : &[char], offset : &mut usize) -> Result<ExpressionNode, Error> {
+    let mut peek_offset = *offset;
+
+    match next_token(src, &mut peek_offset) {
+        Ok(Token::Operator(MathOperator::ParenthesisLeft)) => {
+            *offset = peek_offset;
+
+            match parse_call_arguments(src, offset) {
+                Ok(args) => Ok(ExpressionNode::Call(name, args)),
+                Err(e) => Err(e)
+            }
+        }
+        Ok(_) => Ok(ExpressionNode::Symbol(name)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Parses the arguments of a call, right after the (
+fn parse_call_arguments(src : &[char], offset : &mut usize) -> Result<Vec<Expression>, Error> {
+    let mut peek_offset = *offset;
+
+    if let Ok(Token::Operator(MathOperator::ParenthesisRight)) = next_token(src, &mut peek_offset) {
+        *offset = peek_offset;
+
+        return Ok(vec![]);
+    }
+
+    let mut args = vec![];
+
+    loop {
+        let mut arg = Expression::new();
+
+        match parse_sub_expression(src, offset, &mut arg, true) {
+            Ok(_) => {}
+            Err(e) => return Err(e)
+        }
+
+        // The ) that ends the call is read by the argument, as if it closed a parenthesis inside it.
+        // It's the one that doesn't have a ( to match
+        let opened = arg.nodes.iter().filter(|n| **n == ExpressionNode::Operator(MathOperator::ParenthesisLeft)).count();
+        let closed = arg.nodes.iter().filter(|n| **n == ExpressionNode::Operator(MathOperator::ParenthesisRight)).count();
+
+        let ends_call = closed > opened;
+
+        if ends_call {
+            arg.nodes.pop();
+        }
+
+        if arg.nodes.is_empty() {
+            return Err(ParseError::at_column("Argumento vazio na chamada de função", token_column(src, *offset)).into());
+        }
+
+        args.push(arg);
+
+        if ends_call {
+            break;
+        }
+
+        match next_token(src, offset) {
+            Ok(Token::Ponctuation(PonctuationKind::Comma)) => {}
+            Ok(t) => return Err(ParseError::at_column(format!("Esperado uma vírgula ou o fim dos argumentos da chamada, encontrado {:?}", t), token_column(src, *offset)).into()),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(args)
+}
+
+fn parse_expression(src : &[char], offset : &mut usize) -> Result<Expression, Error> {
     let mut expr = Expression::new();
 
     match parse_sub_expression(src, offset, &mut expr, true) {
@@ -856,11 +1325,11 @@ fn parse_expression(src : &[char], offset : &mut usize) -> Result<Expression, St
     Ok(expr)
 }
 
-fn parse_command(src : &[char], offset : &mut usize, kp : KeyPhrase) -> Result<ParserResult, String> {
+fn parse_command(src : &[char], offset : &mut usize, kp : KeyPhrase) -> Result<ParserResult, Error> {
     let cmd_kind = match CommandKind::from_kp(kp) {
         Some(k) => k,
         // I don't think this will ever happen, so leave this awful message
-        None => return Err("Invalid KeyPhrase to command".to_owned()),
+        None => return Err(ParseError::at_column("Invalid KeyPhrase to command", token_column(src, *offset)).into()),
     };
 
     let mut cmd = Command {
@@ -881,7 +1350,7 @@ fn parse_command(src : &[char], offset : &mut usize, kp : KeyPhrase) -> Result<P
                     _ => false,
                 }
             }
-            Err(e) => return Err(e),
+            Err(e) => return Err(e.into()),
         }
     };
 
@@ -890,7 +1359,7 @@ fn parse_command(src : &[char], offset : &mut usize, kp : KeyPhrase) -> Result<P
     match next_token(src, &mut dummy_offset) {
         Ok(Token::NewLine) | Ok(Token::None) => has_arguments = false,
         Ok(_) => {},
-        Err(e) => return Err(e)
+        Err(e) => return Err(e.into())
     }
 
     if has_arguments {
@@ -906,7 +1375,7 @@ fn parse_command(src : &[char], offset : &mut usize, kp : KeyPhrase) -> Result<P
                 if info.max_args < 0 {
                     arg_index = info.expected_args.len() - 1;
                 } else {
-                    return Err(format!("O comando espera, no máximo, apenas {} argumentos, mas mais que isso foi passado", info.max_args));
+                    return Err(ParseError::at_column(format!("O comando espera, no máximo, apenas {} argumentos, mas mais que isso foi passado", info.max_args), token_column(src, *offset)).into());
                 }
             }
 
@@ -918,10 +1387,24 @@ fn parse_command(src : &[char], offset : &mut usize, kp : KeyPhrase) -> Result<P
                         Ok(t) => {
                             match t {
                                 Token::Symbol(s) => cmd.arguments.push(CommandArgument::Name(s)),
-                                _ => return Err(format!("O argumento espera que o argumento #{} seja um nome, mas {:?} foi encontrado", arg_count, t)),
+                                _ => return Err(ParseError::at_column(format!("O argumento espera que o argumento #{} seja um nome, mas {:?} foi encontrado", arg_count, t), token_column(src, *offset)).into()),
+                            }
+                        }
+                        Err(e) => return Err(e.into()),
+                    }
+
+                    // VEM can say the type the variable will always have
+                    let mut peek_offset = *offset;
+
+                    if cmd_kind == CommandKind::Declare {
+                        if let Ok(Token::Ponctuation(PonctuationKind::Colon)) = next_token(src, &mut peek_offset) {
+                            *offset = peek_offset;
+
+                            match parse_type(src, offset, "a variável") {
+                                Ok(k) => cmd.arguments.push(CommandArgument::Type(k)),
+                                Err(e) => return Err(e)
                             }
                         }
-                        Err(e) => return Err(e),
                     }
                 }
                 CommandArgumentKind::Expression => {
@@ -941,19 +1424,19 @@ fn parse_command(src : &[char], offset : &mut usize, kp : KeyPhrase) -> Result<P
             match next_token(src, &mut peek_offset) {
                 Ok(t) => {
                     match t {
-                        Token::None | Token::NewLine => break,
+                        Token::None | Token::NewLine | Token::Comment => break,
                         Token::Ponctuation(p) => {
                             match p {
                                 PonctuationKind::Comma => {
                                     *offset = peek_offset;
                                 } // OK
-                                _ => return Err(format!("Esperado uma vírgula ou o fim dos argumentos, mas foi encontrado {:?}", p)),
+                                _ => return Err(ParseError::at_column(format!("Esperado uma vírgula ou o fim dos argumentos, mas foi encontrado {:?}", p), token_column(src, *offset)).into()),
                             }
                         }
-                        _ => return Err(format!("Esperado uma vírgula ou o fim dos argumentos, mas foi encontrado {:?}", t)),
+                        _ => return Err(ParseError::at_column(format!("Esperado uma vírgula ou o fim dos argumentos, mas foi encontrado {:?}", t), token_column(src, *offset)).into()),
                     }
                 }
-                Err(e) => return Err(e),
+                Err(e) => return Err(e.into()),
             }
 
             arg_count += 1;
@@ -962,13 +1445,13 @@ fn parse_command(src : &[char], offset : &mut usize, kp : KeyPhrase) -> Result<P
     }
 
     if cmd.arguments.len() < info.min_args as usize {
-        return Err(format!("O comando espera ao menos {} argumentos, mas {} foram passados", info.min_args, cmd.arguments.len()));
+        return Err(ParseError::at_column(format!("O comando espera ao menos {} argumentos, mas {} foram passados", info.min_args, cmd.arguments.len()), token_column(src, *offset)).into());
     }
 
     Ok(ParserResult::Command(cmd))
 }
 
-pub fn parse_line(src : &str) -> Result<ParserResult, String> {
+pub fn parse_line(src : &str) -> Result<ParserResult, Error> {
     if src.trim().is_empty() {
         return Ok(ParserResult::Nothing);
     }
@@ -981,7 +1464,7 @@ pub fn parse_line(src : &str) -> Result<ParserResult, String> {
 
     let first = match next_token(&chars, &mut offset) {
         Ok(t) => t,
-        Err(e) => return Err(e),
+        Err(e) => return Err(e.into()),
     };
 
     match first {
@@ -997,7 +1480,7 @@ pub fn parse_line(src : &str) -> Result<ParserResult, String> {
             offset = 0;
             parse_command(&chars, &mut offset, KeyPhrase::PrintDebug)
         }
-        _ => Err("Linha começa com um token inválido".to_owned()),
+        _ => Err(ParseError::at_column("Linha começa com um token inválido", token_column(&chars, 0)).into()),
     }
 }
 
@@ -1020,6 +1503,20 @@ mod tests {
             assert_eq!(got_func, expected);
         }
 
+        {
+            let src = "JAULA SHOW() # Sem parâmetros";
+
+            let got_func = match parse_line(src) {
+                Ok(ParserResult::FunctionStart(func)) => func,
+                Ok(res) => panic!("Era esperado uma função, recebido {:?}", res),
+                Err(e) => panic!("{}", e)
+            };
+
+            let expected = FunctionDeclaration::from("SHOW".to_owned());
+
+            assert_eq!(got_func, expected);
+        }
+
         {
             let src = "JAULA F(ARG1 : FIBRA, ARG2 : TRAPÉZIO DESCENDENTE)";
 
@@ -1035,6 +1532,33 @@ mod tests {
 
             assert_eq!(got_func, expected);
         }
+
+        {
+            let src = "JAULA G(L : MARMITA) : BATATA DOCE";
+
+            let got_func = match parse_line(src) {
+                Ok(ParserResult::FunctionStart(func)) => func,
+                Ok(res) => panic!("Era esperado uma função, recebido {:?}", res),
+                Err(e) => panic!("{}", e)
+            };
+
+            let mut expected = FunctionDeclaration::from("G".to_owned());
+            expected.arguments.push(FunctionParameter::from("L".to_owned(), TypeKind::List));
+            expected.return_kind = Some(TypeKind::Integer);
+
+            assert_eq!(got_func, expected);
+        }
+
+        assert!(parse_line("JAULA G : ").is_err());
+        assert!(parse_line("JAULA G : X").is_err());
+
+        match parse_line("VEM: X : FIBRA, \"A\"") {
+            Ok(ParserResult::Command(cmd)) => {
+                assert_eq!(cmd.kind, CommandKind::Declare);
+                assert_eq!(cmd.arguments[1], CommandArgument::Type(TypeKind::Text));
+            }
+            res => panic!("Era esperado um VEM, recebido {:?}", res),
+        }
     }
 
     #[test]
@@ -1128,4 +1652,223 @@ mod tests {
             assert_eq!(tok, expected);
         }
     }
+
+    #[test]
+    fn loops() {
+        use parser::*;
+
+        {
+            let src = "ENQUANTO MENOR OU É MEMO: CONTADOR, 10";
+
+            let cmd = match parse_line(src) {
+                Ok(ParserResult::Command(cmd)) => cmd,
+                Ok(res) => panic!("Era esperado um comando, recebido {:?}", res),
+                Err(e) => panic!("{}", e)
+            };
+
+            assert_eq!(cmd.kind, CommandKind::ExecuteWhileEqualOrLess);
+            assert_eq!(cmd.arguments.len(), 2);
+        }
+
+        {
+            let src = "REPETE: 3";
+
+            let cmd = match parse_line(src) {
+                Ok(ParserResult::Command(cmd)) => cmd,
+                Ok(res) => panic!("Era esperado um comando, recebido {:?}", res),
+                Err(e) => panic!("{}", e)
+            };
+
+            assert_eq!(cmd.kind, CommandKind::Repeat);
+            assert_eq!(cmd.arguments.len(), 1);
+        }
+    }
+
+    #[test]
+    fn else_blocks() {
+        use parser::*;
+
+        for src in &["SENÃO", "SENAO:"] {
+            let cmd = match parse_line(src) {
+                Ok(ParserResult::Command(cmd)) => cmd,
+                Ok(res) => panic!("Era esperado um comando, recebido {:?}", res),
+                Err(e) => panic!("{}", e)
+            };
+
+            assert_eq!(cmd.kind, CommandKind::Else);
+            assert!(cmd.arguments.is_empty());
+        }
+    }
+
+    #[test]
+    fn error_spans() {
+        use parser::*;
+        use error::Error;
+
+        match parse_line("BORA: X, 1.2.3") {
+            Err(Error::Lex(e)) => assert_eq!(e.span.column, Some(13)),
+            res => panic!("Era esperado um erro léxico, recebido {:?}", res),
+        }
+
+        // Columns count characters, not bytes
+        match parse_line("CE QUER VER: \"ÇÃO\" = 1") {
+            Err(Error::Lex(e)) => assert_eq!(e.span.column, Some(20)),
+            res => panic!("Era esperado um erro léxico, recebido {:?}", res),
+        }
+
+        match parse_line("    CE QUER VER ISSO: 1 +") {
+            Err(Error::Parse(e)) => assert_eq!(e.span.column, Some(26)),
+            res => panic!("Era esperado um erro de sintaxe, recebido {:?}", res),
+        }
+    }
+
+    #[test]
+    fn lists() {
+        use parser::*;
+
+        {
+            let src = "BOTA NA MARMITA: L[0], [1, \"A\", []]";
+
+            let cmd = match parse_line(src) {
+                Ok(ParserResult::Command(cmd)) => cmd,
+                Ok(res) => panic!("Era esperado um comando, recebido {:?}", res),
+                Err(e) => panic!("{}", e)
+            };
+
+            assert_eq!(cmd.kind, CommandKind::ListPush);
+            assert_eq!(cmd.arguments.len(), 2);
+
+            match cmd.arguments[0] {
+                CommandArgument::Expression(ref expr) => {
+                    assert_eq!(expr.nodes.len(), 2);
+                    assert_eq!(expr.nodes[0], ExpressionNode::Symbol("L".to_owned()));
+
+                    match expr.nodes[1] {
+                        ExpressionNode::Index(ref index) => {
+                            assert_eq!(index.nodes, vec![ExpressionNode::Value(MathValue::Integer(0))]);
+                        }
+                        ref node => panic!("Era esperado um índice, recebido {:?}", node),
+                    }
+                }
+                ref arg => panic!("Era esperada uma expressão, recebido {:?}", arg),
+            }
+
+            match cmd.arguments[1] {
+                CommandArgument::Expression(ref expr) => {
+                    match expr.nodes[..] {
+                        [ExpressionNode::List(ref elements)] => assert_eq!(elements.len(), 3),
+                        ref nodes => panic!("Era esperada uma lista, recebido {:?}", nodes),
+                    }
+                }
+                ref arg => panic!("Era esperada uma expressão, recebido {:?}", arg),
+            }
+        }
+
+        assert!(parse_line("VEM: L, [1, ]").is_err(), "Uma vírgula sobrando na lista deveria ser um erro");
+    }
+
+    #[test]
+    fn logical_expressions() {
+        use parser::*;
+
+        let src = "É VERDADE: NAO A E B OU C >= 1";
+
+        let cmd = match parse_line(src) {
+            Ok(ParserResult::Command(cmd)) => cmd,
+            Ok(res) => panic!("Era esperado um comando, recebido {:?}", res),
+            Err(e) => panic!("{}", e)
+        };
+
+        assert_eq!(cmd.kind, CommandKind::ExecuteIfTrue);
+
+        let expected = vec![
+            ExpressionNode::Operator(MathOperator::Not),
+            ExpressionNode::Symbol("A".to_owned()),
+            ExpressionNode::Operator(MathOperator::And),
+            ExpressionNode::Symbol("B".to_owned()),
+            ExpressionNode::Operator(MathOperator::Or),
+            ExpressionNode::Symbol("C".to_owned()),
+            ExpressionNode::Operator(MathOperator::GreaterOrEqual),
+            ExpressionNode::Value(MathValue::Integer(1)),
+        ];
+
+        match cmd.arguments[0] {
+            CommandArgument::Expression(ref expr) => assert_eq!(expr.nodes, expected),
+            ref arg => panic!("Era esperada uma expressão, recebido {:?}", arg),
+        }
+
+        assert!(parse_line("VEM: X, A = B").is_err(), "Um = sozinho não deveria ser aceito");
+        assert!(parse_line("VEM: X, A NAO B").is_err(), "NAO não pode vir depois de um valor");
+    }
+
+    #[test]
+    fn unary_and_power_operators() {
+        use parser::*;
+
+        let src = "CE QUER VER ISSO: -X ^ 2 % -3";
+
+        let cmd = match parse_line(src) {
+            Ok(ParserResult::Command(cmd)) => cmd,
+            Ok(res) => panic!("Era esperado um comando, recebido {:?}", res),
+            Err(e) => panic!("{}", e)
+        };
+
+        let expected = vec![
+            ExpressionNode::Operator(MathOperator::Negate),
+            ExpressionNode::Symbol("X".to_owned()),
+            ExpressionNode::Operator(MathOperator::Power),
+            ExpressionNode::Value(MathValue::Integer(2)),
+            ExpressionNode::Operator(MathOperator::Modulo),
+            ExpressionNode::Operator(MathOperator::Negate),
+            ExpressionNode::Value(MathValue::Integer(3)),
+        ];
+
+        match cmd.arguments[0] {
+            CommandArgument::Expression(ref expr) => assert_eq!(expr.nodes, expected),
+            ref arg => panic!("Era esperada uma expressão, recebido {:?}", arg),
+        }
+
+        assert!(parse_line("VEM: X, 2 * % 3").is_err(), "% não é um operador unário");
+    }
+
+    #[test]
+    fn function_calls() {
+        use parser::*;
+
+        let src = "VEM: X, SOMA((1 + 2), FATORIAL(4)) + NADA()";
+
+        let cmd = match parse_line(src) {
+            Ok(ParserResult::Command(cmd)) => cmd,
+            Ok(res) => panic!("Era esperado um comando, recebido {:?}", res),
+            Err(e) => panic!("{}", e)
+        };
+
+        let expr = match cmd.arguments[1] {
+            CommandArgument::Expression(ref expr) => expr,
+            ref arg => panic!("Era esperada uma expressão, recebido {:?}", arg),
+        };
+
+        assert_eq!(expr.nodes.len(), 3);
+        assert_eq!(expr.nodes[2], ExpressionNode::Call("NADA".to_owned(), vec![]));
+
+        match expr.nodes[0] {
+            ExpressionNode::Call(ref name, ref args) => {
+                assert_eq!(name, "SOMA");
+                assert_eq!(args.len(), 2);
+                assert_eq!(args[0].nodes.len(), 5);
+
+                match args[1].nodes[..] {
+                    [ExpressionNode::Call(ref inner, ref inner_args)] => {
+                        assert_eq!(inner, "FATORIAL");
+                        assert_eq!(inner_args.len(), 1);
+                    }
+                    ref nodes => panic!("Era esperada uma chamada, recebido {:?}", nodes),
+                }
+            }
+            ref node => panic!("Era esperada uma chamada, recebido {:?}", node),
+        }
+
+        assert!(parse_line("VEM: X, SOMA(1, )").is_err(), "Argumento vazio deveria ser um erro");
+        assert!(parse_line("VEM: X, SOMA(1, 2").is_err(), "Chamada sem ) deveria ser um erro");
+    }
 }
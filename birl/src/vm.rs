@@ -1,12 +1,14 @@
 //! The virtual machine runs code (DUH)
 
 use parser::{ TypeKind, IntegerType };
-use context::BIRL_RET_VAL_VAR_ID;
+use context::RawValue;
+use error::{ RuntimeError, Limit };
 
 use std::io::{ Write, BufRead };
 use std::fmt::{ Display, self };
 
 type StringStorageID = u64;
+type ListStorageID = u64;
 
 const MAIN_STACK_SIZE : usize = 256;
 
@@ -34,6 +36,8 @@ enum DynamicValue {
     Integer(IntegerType),
     Number(f64),
     Text(StringStorageID),
+    List(ListStorageID),
+    Boolean(bool),
     Null,
 }
 
@@ -47,6 +51,8 @@ struct StringEntry {
 struct StringStorage {
     entries : Vec<StringEntry>,
     last_id : u64,
+    // Total size of the entries, so the memory limit can be checked without going through them
+    bytes : usize,
 }
 
 impl StringStorage {
@@ -54,6 +60,7 @@ impl StringStorage {
         StringStorage {
             entries: vec![],
             last_id : 0,
+            bytes : 0,
         }
     }
 
@@ -67,14 +74,17 @@ impl StringStorage {
         None
     }
 
-    fn get_mut(&mut self, id : u64) -> Option<&mut String> {
+    fn append(&mut self, id : u64, text : &str) -> bool {
         for e in &mut self.entries {
             if e.id == id {
-                return Some(&mut e.content);
+                e.content.push_str(text);
+                self.bytes += text.len();
+
+                return true;
             }
         }
 
-        None
+        false
     }
 
     fn get(&mut self, id : u64) -> Option<String> {
@@ -83,6 +93,7 @@ impl StringStorage {
 
             if cid == id {
                 let entry = self.entries.remove(i);
+                self.bytes -= entry.content.len();
 
                 return Some(entry.content);
             }
@@ -98,6 +109,8 @@ impl StringStorage {
     fn add_string(&mut self, content : String) -> u64 {
         let id = self.last_id + 1;
 
+        self.bytes += content.len();
+
         let entry = StringEntry {
             content,
             id,
@@ -111,6 +124,118 @@ impl StringStorage {
     }
 }
 
+// Values inside a list don't live on any stack, so text is kept by value instead of by ID
+#[derive(Debug, Clone, PartialEq)]
+enum ListValue {
+    Integer(IntegerType),
+    Number(f64),
+    Text(String),
+    List(ListStorageID),
+    Boolean(bool),
+    Null,
+}
+
+impl ListValue {
+    // Bytes of text held by the value itself. Lists inside it are counted on their own entries
+    fn text_bytes(&self) -> usize {
+        match self {
+            ListValue::Text(t) => t.len(),
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ListEntry {
+    id : u64,
+    content : Vec<ListValue>,
+}
+
+// Lists are shared by every frame, so writing a list to a variable or passing it to a function
+// doesn't copy it. Since they can be shared, they're never freed, and only changed through push and
+// pop, which keep the totals up to date
+#[derive(Debug)]
+struct ListStorage {
+    entries : Vec<ListEntry>,
+    last_id : u64,
+    // Elements of every list and the size of the text in them, for the memory limits
+    items : usize,
+    bytes : usize,
+}
+
+impl ListStorage {
+    fn new() -> ListStorage {
+        ListStorage {
+            entries : vec![],
+            last_id : 0,
+            items : 0,
+            bytes : 0,
+        }
+    }
+
+    fn get_ref(&self, id : u64) -> Option<&Vec<ListValue>> {
+        for e in &self.entries {
+            if e.id == id {
+                return Some(&e.content);
+            }
+        }
+
+        None
+    }
+
+    fn get_mut(&mut self, id : u64) -> Option<&mut Vec<ListValue>> {
+        for e in &mut self.entries {
+            if e.id == id {
+                return Some(&mut e.content);
+            }
+        }
+
+        None
+    }
+
+    fn push(&mut self, id : u64, value : ListValue) -> bool {
+        let bytes = value.text_bytes();
+
+        match self.get_mut(id) {
+            Some(l) => l.push(value),
+            None => return false,
+        }
+
+        self.items += 1;
+        self.bytes += bytes;
+
+        true
+    }
+
+    // None if the list doesn't exist or is empty
+    fn pop(&mut self, id : u64) -> Option<ListValue> {
+        let value = match self.get_mut(id) {
+            Some(l) => l.pop(),
+            None => None,
+        };
+
+        if let Some(ref v) = value {
+            self.items -= 1;
+            self.bytes -= v.text_bytes();
+        }
+
+        value
+    }
+
+    fn add(&mut self, content : Vec<ListValue>) -> u64 {
+        let id = self.last_id + 1;
+
+        self.items += content.len();
+        self.bytes += content.iter().map(|v| v.text_bytes()).sum::<usize>();
+
+        self.entries.push(ListEntry { id, content });
+
+        self.last_id = id;
+
+        id
+    }
+}
+
 #[derive(Debug)]
 struct RuntimeVariable {
     id : u64,
@@ -128,6 +253,9 @@ pub struct FunctionFrame {
     string_storage : StringStorage,
     ready : bool,
     skip_level : u32,
+    // Height of the main stack when the function started. Calls can happen in the middle of an
+    // expression, so what's below it belongs to the caller
+    main_stack_base : usize,
 }
 
 impl FunctionFrame {
@@ -142,9 +270,23 @@ impl FunctionFrame {
             string_storage : StringStorage::new(),
             ready : false,
             skip_level : 0,
+            main_stack_base : 0,
         }
     }
 
+    pub fn get_id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn get_program_counter(&self) -> usize {
+        self.program_counter
+    }
+
+    /// Frames that aren't ready are calls still getting their arguments, so they haven't run yet
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
+
     fn get_address_of(&self, id : u64) -> Option<usize> {
         for v in &self.runtime_vars {
             if v.id == id {
@@ -155,7 +297,14 @@ impl FunctionFrame {
         None
     }
 
-    fn create_runtime_var(&mut self, id : u64) -> Result<(), String> {
+    fn create_runtime_var(&mut self, id : u64) -> Result<(), RuntimeError> {
+        // Declarations inside a loop run once per iteration, so reuse the old slot if there's one
+        if let Some(address) = self.get_address_of(id) {
+            self.set_value(address, DynamicValue::Null);
+
+            return Ok(());
+        }
+
         let address = self.next_address;
         self.next_address += 1;
 
@@ -164,6 +313,15 @@ impl FunctionFrame {
 
         Ok(())
     }
+
+    // Replaces the value at the address, freeing the text it held
+    fn set_value(&mut self, address : usize, value : DynamicValue) {
+        if let DynamicValue::Text(t) = self.stack[address] {
+            let _ = self.string_storage.get(t);
+        }
+
+        self.stack[address] = value;
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -173,21 +331,58 @@ pub enum ExecutionStatus {
     Returned,
 }
 
+/// Caps on what a program can use while it runs, for when it can't be trusted. None means there's
+/// no limit.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ExecutionLimits {
+    pub max_instructions : Option<u64>,
+    // Counts the calls still getting their arguments too
+    pub max_call_depth : Option<usize>,
+    // Text held by the main stack, by every function running and by the lists
+    pub max_string_bytes : Option<usize>,
+    pub max_input_reads : Option<u64>,
+    // Elements of every list made by the program. Lists are never freed, so this counts the ones
+    // that aren't used anymore too
+    pub max_list_items : Option<usize>,
+}
+
+/// A function implemented in Rust. Gets the arguments in the order the parameters were declared
+pub type NativeFunction = Box<dyn FnMut(Vec<RawValue>) -> Result<RawValue, String>>;
+
+/// Where the VM was before running something, so it can go back there whatever happens
+pub struct Checkpoint {
+    depth : usize,
+    main_stack_top : usize,
+}
+
+struct NativeEntry {
+    // Id of the function entry calling it
+    id : u64,
+    // Ids of the variables holding the arguments, in order
+    params : Vec<u64>,
+    function : NativeFunction,
+}
+
 pub struct VirtualMachine {
     has_quit : bool,
     main_stack : [DynamicValue; MAIN_STACK_SIZE],
     main_stack_top : usize,
     main_storage : StringStorage,
+    list_storage : ListStorage,
     callstack : Vec<FunctionFrame>,
     stdout: Option<Box<Write>>,
     stdin:  Option<Box<BufRead>>,
+    limits : ExecutionLimits,
+    instructions_executed : u64,
+    input_reads : u64,
+    natives : Vec<NativeEntry>,
 }
 
 macro_rules! vm_write{
     ($out:expr,$($arg:tt)*) => ({
         if let Some(output) = $out.as_mut(){
             write!(output, $($arg)*)
-                .map_err(|what| format!("Deu pra escrever não cumpade: {:?}", what))
+                .map_err(|what| RuntimeError::new(format!("Deu pra escrever não cumpade: {:?}", what)))
         }else{
             Ok(())
         }
@@ -201,9 +396,14 @@ impl VirtualMachine {
             main_stack : [DynamicValue::Null; MAIN_STACK_SIZE],
             main_stack_top : 0,
             main_storage : StringStorage::new(),
+            list_storage : ListStorage::new(),
             callstack : vec![],
             stdout: None,
             stdin: None,
+            limits : ExecutionLimits::default(),
+            instructions_executed : 0,
+            input_reads : 0,
+            natives : vec![],
         }
     }
 
@@ -217,6 +417,82 @@ impl VirtualMachine {
         mem::replace(&mut self.stdin, read)
     } 
 
+    pub fn set_limits(&mut self, limits : ExecutionLimits) {
+        self.limits = limits;
+    }
+
+    pub fn get_limits(&self) -> ExecutionLimits {
+        self.limits
+    }
+
+    /// Starts counting the instructions and input reads from zero again
+    pub fn reset_usage(&mut self) {
+        self.instructions_executed = 0;
+        self.input_reads = 0;
+    }
+
+    pub fn get_instructions_executed(&self) -> u64 {
+        self.instructions_executed
+    }
+
+    /// Sets what CallNative with `id` runs, replacing the function set before, if any
+    pub fn set_native(&mut self, id : u64, params : Vec<u64>, function : NativeFunction) {
+        if let Some(n) = self.natives.iter_mut().find(|n| n.id == id) {
+            n.params = params;
+            n.function = function;
+
+            return;
+        }
+
+        self.natives.push(NativeEntry { id, params, function });
+    }
+
+    pub fn has_native(&self, id : u64) -> bool {
+        self.natives.iter().any(|n| n.id == id)
+    }
+
+    fn get_string_bytes(&self) -> usize {
+        let stored = self.main_storage.bytes + self.list_storage.bytes;
+
+        self.callstack.iter().fold(stored, |total, f| total + f.string_storage.bytes)
+    }
+
+    fn check_limits(&mut self, inst : &Instruction) -> Result<(), RuntimeError> {
+        self.instructions_executed += 1;
+
+        if let Some(max) = self.limits.max_instructions {
+            if self.instructions_executed > max {
+                return Err(RuntimeError::limit_exceeded(Limit::Instructions,
+                    format!("Limite de instruções excedido ({})", max)));
+            }
+        }
+
+        match inst {
+            Instruction::MakeNewFrame(_) => {
+                if let Some(max) = self.limits.max_call_depth {
+                    if self.callstack.len() >= max {
+                        return Err(RuntimeError::limit_exceeded(Limit::CallDepth,
+                            format!("Limite de chamadas excedido ({})", max)));
+                    }
+                }
+            }
+            // Skipped reads don't count
+            Instruction::ReadInput if self.get_current_skip_level() == 0 => {
+                self.input_reads += 1;
+
+                if let Some(max) = self.limits.max_input_reads {
+                    if self.input_reads > max {
+                        return Err(RuntimeError::limit_exceeded(Limit::InputReads,
+                            format!("Limite de leituras excedido ({})", max)));
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
     pub fn get_current_skip_level(&self) -> u32 {
         match self.get_last_ready_ref() {
             Some(f) => f.skip_level,
@@ -275,6 +551,24 @@ impl VirtualMachine {
         Some(d)
     }
 
+    // Text on the main stack belongs to the value holding its ID, so values that are dropped instead
+    // of consumed must free it
+    fn drop_value(&mut self, v : DynamicValue) {
+        if let DynamicValue::Text(t) = v {
+            let _ = self.main_storage.get(t);
+        }
+    }
+
+    // Drops every value above `top`
+    fn truncate_main(&mut self, top : usize) {
+        while self.main_stack_top > top {
+            self.main_stack_top -= 1;
+
+            let v = self.main_stack[self.main_stack_top];
+            self.drop_value(v);
+        }
+    }
+
     fn push_main(&mut self, v : DynamicValue) -> Option<()> {
         if self.main_stack_top >= MAIN_STACK_SIZE {
             return None;
@@ -310,28 +604,39 @@ impl VirtualMachine {
                     _ => false,
                 }
             }
+            DynamicValue::List(_) => {
+                matches!(right, DynamicValue::List(_))
+            }
+            DynamicValue::Boolean(_) => {
+                matches!(right, DynamicValue::Boolean(_))
+            }
             _ => false,
         }
     }
 
-    fn add_values(&mut self, left : DynamicValue, right : DynamicValue) -> Result<DynamicValue, String> {
+    fn add_values(&mut self, left : DynamicValue, right : DynamicValue) -> Result<DynamicValue, RuntimeError> {
         if ! VirtualMachine::is_compatible(left, right) {
-            return Err(format!("Add : Os valores não são compatíveis : {:?} e {:?}", left, right));
+            return Err(RuntimeError::new(format!("Add : Os valores não são compatíveis : {:?} e {:?}", left, right)));
         }
 
         match left {
             DynamicValue::Integer(l_i) => {
                 match right {
-                    DynamicValue::Integer(r_i) => Ok(DynamicValue::Integer(l_i + r_i)),
+                    DynamicValue::Integer(r_i) => {
+                        match l_i.checked_add(r_i) {
+                            Some(v) => Ok(DynamicValue::Integer(v)),
+                            None => Err(RuntimeError::new(format!("Add : {} + {} não cabe num inteiro", l_i, r_i))),
+                        }
+                    }
                     DynamicValue::Number(r_n) => Ok(DynamicValue::Number((l_i as f64) + r_n)),
-                    _ => return Err("Incompatível. Não deveria chegar aqui.".to_owned()),
+                    _ => Err(RuntimeError::new("Incompatível. Não deveria chegar aqui.")),
                 }
             }
             DynamicValue::Number(l_n) => {
                 match right {
                     DynamicValue::Integer(r_i) => Ok(DynamicValue::Number(l_n + (r_i as f64))),
                     DynamicValue::Number(r_n) => Ok(DynamicValue::Number(l_n + r_n)),
-                    _ => return Err("Incompatível. Não deveria chegar aqui.".to_owned()),
+                    _ => Err(RuntimeError::new("Incompatível. Não deveria chegar aqui.")),
                 }
             }
             DynamicValue::Text(l_t) => {
@@ -342,105 +647,221 @@ impl VirtualMachine {
 
                         let left_v = match self.main_storage.get(r_t) {
                             Some(s) => s,
-                            None => return Err(format!("Add w/ Text : Id {} não encontrada.", r_t))
+                            None => return Err(RuntimeError::new(format!("Add w/ Text : Id {} não encontrada.", r_t)))
                         };
 
                         // remove right node
-                        let right_v_ref = match self.main_storage.get_mut(l_t) {
-                            Some(c) => c,
-                            None => return Err(format!("Add w/ Text : Id {} não encontrada.", l_t))
-                        };
-
-                        right_v_ref.push_str(left_v.as_str());
+                        if !self.main_storage.append(l_t, left_v.as_str()) {
+                            return Err(RuntimeError::new(format!("Add w/ Text : Id {} não encontrada.", l_t)));
+                        }
 
                         Ok(DynamicValue::Text(l_t))
                     }
-                    _ => return Err("Incompatível. Não deveria chegar aqui.".to_owned()),
+                    _ => Err(RuntimeError::new("Incompatível. Não deveria chegar aqui.")),
                 }
             }
+            DynamicValue::List(_) => Err(RuntimeError::new("Operação inválida em lista : +")),
+            DynamicValue::Boolean(_) => Err(RuntimeError::new("Operação inválida em BIRLEANO : +")),
             DynamicValue::Null => Ok(DynamicValue::Null),
         }
     }
 
-    fn sub_values(&mut self, left : DynamicValue, right : DynamicValue) -> Result<DynamicValue, String> {
+    fn sub_values(&mut self, left : DynamicValue, right : DynamicValue) -> Result<DynamicValue, RuntimeError> {
         if ! VirtualMachine::is_compatible(left, right) {
-            return Err(format!("Add : Os valores não são compatíveis : {:?} e {:?}", left, right));
+            return Err(RuntimeError::new(format!("Add : Os valores não são compatíveis : {:?} e {:?}", left, right)));
         }
 
         match left {
             DynamicValue::Integer(l_i) => {
                 match right {
-                    DynamicValue::Integer(r_i) => Ok(DynamicValue::Integer(l_i - r_i)),
+                    DynamicValue::Integer(r_i) => {
+                        match l_i.checked_sub(r_i) {
+                            Some(v) => Ok(DynamicValue::Integer(v)),
+                            None => Err(RuntimeError::new(format!("Sub : {} - {} não cabe num inteiro", l_i, r_i))),
+                        }
+                    }
                     DynamicValue::Number(r_n) => Ok(DynamicValue::Number((l_i as f64) - r_n)),
-                    _ => return Err("Incompatível. Não deveria chegar aqui.".to_owned()),
+                    _ => Err(RuntimeError::new("Incompatível. Não deveria chegar aqui.")),
                 }
             }
             DynamicValue::Number(l_n) => {
                 match right {
                     DynamicValue::Integer(r_i) => Ok(DynamicValue::Number(l_n - (r_i as f64))),
                     DynamicValue::Number(r_n) => Ok(DynamicValue::Number(l_n - r_n)),
-                    _ => return Err("Incompatível. Não deveria chegar aqui.".to_owned()),
+                    _ => Err(RuntimeError::new("Incompatível. Não deveria chegar aqui.")),
                 }
             }
-            DynamicValue::Text(_) => return Err("Operação inválida em texto : -".to_owned()),
+            DynamicValue::Text(_) => Err(RuntimeError::new("Operação inválida em texto : -")),
+            DynamicValue::List(_) => Err(RuntimeError::new("Operação inválida em lista : -")),
+            DynamicValue::Boolean(_) => Err(RuntimeError::new("Operação inválida em BIRLEANO : -")),
             DynamicValue::Null => Ok(DynamicValue::Null),
         }
     }
 
-    fn mul_values(&mut self, left : DynamicValue, right : DynamicValue) -> Result<DynamicValue, String> {
+    fn mul_values(&mut self, left : DynamicValue, right : DynamicValue) -> Result<DynamicValue, RuntimeError> {
         if ! VirtualMachine::is_compatible(left, right) {
-            return Err(format!("Add : Os valores não são compatíveis : {:?} e {:?}", left, right));
+            return Err(RuntimeError::new(format!("Add : Os valores não são compatíveis : {:?} e {:?}", left, right)));
         }
 
         match left {
             DynamicValue::Integer(l_i) => {
                 match right {
-                    DynamicValue::Integer(r_i) => Ok(DynamicValue::Integer(l_i * r_i)),
+                    DynamicValue::Integer(r_i) => {
+                        match l_i.checked_mul(r_i) {
+                            Some(v) => Ok(DynamicValue::Integer(v)),
+                            None => Err(RuntimeError::new(format!("Mul : {} * {} não cabe num inteiro", l_i, r_i))),
+                        }
+                    }
                     DynamicValue::Number(r_n) => Ok(DynamicValue::Number((l_i as f64) * r_n)),
-                    _ => return Err("Incompatível. Não deveria chegar aqui.".to_owned()),
+                    _ => Err(RuntimeError::new("Incompatível. Não deveria chegar aqui.")),
                 }
             }
             DynamicValue::Number(l_n) => {
                 match right {
                     DynamicValue::Integer(r_i) => Ok(DynamicValue::Number(l_n * (r_i as f64))),
                     DynamicValue::Number(r_n) => Ok(DynamicValue::Number(l_n * r_n)),
-                    _ => return Err("Incompatível. Não deveria chegar aqui.".to_owned()),
+                    _ => Err(RuntimeError::new("Incompatível. Não deveria chegar aqui.")),
                 }
             }
-            DynamicValue::Text(_) => return Err("Operação inválida em texto : *".to_owned()),
+            DynamicValue::Text(_) => Err(RuntimeError::new("Operação inválida em texto : *")),
+            DynamicValue::List(_) => Err(RuntimeError::new("Operação inválida em lista : *")),
+            DynamicValue::Boolean(_) => Err(RuntimeError::new("Operação inválida em BIRLEANO : *")),
             DynamicValue::Null => Ok(DynamicValue::Null),
         }
     }
 
-    fn div_values(&mut self, left : DynamicValue, right : DynamicValue) -> Result<DynamicValue, String> {
+    fn div_values(&mut self, left : DynamicValue, right : DynamicValue) -> Result<DynamicValue, RuntimeError> {
         if ! VirtualMachine::is_compatible(left, right) {
-            return Err(format!("Add : Os valores não são compatíveis : {:?} e {:?}", left, right));
+            return Err(RuntimeError::new(format!("Add : Os valores não são compatíveis : {:?} e {:?}", left, right)));
         }
 
         match left {
             DynamicValue::Integer(l_i) => {
                 match right {
-                    DynamicValue::Integer(r_i) => Ok(DynamicValue::Integer(l_i / r_i)),
+                    DynamicValue::Integer(0) => Err(RuntimeError::new("Divisão por zero")),
+                    DynamicValue::Integer(r_i) => {
+                        match l_i.checked_div(r_i) {
+                            Some(v) => Ok(DynamicValue::Integer(v)),
+                            None => Err(RuntimeError::new(format!("Div : {} / {} não cabe num inteiro", l_i, r_i))),
+                        }
+                    }
+                    DynamicValue::Number(0.0) => Err(RuntimeError::new("Divisão por zero")),
                     DynamicValue::Number(r_n) => Ok(DynamicValue::Number((l_i as f64) / r_n)),
-                    _ => return Err("Incompatível. Não deveria chegar aqui.".to_owned()),
+                    _ => Err(RuntimeError::new("Incompatível. Não deveria chegar aqui.")),
                 }
             }
             DynamicValue::Number(l_n) => {
                 match right {
+                    // Same as with integers, instead of giving an infinity
+                    DynamicValue::Integer(0) => Err(RuntimeError::new("Divisão por zero")),
+                    DynamicValue::Number(0.0) => Err(RuntimeError::new("Divisão por zero")),
                     DynamicValue::Integer(r_i) => Ok(DynamicValue::Number(l_n / (r_i as f64))),
                     DynamicValue::Number(r_n) => Ok(DynamicValue::Number(l_n / r_n)),
-                    _ => return Err("Incompatível. Não deveria chegar aqui.".to_owned()),
+                    _ => Err(RuntimeError::new("Incompatível. Não deveria chegar aqui.")),
+                }
+            }
+            DynamicValue::Text(_) => Err(RuntimeError::new("Operação inválida em texto : /")),
+            DynamicValue::List(_) => Err(RuntimeError::new("Operação inválida em lista : /")),
+            DynamicValue::Boolean(_) => Err(RuntimeError::new("Operação inválida em BIRLEANO : /")),
+            DynamicValue::Null => Ok(DynamicValue::Null),
+        }
+    }
+
+    fn mod_values(&mut self, left : DynamicValue, right : DynamicValue) -> Result<DynamicValue, RuntimeError> {
+        if ! VirtualMachine::is_compatible(left, right) {
+            return Err(RuntimeError::new(format!("Mod : Os valores não são compatíveis : {:?} e {:?}", left, right)));
+        }
+
+        match left {
+            DynamicValue::Integer(l_i) => {
+                match right {
+                    DynamicValue::Integer(0) => Err(RuntimeError::new("Resto de divisão por zero")),
+                    DynamicValue::Integer(r_i) => {
+                        match l_i.checked_rem(r_i) {
+                            Some(v) => Ok(DynamicValue::Integer(v)),
+                            None => Err(RuntimeError::new(format!("Mod : {} % {} não cabe num inteiro", l_i, r_i))),
+                        }
+                    }
+                    DynamicValue::Number(0.0) => Err(RuntimeError::new("Resto de divisão por zero")),
+                    DynamicValue::Number(r_n) => Ok(DynamicValue::Number((l_i as f64) % r_n)),
+                    _ => Err(RuntimeError::new("Incompatível. Não deveria chegar aqui.")),
+                }
+            }
+            DynamicValue::Number(l_n) => {
+                match right {
+                    // Same as with integers, instead of giving NaN
+                    DynamicValue::Integer(0) => Err(RuntimeError::new("Resto de divisão por zero")),
+                    DynamicValue::Number(0.0) => Err(RuntimeError::new("Resto de divisão por zero")),
+                    DynamicValue::Integer(r_i) => Ok(DynamicValue::Number(l_n % (r_i as f64))),
+                    DynamicValue::Number(r_n) => Ok(DynamicValue::Number(l_n % r_n)),
+                    _ => Err(RuntimeError::new("Incompatível. Não deveria chegar aqui.")),
+                }
+            }
+            DynamicValue::Text(_) => Err(RuntimeError::new("Operação inválida em texto : %")),
+            DynamicValue::List(_) => Err(RuntimeError::new("Operação inválida em lista : %")),
+            DynamicValue::Boolean(_) => Err(RuntimeError::new("Operação inválida em BIRLEANO : %")),
+            DynamicValue::Null => Ok(DynamicValue::Null),
+        }
+    }
+
+    fn pow_values(&mut self, left : DynamicValue, right : DynamicValue) -> Result<DynamicValue, RuntimeError> {
+        if ! VirtualMachine::is_compatible(left, right) {
+            return Err(RuntimeError::new(format!("Pow : Os valores não são compatíveis : {:?} e {:?}", left, right)));
+        }
+
+        match left {
+            DynamicValue::Integer(l_i) => {
+                match right {
+                    // A negative exponent gives a fraction, so only positive ones stay integers
+                    DynamicValue::Integer(r_i) if r_i >= 0 => {
+                        if r_i > (u32::MAX as IntegerType) {
+                            return Err(RuntimeError::new(format!("Pow : Expoente muito grande : {}", r_i)));
+                        }
+
+                        match l_i.checked_pow(r_i as u32) {
+                            Some(v) => Ok(DynamicValue::Integer(v)),
+                            None => Err(RuntimeError::new(format!("Pow : {} ^ {} não cabe num inteiro", l_i, r_i))),
+                        }
+                    }
+                    DynamicValue::Integer(r_i) => Ok(DynamicValue::Number((l_i as f64).powf(r_i as f64))),
+                    DynamicValue::Number(r_n) => Ok(DynamicValue::Number((l_i as f64).powf(r_n))),
+                    _ => Err(RuntimeError::new("Incompatível. Não deveria chegar aqui.")),
+                }
+            }
+            DynamicValue::Number(l_n) => {
+                match right {
+                    DynamicValue::Integer(r_i) => Ok(DynamicValue::Number(l_n.powf(r_i as f64))),
+                    DynamicValue::Number(r_n) => Ok(DynamicValue::Number(l_n.powf(r_n))),
+                    _ => Err(RuntimeError::new("Incompatível. Não deveria chegar aqui.")),
+                }
+            }
+            DynamicValue::Text(_) => Err(RuntimeError::new("Operação inválida em texto : ^")),
+            DynamicValue::List(_) => Err(RuntimeError::new("Operação inválida em lista : ^")),
+            DynamicValue::Boolean(_) => Err(RuntimeError::new("Operação inválida em BIRLEANO : ^")),
+            DynamicValue::Null => Ok(DynamicValue::Null),
+        }
+    }
+
+    fn neg_value(&mut self, value : DynamicValue) -> Result<DynamicValue, RuntimeError> {
+        match value {
+            DynamicValue::Integer(i) => {
+                match i.checked_neg() {
+                    Some(v) => Ok(DynamicValue::Integer(v)),
+                    None => Err(RuntimeError::new(format!("Neg : -{} não cabe num inteiro", i))),
                 }
             }
-            DynamicValue::Text(_) => return Err("Operação inválida em texto : /".to_owned()),
+            DynamicValue::Number(n) => Ok(DynamicValue::Number(-n)),
+            DynamicValue::Text(_) => Err(RuntimeError::new("Operação inválida em texto : - unário")),
+            DynamicValue::List(_) => Err(RuntimeError::new("Operação inválida em lista : - unário")),
+            DynamicValue::Boolean(_) => Err(RuntimeError::new("Operação inválida em BIRLEANO : - unário")),
             DynamicValue::Null => Ok(DynamicValue::Null),
         }
     }
 
-    fn print_debug_main_top(&self) -> Result<(), String> {
+    fn print_debug_main_top(&self) -> Result<(), RuntimeError> {
         let top = match self.get_main_top() {
             Some(t) => t,
-            None => return Err("MainPrintDebug : Main stack is empty".to_owned()),
+            None => return Err(RuntimeError::new("MainPrintDebug : Main stack is empty")),
         };
 
         print!("<");
@@ -459,11 +880,19 @@ impl VirtualMachine {
                     Some(ref t) => {
                         print!("{}", t);
                     }
-                    None => return Err(format!("Não foi encontrado o texto com ID {}", t)),
+                    None => return Err(RuntimeError::new(format!("Não foi encontrado o texto com ID {}", t))),
                 }
 
                 println!("\"");
             }
+            DynamicValue::List(l) => {
+                let text = self.list_to_string(l)?;
+
+                println!("(List) : {}", text);
+            }
+            DynamicValue::Boolean(b) => {
+                println!("(Boolean) : {}", VirtualMachine::bool_to_str(b));
+            }
             DynamicValue::Null => {
                 println!("<Null>");
             }
@@ -472,20 +901,20 @@ impl VirtualMachine {
         Ok(())
     }
 
-    fn get_last_comparision(&self) -> Result<Comparision, String> {
+    fn get_last_comparision(&self) -> Result<Comparision, RuntimeError> {
         if self.callstack.is_empty() {
-            return Err("Callstack vazia".to_owned());
+            return Err(RuntimeError::new("Callstack vazia"));
         }
 
         match self.callstack.last().unwrap().last_comparision {
             Some(c) => Ok(c),
-            None => Err("Nenhuma comparação na função atual".to_owned())
+            None => Err(RuntimeError::new("Nenhuma comparação na função atual"))
         }
     }
 
-    fn compare(&self, left : DynamicValue, right : DynamicValue) -> Result<Comparision, String> {
+    fn compare(&self, left : DynamicValue, right : DynamicValue) -> Result<Comparision, RuntimeError> {
         if ! VirtualMachine::is_compatible(left, right) {
-            return Err(format!("Compare : Valores incompatíveis : {:?} e {:?}", left, right));
+            return Err(RuntimeError::new(format!("Compare : Valores incompatíveis : {:?} e {:?}", left, right)));
         }
 
         let comp_numbers: fn(f64, f64) -> Comparision = | l, r | {
@@ -530,12 +959,12 @@ impl VirtualMachine {
                     DynamicValue::Text(r_t) => {
                         let ltext = match self.main_storage.get_ref(l_t) {
                             Some(t) => t,
-                            None => return Err(format!("Erro : TextID não encontrada : {}", l_t)),
+                            None => return Err(RuntimeError::new(format!("Erro : TextID não encontrada : {}", l_t))),
                         };
 
                         let rtext = match self.main_storage.get_ref(r_t) {
                             Some(t) => t,
-                            None => return Err(format!("Erro : TextID não encontrada : {}", r_t)),
+                            None => return Err(RuntimeError::new(format!("Erro : TextID não encontrada : {}", r_t))),
                         };
 
                         let llen = ltext.len();
@@ -556,15 +985,43 @@ impl VirtualMachine {
                     _ => Comparision::NotEqual
                 }
             }
+            DynamicValue::List(l_l) => {
+                match right {
+                    DynamicValue::List(r_l) => {
+                        let llist = match self.list_storage.get_ref(l_l) {
+                            Some(l) => l,
+                            None => return Err(RuntimeError::new(format!("Erro : ListID não encontrada : {}", l_l))),
+                        };
+
+                        let rlist = match self.list_storage.get_ref(r_l) {
+                            Some(l) => l,
+                            None => return Err(RuntimeError::new(format!("Erro : ListID não encontrada : {}", r_l))),
+                        };
+
+                        if llist == rlist {
+                            Comparision::Equal
+                        } else {
+                            Comparision::NotEqual
+                        }
+                    }
+                    _ => Comparision::NotEqual
+                }
+            }
+            DynamicValue::Boolean(l_b) => {
+                match right {
+                    DynamicValue::Boolean(r_b) if l_b == r_b => Comparision::Equal,
+                    _ => Comparision::NotEqual,
+                }
+            }
             DynamicValue::Null => Comparision::NotEqual,
         };
 
         Ok(comp)
     }
 
-    fn set_last_comparision(&mut self, comp : Comparision) -> Result<(), String> {
+    fn set_last_comparision(&mut self, comp : Comparision) -> Result<(), RuntimeError> {
         if self.callstack.is_empty() {
-            return Err("Callstack tá vazia. Provavelmente é erro interno".to_owned());
+            return Err(RuntimeError::new("Callstack tá vazia. Provavelmente é erro interno"));
         }
 
         self.callstack.last_mut().unwrap().last_comparision = Some(comp);
@@ -572,35 +1029,32 @@ impl VirtualMachine {
         Ok(())
     }
 
-    // This function doesn't search all the callstack, just the first frame
-    fn get_last_ready_index(&self) -> Option<usize> {
-        if self.callstack.is_empty() {
-            None
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            depth : self.callstack.len(),
+            main_stack_top : self.main_stack_top,
         }
-        else if self.callstack.len() < 2 {
-            if self.callstack[0].ready {
-                Some(0)
-            } else {
-                None
-            }
-        } else {
-            let last = self.callstack.len() - 1;
+    }
 
-            if self.callstack[last].ready {
-                Some(last)
-            } else {
-                Some(last - 1)
-            }
-        }
+    /// Drops the frames and values pushed after the checkpoint
+    pub fn restore(&mut self, checkpoint : Checkpoint) {
+        self.callstack.truncate(checkpoint.depth);
+        self.truncate_main(checkpoint.main_stack_top);
+
+        self.has_quit = false;
+    }
+
+    pub fn get_last_ready_index(&self) -> Option<usize> {
+        self.callstack.iter().rposition(|f| f.ready)
     }
 
-    fn write_main_top_to(&mut self, stack_index : usize, id : u64) -> Result<(), String> {
+    fn write_main_top_to(&mut self, stack_index : usize, id : u64) -> Result<(), RuntimeError> {
         if self.callstack.len() <= stack_index {
-            return Err(format!("Index inválido : {}", stack_index));
+            return Err(RuntimeError::new(format!("Index inválido : {}", stack_index)));
         }
 
         if self.main_stack_top == 0 {
-            return Err("Main stack underflow".to_owned());
+            return Err(RuntimeError::new("Main stack underflow"));
         }
 
         self.main_stack_top -= 1;
@@ -615,7 +1069,7 @@ impl VirtualMachine {
                     Ok(_) => {
                         match frame.get_address_of(id) {
                             Some(i) => i,
-                            None => return Err(format!("ID {} not found", id)),
+                            None => return Err(RuntimeError::new(format!("ID {} not found", id))),
                         }
                     }
                     Err(e) => return Err(e),
@@ -624,47 +1078,48 @@ impl VirtualMachine {
         };
 
         if frame.stack.len() <= addr {
-            return Err("Endereço inválido pra stack".to_owned());
+            return Err(RuntimeError::new("Endereço inválido pra stack"));
         }
 
+        // The text moves to the storage of the frame, since nothing else holds it
         match val {
             DynamicValue::Text(t) => {
-                let raw = match self.main_storage.get_ref(t) {
+                let raw = match self.main_storage.get(t) {
                     Some(t) => t,
-                    None => return Err(format!("TextID {} é inválida.", t))
+                    None => return Err(RuntimeError::new(format!("TextID {} é inválida.", t)))
                 };
 
-                let id = frame.string_storage.add(raw);
+                let id = frame.string_storage.add_string(raw);
 
-                frame.stack[addr] = DynamicValue::Text(id);
+                frame.set_value(addr, DynamicValue::Text(id));
             }
-            _ => frame.stack[addr] = val,
+            _ => frame.set_value(addr, val),
         }
 
         Ok(())
     }
 
-    fn increase_skip_level(&mut self) -> Result<(), String> {
+    fn increase_skip_level(&mut self) -> Result<(), RuntimeError> {
         match self.get_last_ready_mut() {
             Some(f) => f.skip_level += 1,
-            None => return Err("Nenhuma função ready em execução".to_owned())
+            None => return Err(RuntimeError::new("Nenhuma função ready em execução"))
         }
 
         Ok(())
     }
 
-    fn decrease_skip_level(&mut self) -> Result<(), String> {
+    fn decrease_skip_level(&mut self) -> Result<(), RuntimeError> {
         match self.get_last_ready_mut() {
             Some(f) => f.skip_level -= 1,
-            None => return Err("Nenhuma função ready em execução".to_owned())
+            None => return Err(RuntimeError::new("Nenhuma função ready em execução"))
         }
 
         Ok(())
     }
 
-    fn create_runtime_var(&mut self, id : u64) -> Result<(), String> {
+    fn create_runtime_var(&mut self, id : u64) -> Result<(), RuntimeError> {
         if self.callstack.is_empty() {
-            return Err("CreateVar : Callstack tá vazia. Possível erro interno".to_owned());
+            return Err(RuntimeError::new("CreateVar : Callstack tá vazia. Possível erro interno"));
         }
 
         let frame = self.callstack.last_mut().unwrap();
@@ -672,9 +1127,9 @@ impl VirtualMachine {
         frame.create_runtime_var(id)
     }
 
-    fn read_from_id_to_main(&mut self, index : usize, id : u64) -> Result<(), String> {
+    fn read_from_id_to_main(&mut self, index : usize, id : u64) -> Result<(), RuntimeError> {
         if self.callstack.len() < index {
-            return Err(format!("Index out of bounds for read : {}", index));
+            return Err(RuntimeError::new(format!("Index out of bounds for read : {}", index)));
         }
 
         let val = {
@@ -688,7 +1143,7 @@ impl VirtualMachine {
                         Ok(_) => {
                             match frame.get_address_of(id) {
                                 Some(a) => a,
-                                None => return Err("Erro fatal : Não foi possível criar variável".to_owned())
+                                None => return Err(RuntimeError::new("Erro fatal : Não foi possível criar variável"))
                             }
                         }
                         Err(e) => return Err(e),
@@ -697,15 +1152,24 @@ impl VirtualMachine {
             };
 
             if frame.stack.len() <= addr {
-                return Err("Erro : Endereço pra variável é inválido".to_owned());
+                return Err(RuntimeError::new("Erro : Endereço pra variável é inválido"));
             }
 
-            frame.stack[addr]
+            // Text lives in the storage of the frame, so it has to be copied to the main one
+            match frame.stack[addr] {
+                DynamicValue::Text(t) => {
+                    match frame.string_storage.get_ref(t) {
+                        Some(text) => DynamicValue::Text(self.main_storage.add(text)),
+                        None => return Err(RuntimeError::new(format!("TextID {} é inválida.", t)))
+                    }
+                }
+                v => v,
+            }
         };
 
         match self.push_main(val) {
             Some(_) => {}
-            None => return Err("Main stack overflow".to_owned()),
+            None => return Err(RuntimeError::new("Main stack overflow")),
         }
 
         Ok(())
@@ -726,87 +1190,498 @@ impl VirtualMachine {
         }
     }
 
-    pub fn increment_pc(&mut self) -> Result<(), String> {
+    pub fn increment_pc(&mut self) -> Result<(), RuntimeError> {
         match self.get_last_ready_mut() {
             Some(f) => f.program_counter += 1,
-            None => return Err("Nenhuma função em execução".to_owned())
+            None => return Err(RuntimeError::new("Nenhuma função em execução"))
         }
 
         Ok(())
     }
 
-    pub fn decrement_pc(&mut self) -> Result<(), String> {
+    pub fn decrement_pc(&mut self) -> Result<(), RuntimeError> {
         match self.get_last_ready_mut() {
             Some(f) => f.program_counter -= 1,
-            None => return Err("Nenhuma função em execução".to_owned())
+            None => return Err(RuntimeError::new("Nenhuma função em execução"))
         }
 
         Ok(())
 
     }
 
-    fn conv_to_string(&mut self, val : DynamicValue) -> Result<String, String> {
-        match val {
-            DynamicValue::Text(t) => {
-                let s = match self.main_storage.get(t) {
-                    Some(s) => s,
-                    None => return Err("Invalid string ID".to_owned()),
-                };
+    pub fn get_callstack(&self) -> &[FunctionFrame] {
+        &self.callstack
+    }
 
-                Ok(s)
-            }
-            DynamicValue::Integer(i) => Ok(format!("{}", i)),
-            DynamicValue::Number(n) => Ok(format!("{}", n)),
-            DynamicValue::Null => Ok(String::from("<Null>")),
-        }
+    /// Number of functions that started running and haven't returned yet
+    pub fn get_depth(&self) -> usize {
+        self.callstack.iter().filter(|f| f.ready).count()
     }
 
-    fn conv_to_int(&mut self, val : DynamicValue) -> Result<IntegerType, String> {
-        match val {
-            DynamicValue::Text(t) => {
-                let text = match self.main_storage.get(t) {
-                    Some(v) => v,
-                    None => return Err("Invalid text id".to_owned())
-                };
+    /// Shows the value of a variable of a frame without changing anything, or None if the frame
+    /// didn't create the variable yet.
+    pub fn describe_var(&self, frame_index : usize, id : u64) -> Result<Option<String>, RuntimeError> {
+        let frame = match self.callstack.get(frame_index) {
+            Some(f) => f,
+            None => return Err(RuntimeError::new(format!("Index inválido : {}", frame_index))),
+        };
 
-                let i = match text.parse::<IntegerType>() {
-                    Ok(i) => i,
-                    Err(_) => return Err(format!("Não foi possível converter \"{}\" pra Int", text))
-                };
+        let addr = match frame.get_address_of(id) {
+            Some(a) => a,
+            None => return Ok(None),
+        };
 
-                Ok(i)
-            }
-            DynamicValue::Number(n) => Ok(n as IntegerType),
-            DynamicValue::Integer(i) => Ok(i),
-            DynamicValue::Null => return Err("Convert : <Null>".to_owned()),
+        if frame.stack.len() <= addr {
+            return Err(RuntimeError::new("Erro : Endereço pra variável é inválido"));
         }
+
+        let text = match frame.stack[addr] {
+            DynamicValue::Text(t) => {
+                match frame.string_storage.get_ref(t) {
+                    Some(text) => format!("\"{}\"", text),
+                    None => return Err(RuntimeError::new(format!("TextID {} é inválida.", t)))
+                }
+            }
+            DynamicValue::Integer(i) => format!("{}", i),
+            DynamicValue::Number(n) => format!("{}", n),
+            DynamicValue::List(l) => self.list_to_string(l)?,
+            DynamicValue::Boolean(b) => VirtualMachine::bool_to_str(b).to_owned(),
+            DynamicValue::Null => String::from("<Null>"),
+        };
+
+        Ok(Some(text))
+    }
+
+    /// Reads a variable of a frame in the callstack as a raw value. None if it wasn't created yet
+    pub fn read_var(&self, frame_index : usize, id : u64) -> Result<Option<RawValue>, RuntimeError> {
+        let frame = match self.callstack.get(frame_index) {
+            Some(f) => f,
+            None => return Err(RuntimeError::new(format!("Index inválido : {}", frame_index))),
+        };
+
+        let addr = match frame.get_address_of(id) {
+            Some(a) => a,
+            None => return Ok(None),
+        };
+
+        if frame.stack.len() <= addr {
+            return Err(RuntimeError::new("Erro : Endereço pra variável é inválido"));
+        }
+
+        let value = match frame.stack[addr] {
+            DynamicValue::Text(t) => {
+                match frame.string_storage.get_ref(t) {
+                    Some(text) => RawValue::Text(text.to_owned()),
+                    None => return Err(RuntimeError::new(format!("TextID {} é inválida.", t)))
+                }
+            }
+            DynamicValue::Integer(i) => RawValue::Integer(i),
+            DynamicValue::Number(n) => RawValue::Number(n),
+            DynamicValue::Boolean(b) => RawValue::Boolean(b),
+            DynamicValue::Null => RawValue::Null,
+            DynamicValue::List(l) => {
+                self.list_to_raw(l, &mut vec![])?
+            }
+        };
+
+        Ok(Some(value))
+    }
+
+    /// Reads the value on the top of the main stack as a raw value, like what a function returned
+    pub fn read_main_top(&self) -> Result<RawValue, RuntimeError> {
+        let top = match self.get_main_top() {
+            Some(v) => v,
+            None => return Err(RuntimeError::new("Main stack is empty")),
+        };
+
+        match top {
+            DynamicValue::Text(t) => {
+                match self.main_storage.get_ref(t) {
+                    Some(text) => Ok(RawValue::Text(text.to_owned())),
+                    None => Err(RuntimeError::new(format!("TextID {} é inválida.", t)))
+                }
+            }
+            DynamicValue::Integer(i) => Ok(RawValue::Integer(i)),
+            DynamicValue::Number(n) => Ok(RawValue::Number(n)),
+            DynamicValue::Boolean(b) => Ok(RawValue::Boolean(b)),
+            DynamicValue::Null => Ok(RawValue::Null),
+            DynamicValue::List(l) => self.list_to_raw(l, &mut vec![]),
+        }
+    }
+
+    /// Gives a variable of a frame in the callstack a new value, creating it if needed
+    pub fn write_var(&mut self, frame_index : usize, id : u64, value : RawValue) -> Result<(), RuntimeError> {
+        if self.callstack.len() <= frame_index {
+            return Err(RuntimeError::new(format!("Index inválido : {}", frame_index)));
+        }
+
+        let value = self.raw_to_dynamic(value, Some(frame_index));
+
+        let frame = &mut self.callstack[frame_index];
+
+        let addr = match frame.get_address_of(id) {
+            Some(a) => a,
+            None => {
+                match frame.create_runtime_var(id) {
+                    Ok(_) => {}
+                    Err(e) => return Err(e)
+                }
+
+                match frame.get_address_of(id) {
+                    Some(a) => a,
+                    None => return Err(RuntimeError::new(format!("ID {} not found", id))),
+                }
+            }
+        };
+
+        if frame.stack.len() <= addr {
+            return Err(RuntimeError::new("Endereço inválido pra stack"));
+        }
+
+        frame.set_value(addr, value);
+
+        Ok(())
+    }
+
+    fn list_to_raw(&self, id : ListStorageID, visited : &mut Vec<ListStorageID>) -> Result<RawValue, RuntimeError> {
+        if visited.contains(&id) {
+            return Err(RuntimeError::new("A lista contém ela mesma e não pode ser copiada"));
+        }
+
+        let list = match self.list_storage.get_ref(id) {
+            Some(l) => l,
+            None => return Err(RuntimeError::new(format!("ListID {} é inválida.", id))),
+        };
+
+        visited.push(id);
+
+        let mut items = vec![];
+
+        for value in list {
+            let item = match value {
+                ListValue::Integer(i) => RawValue::Integer(*i),
+                ListValue::Number(n) => RawValue::Number(*n),
+                ListValue::Text(t) => RawValue::Text(t.clone()),
+                ListValue::List(l) => self.list_to_raw(*l, visited)?,
+                ListValue::Boolean(b) => RawValue::Boolean(*b),
+                ListValue::Null => RawValue::Null,
+            };
+
+            items.push(item);
+        }
+
+        visited.pop();
+
+        Ok(RawValue::List(items))
     }
 
-    fn conv_to_num(&mut self, val : DynamicValue) -> Result<f64, String> {
+    fn raw_to_list_value(&mut self, value : RawValue) -> ListValue {
+        match value {
+            RawValue::Integer(i) => ListValue::Integer(i),
+            RawValue::Number(n) => ListValue::Number(n),
+            RawValue::Text(t) => ListValue::Text(t),
+            RawValue::Boolean(b) => ListValue::Boolean(b),
+            RawValue::Null => ListValue::Null,
+            RawValue::List(items) => {
+                let content = items.into_iter().map(|v| self.raw_to_list_value(v)).collect();
+
+                ListValue::List(self.list_storage.add(content))
+            }
+        }
+    }
+
+    /// Text goes to the storage of the given frame, or to the main one if there's none
+    fn raw_to_dynamic(&mut self, value : RawValue, frame_index : Option<usize>) -> DynamicValue {
+        match value {
+            RawValue::Integer(i) => DynamicValue::Integer(i),
+            RawValue::Number(n) => DynamicValue::Number(n),
+            RawValue::Boolean(b) => DynamicValue::Boolean(b),
+            RawValue::Null => DynamicValue::Null,
+            RawValue::Text(t) => {
+                match frame_index {
+                    Some(i) => DynamicValue::Text(self.callstack[i].string_storage.add_string(t)),
+                    None => DynamicValue::Text(self.main_storage.add_string(t)),
+                }
+            }
+            RawValue::List(items) => {
+                let content = items.into_iter().map(|v| self.raw_to_list_value(v)).collect();
+
+                DynamicValue::List(self.list_storage.add(content))
+            }
+        }
+    }
+
+    fn call_native(&mut self, id : u64) -> Result<(), RuntimeError> {
+        let index = match self.get_last_ready_index() {
+            Some(i) => i,
+            None => return Err(RuntimeError::new("Nenhuma função em execução"))
+        };
+
+        let native = match self.natives.iter().position(|n| n.id == id) {
+            Some(n) => n,
+            None => return Err(RuntimeError::new(format!("A função nativa com ID {} não foi registrada", id))),
+        };
+
+        let mut args = vec![];
+
+        for i in 0..self.natives[native].params.len() {
+            let param = self.natives[native].params[i];
+
+            match self.read_var(index, param) {
+                Ok(Some(v)) => args.push(v),
+                Ok(None) => return Err(RuntimeError::new(format!("ID {} not found", param))),
+                Err(e) => return Err(e)
+            }
+        }
+
+        let result = match (self.natives[native].function)(args) {
+            Ok(v) => v,
+            Err(message) => return Err(RuntimeError::new(message)),
+        };
+
+        let value = self.raw_to_dynamic(result, None);
+
+        match self.push_main(value) {
+            Some(_) => Ok(()),
+            None => Err(RuntimeError::new("Main stack overflow"))
+        }
+    }
+
+    fn conv_to_string(&mut self, val : DynamicValue) -> Result<String, RuntimeError> {
+        match val {
+            DynamicValue::Text(t) => {
+                let s = match self.main_storage.get(t) {
+                    Some(s) => s,
+                    None => return Err(RuntimeError::new("Invalid string ID")),
+                };
+
+                Ok(s)
+            }
+            DynamicValue::Integer(i) => Ok(format!("{}", i)),
+            DynamicValue::Number(n) => Ok(format!("{}", n)),
+            DynamicValue::List(l) => self.list_to_string(l),
+            DynamicValue::Boolean(b) => Ok(VirtualMachine::bool_to_str(b).to_owned()),
+            DynamicValue::Null => Ok(String::from("<Null>")),
+        }
+    }
+
+    fn conv_to_int(&mut self, val : DynamicValue) -> Result<IntegerType, RuntimeError> {
         match val {
             DynamicValue::Text(t) => {
                 let text = match self.main_storage.get(t) {
                     Some(v) => v,
-                    None => return Err("Invalid text id".to_owned())
+                    None => return Err(RuntimeError::new("Invalid text id"))
+                };
+
+                let i = match text.parse::<IntegerType>() {
+                    Ok(i) => i,
+                    Err(_) => return Err(RuntimeError::new(format!("Não foi possível converter \"{}\" pra Int", text)))
+                };
+
+                Ok(i)
+            }
+            DynamicValue::Number(n) => Ok(n as IntegerType),
+            DynamicValue::Integer(i) => Ok(i),
+            DynamicValue::List(_) => Err(RuntimeError::new("Não é possível converter uma lista pra Int")),
+            DynamicValue::Boolean(b) => Ok(if b { 1 } else { 0 }),
+            DynamicValue::Null => Err(RuntimeError::new("Convert : <Null>")),
+        }
+    }
+
+    fn conv_to_num(&mut self, val : DynamicValue) -> Result<f64, RuntimeError> {
+        match val {
+            DynamicValue::Text(t) => {
+                let text = match self.main_storage.get(t) {
+                    Some(v) => v,
+                    None => return Err(RuntimeError::new("Invalid text id"))
                 };
 
                 let n = match text.parse::<f64>() {
                     Ok(n) => n,
-                    Err(_) => return Err(format!("Não foi possível converter \"{}\" pra Num", text))
+                    Err(_) => return Err(RuntimeError::new(format!("Não foi possível converter \"{}\" pra Num", text)))
                 };
 
                 Ok(n)
             }
             DynamicValue::Number(n) => Ok(n),
             DynamicValue::Integer(i) => Ok(i as f64),
-            DynamicValue::Null => return Err("Convert : <Null>".to_owned()),
+            DynamicValue::List(_) => Err(RuntimeError::new("Não é possível converter uma lista pra Num")),
+            DynamicValue::Boolean(b) => Ok(if b { 1.0 } else { 0.0 }),
+            DynamicValue::Null => Err(RuntimeError::new("Convert : <Null>")),
         }
     }
 
-    pub fn run(&mut self, inst : &Instruction) -> Result<ExecutionStatus, String> {
+    fn jump_to(&mut self, address : usize) -> Result<(), RuntimeError> {
+        match self.get_last_ready_mut() {
+            Some(f) => f.program_counter = address,
+            None => return Err(RuntimeError::new("Nenhuma função em execução"))
+        }
+
+        Ok(())
+    }
+
+    fn bool_to_str(b : bool) -> &'static str {
+        if b {
+            "VERDADE"
+        } else {
+            "MENTIRA"
+        }
+    }
+
+    fn pop_main_bool(&mut self) -> Result<bool, RuntimeError> {
+        match self.pop_main() {
+            Some(DynamicValue::Boolean(b)) => Ok(b),
+            Some(v) => Err(RuntimeError::new(format!("Era esperado um BIRLEANO, mas foi encontrado {:?}", v))),
+            None => Err(RuntimeError::new("Main stack underflow")),
+        }
+    }
+
+    // Pops two values and pushes whether their comparision is one of the expected ones
+    fn compare_main_top_to_bool(&mut self, expected : &[Comparision]) -> Result<(), RuntimeError> {
+        let right = match self.pop_main() {
+            Some(v) => v,
+            None => return Err(RuntimeError::new("Main stack vazia")),
+        };
+
+        let left = match self.pop_main() {
+            Some(v) => v,
+            None => return Err(RuntimeError::new("Main stack vazia")),
+        };
+
+        let result = self.compare(left, right)?;
+
+        self.drop_value(left);
+        self.drop_value(right);
+
+        match self.push_main(DynamicValue::Boolean(expected.contains(&result))) {
+            Some(_) => Ok(()),
+            None => Err(RuntimeError::new("Main stack overflow")),
+        }
+    }
+
+    fn list_to_string(&self, id : ListStorageID) -> Result<String, RuntimeError> {
+        let mut visited = vec![];
+
+        self.list_to_string_visiting(id, &mut visited)
+    }
+
+    // A list can end up inside itself, so keep track of the ones being printed to not loop forever
+    fn list_to_string_visiting(&self, id : ListStorageID, visited : &mut Vec<ListStorageID>)
+        -> Result<String, RuntimeError> {
+        if visited.contains(&id) {
+            return Ok(String::from("[...]"));
+        }
+
+        let list = match self.list_storage.get_ref(id) {
+            Some(l) => l,
+            None => return Err(RuntimeError::new(format!("ListID {} é inválida.", id))),
+        };
+
+        visited.push(id);
+
+        let mut parts = vec![];
+
+        for value in list {
+            let part = match value {
+                ListValue::Integer(i) => format!("{}", i),
+                ListValue::Number(n) => format!("{}", n),
+                ListValue::Text(t) => format!("\"{}\"", t),
+                ListValue::List(l) => self.list_to_string_visiting(*l, visited)?,
+                ListValue::Boolean(b) => VirtualMachine::bool_to_str(*b).to_owned(),
+                ListValue::Null => String::from("<Null>"),
+            };
+
+            parts.push(part);
+        }
+
+        visited.pop();
+
+        Ok(format!("[{}]", parts.join(", ")))
+    }
+
+    fn dynamic_to_list_value(&mut self, val : DynamicValue) -> Result<ListValue, RuntimeError> {
+        match val {
+            DynamicValue::Integer(i) => Ok(ListValue::Integer(i)),
+            DynamicValue::Number(n) => Ok(ListValue::Number(n)),
+            DynamicValue::Text(t) => {
+                match self.main_storage.get(t) {
+                    Some(text) => Ok(ListValue::Text(text)),
+                    None => Err(RuntimeError::new(format!("TextID {} é inválida.", t))),
+                }
+            }
+            DynamicValue::List(l) => Ok(ListValue::List(l)),
+            DynamicValue::Boolean(b) => Ok(ListValue::Boolean(b)),
+            DynamicValue::Null => Ok(ListValue::Null),
+        }
+    }
+
+    fn list_value_to_dynamic(&mut self, val : ListValue) -> DynamicValue {
+        match val {
+            ListValue::Integer(i) => DynamicValue::Integer(i),
+            ListValue::Number(n) => DynamicValue::Number(n),
+            ListValue::Text(t) => DynamicValue::Text(self.main_storage.add_string(t)),
+            ListValue::List(l) => DynamicValue::List(l),
+            ListValue::Boolean(b) => DynamicValue::Boolean(b),
+            ListValue::Null => DynamicValue::Null,
+        }
+    }
+
+    fn pop_main_list(&mut self) -> Result<ListStorageID, RuntimeError> {
+        match self.pop_main() {
+            Some(DynamicValue::List(l)) => Ok(l),
+            Some(v) => Err(RuntimeError::new(format!("Era esperada uma lista, mas foi encontrado {:?}", v))),
+            None => Err(RuntimeError::new("Main stack underflow")),
+        }
+    }
+
+    fn get_list(&self, id : ListStorageID) -> Result<&Vec<ListValue>, RuntimeError> {
+        match self.list_storage.get_ref(id) {
+            Some(l) => Ok(l),
+            None => Err(RuntimeError::new(format!("ListID {} é inválida.", id))),
+        }
+    }
+
+    pub fn run(&mut self, inst : &Instruction) -> Result<ExecutionStatus, RuntimeError> {
+        match self.check_limits(inst) {
+            Ok(_) => {}
+            Err(e) => return Err(e)
+        }
+
+        let status = self.run_instruction(inst)?;
+
+        // Text is only checked after it's made, since the size isn't known before
+        if let Some(max) = self.limits.max_string_bytes {
+            if self.get_string_bytes() > max {
+                return Err(RuntimeError::limit_exceeded(Limit::StringBytes,
+                    format!("Limite de memória pra texto excedido ({} bytes)", max)));
+            }
+        }
+
+        if let Some(max) = self.limits.max_list_items {
+            if self.list_storage.items > max {
+                return Err(RuntimeError::limit_exceeded(Limit::ListItems,
+                    format!("Limite de elementos em listas excedido ({})", max)));
+            }
+        }
+
+        Ok(status)
+    }
+
+    fn run_instruction(&mut self, inst : &Instruction) -> Result<ExecutionStatus, RuntimeError> {
         if self.get_current_skip_level() > 0 {
-            if let &Instruction::EndExecuteIf = inst {
-                self.decrease_skip_level()?;
+            // Keep track of nested scopes, so only the matching end stops the skipping
+            match inst {
+                Instruction::EndExecuteIf => self.decrease_skip_level()?,
+                Instruction::ExecuteIfEqual |
+                Instruction::ExecuteIfNotEqual |
+                Instruction::ExecuteIfGreater |
+                Instruction::ExecuteIfGreaterOrEqual |
+                Instruction::ExecuteIfLess |
+                Instruction::ExecuteIfLessOrEqual |
+                Instruction::ExecuteIfTrue => self.increase_skip_level()?,
+                // Only the else of the block that started the skipping gets executed
+                Instruction::ExecuteElse if self.get_current_skip_level() == 1 => self.decrease_skip_level()?,
+                _ => {}
             }
 
             return Ok(ExecutionStatus::Normal);
@@ -819,7 +1694,7 @@ impl VirtualMachine {
 
                 match self.push_main(dyn) {
                     Some(_) => {}
-                    None => return Err("Main stack overflow".to_owned()),
+                    None => return Err(RuntimeError::new("Main stack overflow")),
                 }
             }
             Instruction::PushMainNum(n) => {
@@ -827,7 +1702,7 @@ impl VirtualMachine {
 
                 match self.push_main(dyn) {
                     Some(_) => {}
-                    None => return Err("Main stack overflow".to_owned()),
+                    None => return Err(RuntimeError::new("Main stack overflow")),
                 }
             }
             Instruction::PushMainStr(str) => {
@@ -837,24 +1712,24 @@ impl VirtualMachine {
 
                 match self.push_main(dyn) {
                     Some(_) => {}
-                    None => return Err("Main stack overflow".to_owned()),
+                    None => return Err(RuntimeError::new("Main stack overflow")),
                 }
             }
             Instruction::PushNull => {
                 match self.push_main(DynamicValue::Null) {
                     Some(_) => {}
-                    None => return Err("Main stack overflow".to_owned()),
+                    None => return Err(RuntimeError::new("Main stack overflow")),
                 }
             }
             Instruction::MainAdd => {
                 let right = match self.pop_main() {
                     Some(v) => v,
-                    None => return Err("Main stack is empty".to_owned())
+                    None => return Err(RuntimeError::new("Main stack is empty"))
                 };
 
                 let left = match self.pop_main() {
                     Some(v) => v,
-                    None => return Err("Main stack is empty".to_owned())
+                    None => return Err(RuntimeError::new("Main stack is empty"))
                 };
 
                 let result = match self.add_values(left, right) {
@@ -864,18 +1739,18 @@ impl VirtualMachine {
 
                 match self.push_main(result) {
                     Some(_) => {}
-                    None => return Err("Main stack overflow".to_owned()),
+                    None => return Err(RuntimeError::new("Main stack overflow")),
                 };
             }
             Instruction::MainSub => {
                 let right = match self.pop_main() {
                     Some(v) => v,
-                    None => return Err("Main stack is empty".to_owned())
+                    None => return Err(RuntimeError::new("Main stack is empty"))
                 };
 
                 let left = match self.pop_main() {
                     Some(v) => v,
-                    None => return Err("Main stack is empty".to_owned())
+                    None => return Err(RuntimeError::new("Main stack is empty"))
                 };
 
                 let result = match self.sub_values(left, right) {
@@ -885,18 +1760,18 @@ impl VirtualMachine {
 
                 match self.push_main(result) {
                     Some(_) => {}
-                    None => return Err("Main stack overflow".to_owned()),
+                    None => return Err(RuntimeError::new("Main stack overflow")),
                 };
             }
             Instruction::MainDiv => {
                 let right = match self.pop_main() {
                     Some(v) => v,
-                    None => return Err("Main stack is empty".to_owned())
+                    None => return Err(RuntimeError::new("Main stack is empty"))
                 };
 
                 let left = match self.pop_main() {
                     Some(v) => v,
-                    None => return Err("Main stack is empty".to_owned())
+                    None => return Err(RuntimeError::new("Main stack is empty"))
                 };
 
                 let result = match self.div_values(left, right) {
@@ -906,18 +1781,18 @@ impl VirtualMachine {
 
                 match self.push_main(result) {
                     Some(_) => {}
-                    None => return Err("Main stack overflow".to_owned()),
+                    None => return Err(RuntimeError::new("Main stack overflow")),
                 };
             }
             Instruction::MainMul => {
                 let right = match self.pop_main() {
                     Some(v) => v,
-                    None => return Err("Main stack is empty".to_owned())
+                    None => return Err(RuntimeError::new("Main stack is empty"))
                 };
 
                 let left = match self.pop_main() {
                     Some(v) => v,
-                    None => return Err("Main stack is empty".to_owned())
+                    None => return Err(RuntimeError::new("Main stack is empty"))
                 };
 
                 let result = match self.mul_values(left, right) {
@@ -927,13 +1802,83 @@ impl VirtualMachine {
 
                 match self.push_main(result) {
                     Some(_) => {}
-                    None => return Err("Main stack overflow".to_owned()),
+                    None => return Err(RuntimeError::new("Main stack overflow")),
+                };
+            }
+            Instruction::PushMainBool(b) => {
+                match self.push_main(DynamicValue::Boolean(*b)) {
+                    Some(_) => {}
+                    None => return Err(RuntimeError::new("Main stack overflow")),
+                }
+            }
+            Instruction::MainEqual => self.compare_main_top_to_bool(&[Comparision::Equal])?,
+            Instruction::MainNotEqual => {
+                self.compare_main_top_to_bool(&[Comparision::NotEqual, Comparision::LessThan, Comparision::MoreThan])?
+            }
+            Instruction::MainLess => self.compare_main_top_to_bool(&[Comparision::LessThan])?,
+            Instruction::MainLessOrEqual => self.compare_main_top_to_bool(&[Comparision::LessThan, Comparision::Equal])?,
+            Instruction::MainGreater => self.compare_main_top_to_bool(&[Comparision::MoreThan])?,
+            Instruction::MainGreaterOrEqual => self.compare_main_top_to_bool(&[Comparision::MoreThan, Comparision::Equal])?,
+            Instruction::MainAnd | Instruction::MainOr => {
+                let right = self.pop_main_bool()?;
+                let left = self.pop_main_bool()?;
+
+                let result = match inst {
+                    Instruction::MainAnd => left && right,
+                    _ => left || right,
+                };
+
+                match self.push_main(DynamicValue::Boolean(result)) {
+                    Some(_) => {}
+                    None => return Err(RuntimeError::new("Main stack overflow")),
+                }
+            }
+            Instruction::MainNot => {
+                let value = self.pop_main_bool()?;
+
+                match self.push_main(DynamicValue::Boolean(!value)) {
+                    Some(_) => {}
+                    None => return Err(RuntimeError::new("Main stack overflow")),
+                }
+            }
+            Instruction::MainMod | Instruction::MainPow => {
+                let right = match self.pop_main() {
+                    Some(v) => v,
+                    None => return Err(RuntimeError::new("Main stack is empty"))
+                };
+
+                let left = match self.pop_main() {
+                    Some(v) => v,
+                    None => return Err(RuntimeError::new("Main stack is empty"))
+                };
+
+                let result = match inst {
+                    Instruction::MainMod => self.mod_values(left, right)?,
+                    _ => self.pow_values(left, right)?,
+                };
+
+                match self.push_main(result) {
+                    Some(_) => {}
+                    None => return Err(RuntimeError::new("Main stack overflow")),
+                };
+            }
+            Instruction::MainNeg => {
+                let value = match self.pop_main() {
+                    Some(v) => v,
+                    None => return Err(RuntimeError::new("Main stack is empty"))
+                };
+
+                let result = self.neg_value(value)?;
+
+                match self.push_main(result) {
+                    Some(_) => {}
+                    None => return Err(RuntimeError::new("Main stack overflow")),
                 };
             }
             Instruction::MainPrint => {
                 let top = match self.get_main_top() {
                     Some(t) => t,
-                    None => return Err("MainPrint : Main stack is empty".to_owned()),
+                    None => return Err(RuntimeError::new("MainPrint : Main stack is empty")),
                 };
 
                 match top {
@@ -942,11 +1887,17 @@ impl VirtualMachine {
                     DynamicValue::Text(t) => {
                         let t = match self.main_storage.get_ref(t) {
                             Some(t) => t,
-                            None => return Err(format!("MainPrint : Não foi encontrado text com ID {}", t)),
+                            None => return Err(RuntimeError::new(format!("MainPrint : Não foi encontrado text com ID {}", t))),
                         };
 
                         vm_write!(self.stdout, "{}", t)?
                     }
+                    DynamicValue::List(l) => {
+                        let text = self.list_to_string(l)?;
+
+                        vm_write!(self.stdout, "{}", text)?
+                    }
+                    DynamicValue::Boolean(b) => vm_write!(self.stdout, "{}", VirtualMachine::bool_to_str(b))?,
                     DynamicValue::Null => vm_write!(self.stdout, "<Null>")?,
                 }
             }
@@ -956,6 +1907,12 @@ impl VirtualMachine {
                     Err(e) => return Err(e)
                 }
             }
+            Instruction::PopMain => {
+                match self.pop_main() {
+                    Some(v) => self.drop_value(v),
+                    None => return Err(RuntimeError::new("PopMain : Main stack is empty")),
+                }
+            }
             Instruction::PrintNewLine => {
                 vm_write!(self.stdout, "\n")?
             }
@@ -970,7 +1927,7 @@ impl VirtualMachine {
             Instruction::ReadVarWithId(id) => {
                 let index = match self.get_last_ready_index() {
                     Some(i) => i,
-                    None => return Err("Nenhuma função em execução".to_owned())
+                    None => return Err(RuntimeError::new("Nenhuma função em execução"))
                 };
 
                 match self.read_from_id_to_main(index, *id) {
@@ -981,7 +1938,7 @@ impl VirtualMachine {
             Instruction::WriteToVarWithId(id) => {
                 let index = match self.get_last_ready_index() {
                     Some(i) => i,
-                    None => return Err("Nenhuma função em execução".to_owned())
+                    None => return Err(RuntimeError::new("Nenhuma função em execução"))
                 };
 
                 match self.write_main_top_to(index, *id) {
@@ -1011,12 +1968,12 @@ impl VirtualMachine {
             Instruction::CompareMainTop => {
                 let right = match self.pop_main() {
                     Some(v) => v,
-                    None => return Err("Main stack vazia".to_owned()),
+                    None => return Err(RuntimeError::new("Main stack vazia")),
                 };
 
                 let left = match self.pop_main() {
                     Some(v) => v,
-                    None => return Err("Main stack vazia".to_owned()),
+                    None => return Err(RuntimeError::new("Main stack vazia")),
                 };
 
                 let result = match self.compare(left, right) {
@@ -1024,6 +1981,9 @@ impl VirtualMachine {
                     Err(e) => return Err(e),
                 };
 
+                self.drop_value(left);
+                self.drop_value(right);
+
                 match self.set_last_comparision(result) {
                     Ok(_) => {}
                     Err(e) => return Err(e)
@@ -1036,26 +1996,54 @@ impl VirtualMachine {
                     return Ok(ExecutionStatus::Quit);
                 }
 
-                if self.main_stack_top == 0 {
-                    self.push_main(DynamicValue::Null);
-                }
+                let base = self.callstack.last().unwrap().main_stack_base;
 
+                // The caller is the last ready frame before this one. Frames between them are calls
+                // still getting their arguments
                 let len = self.callstack.len();
-                match self.write_main_top_to(len - 2, BIRL_RET_VAL_VAR_ID) {
-                    Ok(_) => {}
-                    Err(e) => return Err(e),
+
+                if !self.callstack[.. len - 1].iter().any(|f| f.ready) {
+                    return Err(RuntimeError::new("Return : Nenhuma função pra retornar"));
+                }
+
+                let value = if self.main_stack_top > base {
+                    self.main_stack_top -= 1;
+                    self.main_stack[self.main_stack_top]
+                } else {
+                    DynamicValue::Null
+                };
+
+                // Anything else the function left on the main stack is garbage for the caller, which
+                // finds the value on top of it. Calls used as commands write it to TREZE
+                self.truncate_main(base);
+
+                match self.push_main(value) {
+                    Some(_) => {}
+                    None => return Err(RuntimeError::new("Main stack overflow")),
                 }
 
                 let _ = self.callstack.pop();
 
                 return Ok(ExecutionStatus::Returned);
             }
+            Instruction::CallNative(id) => {
+                match self.call_native(*id) {
+                    Ok(_) => {}
+                    Err(e) => return Err(e)
+                }
+            }
             Instruction::CreateVarWithId(id) => {
                 match self.create_runtime_var(*id) {
                     Ok(_) => {}
                     Err(e) => return Err(e)
                 }
             }
+            Instruction::Jump(address) => {
+                match self.jump_to(*address) {
+                    Ok(_) => {}
+                    Err(e) => return Err(e)
+                }
+            }
             Instruction::ExecuteIfEqual => {
                 if self.get_current_skip_level() > 0 {
                     self.increase_skip_level()?;
@@ -1140,6 +2128,17 @@ impl VirtualMachine {
                     }
                 }
             }
+            Instruction::ExecuteIfTrue => {
+                let condition = self.pop_main_bool()?;
+
+                if !condition {
+                    self.increase_skip_level()?;
+                }
+            }
+            Instruction::ExecuteElse => {
+                // The condition was true, so skip the alternative block
+                self.increase_skip_level()?;
+            }
             Instruction::MakeNewFrame(id) => {
                 // Add a new, not ready frame to the callstack
 
@@ -1151,38 +2150,57 @@ impl VirtualMachine {
                 // Set the last frame to ready
 
                 if ! self.callstack.is_empty() {
-                    self.callstack.last_mut().unwrap().ready = true;
+                    let base = self.main_stack_top;
+
+                    let frame = self.callstack.last_mut().unwrap();
+
+                    frame.ready = true;
+                    frame.main_stack_base = base;
                 } else {
-                    return Err("Callstack vazia".to_owned());
+                    return Err(RuntimeError::new("Callstack vazia"));
                 }
             }
             Instruction::AssertMainTopTypeCompatible(kind) => {
                 let v = match self.get_main_top() {
                     Some(v) => v,
-                    None => return Err("AssertMainTopType : Main stack vazia".to_owned()),
+                    None => return Err(RuntimeError::new("AssertMainTopType : Main stack vazia")),
                 };
 
                 match v {
-                    DynamicValue::Null => return Err("Tipo incompatível : Null".to_owned()),
+                    DynamicValue::Null => return Err(RuntimeError::new("Tipo incompatível : Null")),
+                    DynamicValue::Boolean(_) => {
+                        if kind == &TypeKind::Boolean {
+                            // Ok
+                        } else {
+                            return Err(RuntimeError::new("Tipo incompatível : BIRLEANO"));
+                        }
+                    }
+                    DynamicValue::List(_) => {
+                        if kind == &TypeKind::List {
+                            // Ok
+                        } else {
+                            return Err(RuntimeError::new("Tipo incompatível : Lista"));
+                        }
+                    }
                     DynamicValue::Text(_) => {
                         if kind == &TypeKind::Text {
                             // Ok
                         } else {
-                            return Err("Tipo incompatível : Texto".to_owned());
+                            return Err(RuntimeError::new("Tipo incompatível : Texto"));
                         }
                     }
                     DynamicValue::Integer(_) => {
                         if kind == &TypeKind::Integer || kind == &TypeKind::Number {
                             // Ok
                         } else {
-                            return Err("Tipo incompatível : Int ou Num".to_owned());
+                            return Err(RuntimeError::new("Tipo incompatível : Int ou Num"));
                         }
                     }
                     DynamicValue::Number(_) => {
                         if kind == &TypeKind::Number {
                             // Ok
                         } else {
-                            return Err("Tipo incompatível : Number".to_owned());
+                            return Err(RuntimeError::new("Tipo incompatível : Number"));
                         }
                     }
                 }
@@ -1192,7 +2210,7 @@ impl VirtualMachine {
                     let mut line = String::new();
                     match input.read_line(&mut line) {
                         Ok(_) => {}
-                        Err(e) => return Err(format!("Erro lendo input : {:?}", e))
+                        Err(e) => return Err(RuntimeError::new(format!("Erro lendo input : {:?}", e)))
                     };
 
                     // FIXME
@@ -1206,14 +2224,14 @@ impl VirtualMachine {
                     let id = self.main_storage.add_string(line);
                     match self.push_main(DynamicValue::Text(id)) {
                         Some(_) => {}
-                        None => return Err("Main stack overflow".to_owned())
+                        None => return Err(RuntimeError::new("Main stack overflow"))
                     };
                 }
             }
             Instruction::ConvertToNum => {
                 let top = match self.pop_main() {
                     Some(v) => v,
-                    None => return Err("Main stack underflow".to_owned())
+                    None => return Err(RuntimeError::new("Main stack underflow"))
                 };
 
                 let v = match self.conv_to_num(top) {
@@ -1223,13 +2241,13 @@ impl VirtualMachine {
 
                 match self.push_main(DynamicValue::Number(v)) {
                     Some(_) => {}
-                    None => return Err("Main stack overflow".to_owned())
+                    None => return Err(RuntimeError::new("Main stack overflow"))
                 }
             }
             Instruction::ConvertToInt => {
                 let top = match self.pop_main() {
                     Some(v) => v,
-                    None => return Err("Main stack underflow".to_owned())
+                    None => return Err(RuntimeError::new("Main stack underflow"))
                 };
 
                 let v = match self.conv_to_int(top) {
@@ -1239,13 +2257,13 @@ impl VirtualMachine {
 
                 match self.push_main(DynamicValue::Integer(v)) {
                     Some(_) => {}
-                    None => return Err("Main stack overflow".to_owned())
+                    None => return Err(RuntimeError::new("Main stack overflow"))
                 }
             }
             Instruction::ConvertToString => {
                 let top = match self.pop_main() {
                     Some(v) => v,
-                    None => return Err("Main stack underflow".to_owned())
+                    None => return Err(RuntimeError::new("Main stack underflow"))
                 };
 
                 let v = match self.conv_to_string(top) {
@@ -1257,7 +2275,97 @@ impl VirtualMachine {
 
                 match self.push_main(DynamicValue::Text(id)) {
                     Some(_) => {}
-                    None => return Err("Main stack overflow".to_owned())
+                    None => return Err(RuntimeError::new("Main stack overflow"))
+                }
+            }
+            Instruction::MakeList(len) => {
+                let mut content = vec![];
+
+                for _ in 0..*len {
+                    let v = match self.pop_main() {
+                        Some(v) => v,
+                        None => return Err(RuntimeError::new("MakeList : Main stack underflow"))
+                    };
+
+                    content.push(self.dynamic_to_list_value(v)?);
+                }
+
+                // The first element was the first to be pushed, so it's the last one to come out
+                content.reverse();
+
+                let id = self.list_storage.add(content);
+
+                match self.push_main(DynamicValue::List(id)) {
+                    Some(_) => {}
+                    None => return Err(RuntimeError::new("Main stack overflow"))
+                }
+            }
+            Instruction::ListGet => {
+                let index = match self.pop_main() {
+                    Some(DynamicValue::Integer(i)) => i,
+                    Some(v) => return Err(RuntimeError::new(format!("O índice de uma lista deve ser Int, não {:?}", v))),
+                    None => return Err(RuntimeError::new("Main stack underflow"))
+                };
+
+                let list = self.pop_main_list()?;
+
+                let value = {
+                    let content = self.get_list(list)?;
+
+                    if index < 0 || index as usize >= content.len() {
+                        return Err(RuntimeError::new(format!("Índice {} fora da lista de tamanho {}", index, content.len())));
+                    }
+
+                    content[index as usize].clone()
+                };
+
+                let v = self.list_value_to_dynamic(value);
+
+                match self.push_main(v) {
+                    Some(_) => {}
+                    None => return Err(RuntimeError::new("Main stack overflow"))
+                }
+            }
+            Instruction::ListPush => {
+                let value = match self.pop_main() {
+                    Some(v) => v,
+                    None => return Err(RuntimeError::new("Main stack underflow"))
+                };
+
+                let list = self.pop_main_list()?;
+                let value = self.dynamic_to_list_value(value)?;
+
+                if !self.list_storage.push(list, value) {
+                    return Err(RuntimeError::new(format!("ListID {} é inválida.", list)));
+                }
+            }
+            Instruction::ListPop => {
+                let list = self.pop_main_list()?;
+
+                if self.get_list(list)?.is_empty() {
+                    return Err(RuntimeError::new("Não dá pra tirar nada de uma lista vazia"));
+                }
+
+                let value = match self.list_storage.pop(list) {
+                    Some(v) => v,
+                    None => return Err(RuntimeError::new(format!("ListID {} é inválida.", list))),
+                };
+
+                let v = self.list_value_to_dynamic(value);
+
+                match self.push_main(v) {
+                    Some(_) => {}
+                    None => return Err(RuntimeError::new("Main stack overflow"))
+                }
+            }
+            Instruction::ListLength => {
+                let list = self.pop_main_list()?;
+
+                let len = self.get_list(list)?.len();
+
+                match self.push_main(DynamicValue::Integer(len as IntegerType)) {
+                    Some(_) => {}
+                    None => return Err(RuntimeError::new("Main stack overflow"))
                 }
             }
         }
@@ -1271,12 +2379,31 @@ pub enum Instruction {
     PushMainInt(IntegerType),
     PushMainNum(f64),
     PushMainStr(String),
+    PushMainBool(bool),
     PushNull,
     MainAdd,
     MainSub,
     MainDiv,
     MainMul,
+    MainMod,
+    MainPow,
+    // Negate the value on the top of the main stack
+    MainNeg,
+    // Pop two values, compare them and push the result as a boolean
+    MainEqual,
+    MainNotEqual,
+    MainLess,
+    MainLessOrEqual,
+    MainGreater,
+    MainGreaterOrEqual,
+    // Logical operators. Only work on booleans
+    MainAnd,
+    MainOr,
+    MainNot,
     MainPrint,
+    // Drop the value on the top of the main stack, freeing its text. The prints only read it, so the
+    // compiler emits this after them to keep the stack and the text storage from growing inside loops
+    PopMain,
     PrintNewLine,
     MainPrintDebug,
     FlushStdout,
@@ -1296,8 +2423,16 @@ pub enum Instruction {
     ExecuteIfGreaterOrEqual,
     ExecuteIfLess,
     ExecuteIfLessOrEqual,
+    // Pop a boolean and execute the block only if it's true
+    ExecuteIfTrue,
+    // Start the alternative block of the current conditional, executing it only if the block before
+    // it was skipped
+    ExecuteElse,
     MakeNewFrame(u64),
     SetLastFrameReady,
+    // Set the program counter of the current function to the given address. Used to go back to the
+    // condition at the end of loops
+    Jump(usize),
     // For use when pushing arguments for a function. Check if the value on the top of the main stack
     // has a compatible type
     AssertMainTopTypeCompatible(TypeKind),
@@ -1309,4 +2444,160 @@ pub enum Instruction {
     ConvertToNum,
     // Turn the main stack top into int
     ConvertToInt,
+    // Pop the given number of values and push a new list with them, in the order they were pushed
+    MakeList(usize),
+    // Pop an index and a list and push the element of the list at that index
+    ListGet,
+    // Pop a value and a list and add the value to the end of the list
+    ListPush,
+    // Pop a list, remove its last element and push it
+    ListPop,
+    // Pop a list and push its length
+    ListLength,
+    // Run the native function registered for the given function id with the arguments of the
+    // current frame and push what it returns
+    CallNative(u64),
+}
+
+mod tests {
+    #[test]
+    fn text_storage() {
+        use vm::{ VirtualMachine, FunctionFrame, Instruction };
+
+        let mut vm = VirtualMachine::new();
+
+        let mut frame = FunctionFrame::new(1);
+        frame.ready = true;
+        vm.callstack.push(frame);
+
+        // Printing, writing and comparing consume the text, so the main storage ends up empty and
+        // the variable keeps only its last value, however many times it runs
+        let mut body = vec![];
+
+        for _ in 0..3 {
+            body.push(Instruction::PushMainStr("X".to_owned()));
+            body.push(Instruction::MainPrint);
+            body.push(Instruction::PopMain);
+            body.push(Instruction::CreateVarWithId(1));
+            body.push(Instruction::PushMainStr("A".to_owned()));
+            body.push(Instruction::WriteToVarWithId(1));
+            body.push(Instruction::PushMainStr("B".to_owned()));
+            body.push(Instruction::WriteToVarWithId(1));
+            body.push(Instruction::ReadVarWithId(1));
+            body.push(Instruction::PushMainStr("B".to_owned()));
+            body.push(Instruction::MainEqual);
+            body.push(Instruction::PopMain);
+        }
+
+        for inst in &body {
+            if let Err(e) = vm.run(inst) {
+                panic!("Erro executando {:?} : {}", inst, e);
+            }
+        }
+
+        assert_eq!(vm.main_stack_top, 0);
+        assert!(vm.main_storage.entries.is_empty());
+        assert_eq!(vm.callstack[0].string_storage.entries.len(), 1);
+        assert_eq!(vm.callstack[0].string_storage.bytes, 1);
+    }
+
+    #[test]
+    fn lists() {
+        use vm::{ VirtualMachine, FunctionFrame, Instruction };
+        use context::RawValue;
+        use error::RuntimeError;
+
+        let mut vm = VirtualMachine::new();
+
+        let mut frame = FunctionFrame::new(1);
+        frame.ready = true;
+        vm.callstack.push(frame);
+
+        fn run(vm : &mut VirtualMachine, body : Vec<Instruction>) -> Result<(), RuntimeError> {
+            for inst in &body {
+                vm.run(inst)?;
+            }
+
+            Ok(())
+        }
+
+        // The elements keep the order they were pushed in, and a list can hold another list
+        let make = vec![
+            Instruction::PushMainInt(1),
+            Instruction::PushMainStr("A".to_owned()),
+            Instruction::PushMainInt(2),
+            Instruction::MakeList(1),
+            Instruction::MakeList(3),
+        ];
+
+        run(&mut vm, make.clone()).unwrap();
+
+        let expected = RawValue::List(vec![
+            RawValue::Integer(1),
+            RawValue::Text("A".to_owned()),
+            RawValue::List(vec![RawValue::Integer(2)]),
+        ]);
+
+        assert_eq!(vm.read_main_top().unwrap(), expected);
+
+        // The text moved into the list, so nothing is left behind in the main storage
+        assert!(vm.main_storage.entries.is_empty());
+        assert_eq!(vm.list_storage.items, 4);
+        assert_eq!(vm.list_storage.bytes, 1);
+
+        vm.run(&Instruction::PopMain).unwrap();
+
+        // Indexing copies the element out, text included
+        let mut get = make.clone();
+        get.push(Instruction::PushMainInt(1));
+        get.push(Instruction::ListGet);
+
+        run(&mut vm, get).unwrap();
+        assert_eq!(vm.read_main_top().unwrap(), RawValue::Text("A".to_owned()));
+        vm.run(&Instruction::PopMain).unwrap();
+
+        let mut nested = make.clone();
+        nested.push(Instruction::PushMainInt(2));
+        nested.push(Instruction::ListGet);
+        nested.push(Instruction::PushMainInt(0));
+        nested.push(Instruction::ListGet);
+
+        run(&mut vm, nested).unwrap();
+        assert_eq!(vm.read_main_top().unwrap(), RawValue::Integer(2));
+        vm.run(&Instruction::PopMain).unwrap();
+
+        // Indexes out of the list and of the wrong type are errors, not panics
+        for index in [Instruction::PushMainInt(3), Instruction::PushMainInt(-1), Instruction::PushMainNum(0.0)] {
+            let mut body = make.clone();
+            body.push(index);
+            body.push(Instruction::ListGet);
+
+            assert!(run(&mut vm, body).is_err());
+        }
+
+        // Text pushed into a list comes back out when it's popped, and popping an empty list fails
+        let popped = vec![
+            Instruction::MakeList(0),
+            Instruction::CreateVarWithId(1),
+            Instruction::WriteToVarWithId(1),
+            Instruction::ReadVarWithId(1),
+            Instruction::PushMainStr("B".to_owned()),
+            Instruction::ListPush,
+            Instruction::ReadVarWithId(1),
+            Instruction::ListLength,
+        ];
+
+        run(&mut vm, popped).unwrap();
+        assert_eq!(vm.read_main_top().unwrap(), RawValue::Integer(1));
+        vm.run(&Instruction::PopMain).unwrap();
+
+        let items = vm.list_storage.items;
+
+        run(&mut vm, vec![Instruction::ReadVarWithId(1), Instruction::ListPop]).unwrap();
+        assert_eq!(vm.read_main_top().unwrap(), RawValue::Text("B".to_owned()));
+        assert_eq!(vm.list_storage.items, items - 1);
+        vm.run(&Instruction::PopMain).unwrap();
+
+        assert!(run(&mut vm, vec![Instruction::ReadVarWithId(1), Instruction::ListPop]).is_err());
+    }
 }
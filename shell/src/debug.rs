@@ -0,0 +1,178 @@
+use birl::context::{Context, FrameInfo};
+use birl::debugger::{Breakpoint, Debugger, StopReason};
+
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Stdin, Write};
+use std::rc::Rc;
+
+const HELP: &str = "\
+Comandos:
+    pare JAULA | LINHA    (b) Para quando a JAULA for chamada ou antes da LINHA
+    tira ID               (d) Remove o ponto de parada ID
+    pontos                (i) Lista os pontos de parada
+    passo                 (s) Vai pra próxima linha, entrando nas JAULAs chamadas
+    pula                  (n) Vai pra próxima linha da JAULA atual
+    sai                   (f) Roda até a JAULA atual retornar
+    continua              (c) Roda até o próximo ponto de parada
+    pilha                 (bt) Mostra as JAULAs em execução
+    mostra NOME           (p) Mostra o valor de uma variável
+    ajuda                 (h) Mostra essa mensagem
+    quit                  (q) Sai do depurador";
+
+/* Standard input, shared between the program being debugged and the
+ * debugger's prompt. Each one buffering stdin on its own would let one of
+ * them swallow lines meant for the other. */
+struct SharedInput(Rc<RefCell<BufReader<Stdin>>>);
+
+impl Read for SharedInput{
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>{
+		self.0.borrow_mut().read(buf)
+	}
+}
+
+/* Prints where the program stopped, along with the source of the line, when
+ * it's known. */
+fn print_location(frame: &FrameInfo, source: &[String]){
+	match frame.line{
+		Some(line) => {
+			let text = source.get(line - 1).map(|l| l.trim()).unwrap_or("");
+			eprintln!("JAULA {}, linha {}: {}", frame.function, line, text);
+		},
+		None => eprintln!("JAULA {}, instrução {}", frame.function, frame.program_counter)
+	}
+}
+
+fn report(debugger: &Debugger, result: Result<StopReason, birl::error::RuntimeError>, source: &[String]){
+	match result{
+		Ok(StopReason::Finished) => eprintln!("O programa terminou."),
+		Ok(StopReason::Breakpoint(id)) => {
+			eprintln!("Ponto de parada {}.", id);
+			if let Some(frame) = debugger.location(){
+				print_location(&frame, source)
+			}
+		},
+		Ok(StopReason::Step) => if let Some(frame) = debugger.location(){
+			print_location(&frame, source)
+		},
+		Err(what) => eprintln!("Erro durante a execução : {}", what)
+	}
+}
+
+/* Runs the file under the debugger, reading commands from standard input
+ * until the user quits or the input ends. */
+pub fn run(file: &str) -> Result<(), String>{
+	let source: Vec<String> = match fs::read_to_string(file){
+		Ok(text) => text.lines().map(String::from).collect(),
+		Err(what) => return Err(format!("Não foi possível ler {}: {}", file, what))
+	};
+
+	let input = Rc::new(RefCell::new(BufReader::new(io::stdin())));
+
+	/* The program's reader can't hold anything beyond what it consumed, or
+	 * the prompt would lose it, hence the one byte buffer. */
+	let mut c = Context::new();
+	let program_input = BufReader::with_capacity(1, SharedInput(input.clone()));
+	let _ = c.set_stdin(Some(Box::new(program_input)));
+	let _ = c.set_stdout(Some(Box::new(io::stdout())));
+
+	if let Err(what) = c.add_file(file){
+		return Err(format!("{}", what))
+	}
+
+	let mut debugger = Debugger::new(c);
+	if let Err(what) = debugger.start(){
+		return Err(format!("{}", what))
+	}
+
+	eprintln!("Depurando {}. Use \"ajuda\" pra ver os comandos.", file);
+
+	loop{
+		eprint!("(depurador) ");
+
+		let mut line = String::new();
+		match input.borrow_mut().read_line(&mut line){
+			Ok(0) => break,
+			Ok(_) => {},
+			Err(what) => return Err(format!("A read error occured: {:?}", what))
+		}
+
+		let mut words = line.split_whitespace();
+		let command = match words.next(){
+			Some(command) => command,
+			None => continue
+		};
+		let argument = words.collect::<Vec<_>>().join(" ");
+
+		/* Anything the program printed must show up before the
+		 * debugger's messages. */
+		let flush = |debugger: &mut Debugger| {
+			if let Some(mut out) = debugger.context_mut().set_stdout(None){
+				let _ = out.flush();
+				debugger.context_mut().set_stdout(Some(out));
+			}
+		};
+
+		match command{
+			"pare" | "b" => {
+				if argument.is_empty(){
+					eprintln!("Passe o nome de uma JAULA ou o número de uma linha.");
+					continue
+				}
+
+				let breakpoint = match argument.parse::<usize>(){
+					Ok(line) => Breakpoint::Line(line),
+					Err(_) => Breakpoint::Function(argument.clone())
+				};
+
+				let id = debugger.add_breakpoint(breakpoint);
+				eprintln!("Ponto de parada {} criado.", id);
+			},
+			"tira" | "d" => match argument.parse::<u32>(){
+				Ok(id) => if !debugger.remove_breakpoint(id){
+					eprintln!("Nenhum ponto de parada com ID {}.", id)
+				},
+				Err(_) => eprintln!("Passe o ID do ponto de parada.")
+			},
+			"pontos" | "i" => for (id, breakpoint) in debugger.breakpoints(){
+				match breakpoint{
+					Breakpoint::Function(name) => eprintln!("{}: JAULA {}", id, name),
+					Breakpoint::Line(line) => eprintln!("{}: linha {}", id, line)
+				}
+			},
+			"passo" | "s" | "pula" | "n" | "sai" | "f" | "continua" | "c" => {
+				let result = match command{
+					"passo" | "s" => debugger.step_in(),
+					"pula" | "n" => debugger.step_over(),
+					"sai" | "f" => debugger.step_out(),
+					_ => debugger.resume()
+				};
+
+				flush(&mut debugger);
+				report(&debugger, result, &source);
+			},
+			"pilha" | "bt" => {
+				if !debugger.is_started() || debugger.is_finished(){
+					eprintln!("O programa não está rodando.");
+					continue
+				}
+
+				/* Newest first, like most debuggers. Frames that aren't
+				 * ready are calls still getting their arguments. */
+				for frame in debugger.callstack().iter().rev().filter(|f| f.ready){
+					print_location(frame, &source)
+				}
+			},
+			"mostra" | "p" => match debugger.read_variable(&argument){
+				Ok(Some(value)) => eprintln!("{} = {}", argument, value),
+				Ok(None) => eprintln!("A variável {} não existe aqui.", argument),
+				Err(what) => eprintln!("{}", what)
+			},
+			"ajuda" | "h" => eprintln!("{}", HELP),
+			"quit" | "q" => break,
+			_ => eprintln!("Comando desconhecido: {}. Use \"ajuda\" pra ver os comandos.", command)
+		}
+	}
+
+	Ok(())
+}
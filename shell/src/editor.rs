@@ -0,0 +1,192 @@
+use birl::context::{Context, BIRL_GLOBAL_FUNCTION_ID};
+use birl::parser::{KeyPhrase, TypeKind};
+
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Editor, Helper};
+
+use std::path::PathBuf;
+
+const HISTORY_FILE: &str = ".birl_history";
+
+/* Completes key phrases at the start of a line and names declared in the
+ * context after the colon. The names are a copy, refreshed before each line
+ * is read, since the editor can't hold on to the context. */
+pub struct BirlHelper{
+	names: Vec<String>,
+	files: FilenameCompleter
+}
+
+impl BirlHelper{
+	fn new() -> BirlHelper{
+		BirlHelper{
+			names: vec![],
+			files: FilenameCompleter::new()
+		}
+	}
+
+	/* Functions, globals and, while a JAULA is being typed, its own
+	 * variables. */
+	pub fn update(&mut self, c: &Context){
+		let mut names: Vec<String> = c.functions().iter()
+			.filter(|f| f.id != BIRL_GLOBAL_FUNCTION_ID)
+			.map(|f| f.name.clone())
+			.collect();
+
+		if let Some(global) = c.get_entry_by_id(BIRL_GLOBAL_FUNCTION_ID){
+			names.extend(global.vars.iter().map(|v| v.name.clone()));
+		}
+
+		if let Some(function) = c.current_function(){
+			names.extend(function.vars.iter().map(|v| v.name.clone()));
+		}
+
+		/* Types show up after the colon too, in parameter lists. */
+		let types = [TypeKind::Integer, TypeKind::Number, TypeKind::Text, TypeKind::List, TypeKind::Boolean];
+		names.extend(types.iter().map(|t| t.to_string()));
+
+		names.sort();
+		names.dedup();
+		self.names = names;
+	}
+}
+
+fn pairs<'a, I>(candidates: I, prefix: &str) -> Vec<Pair>
+	where I: Iterator<Item = &'a str>{
+
+	candidates.filter(|c| c.starts_with(prefix))
+		.map(|c| Pair{ display: c.to_owned(), replacement: c.to_owned() })
+		.collect()
+}
+
+impl Completer for BirlHelper{
+	type Candidate = Pair;
+
+	fn complete(&self, line: &str, pos: usize, ctx: &rustyline::Context<'_>)
+		-> rustyline::Result<(usize, Vec<Pair>)>{
+
+		let before = &line[..pos];
+		let trimmed = before.trim_start();
+		let start = before.len() - trimmed.len();
+
+		/* Shell commands, and the file for :load. */
+		if trimmed.starts_with(":load "){
+			return self.files.complete(line, pos, ctx)
+		}
+		if trimmed.starts_with(':'){
+			if trimmed.contains(char::is_whitespace){
+				return Ok((pos, vec![]))
+			}
+			return Ok((start, pairs(crate::meta::COMMANDS.iter().cloned(), trimmed)))
+		}
+
+		/* Up to the colon, the line is still a key phrase, which may
+		 * have spaces in it. */
+		if !trimmed.contains(':'){
+			return Ok((start, pairs(KeyPhrase::phrases().into_iter(), trimmed)))
+		}
+
+		let word_start = before.char_indices()
+			.rev()
+			.find(|&(_, c)| !(c.is_alphanumeric() || c == '_'))
+			.map(|(i, c)| i + c.len_utf8())
+			.unwrap_or(0);
+		let word = &before[word_start..];
+		if word.is_empty(){
+			return Ok((pos, vec![]))
+		}
+
+		Ok((word_start, pairs(self.names.iter().map(|n| n.as_str()), word)))
+	}
+}
+
+impl Hinter for BirlHelper{
+	type Hint = String;
+}
+
+impl Highlighter for BirlHelper{}
+impl Validator for BirlHelper{}
+impl Helper for BirlHelper{}
+
+pub type BirlEditor = Editor<BirlHelper, DefaultHistory>;
+
+/* Where the history is kept between sessions, if there's a home to keep it
+ * in. */
+pub fn history_path() -> Option<PathBuf>{
+	std::env::var_os("HOME")
+		.or_else(|| std::env::var_os("USERPROFILE"))
+		.map(|home| PathBuf::from(home).join(HISTORY_FILE))
+}
+
+pub fn new() -> Result<BirlEditor, String>{
+	let mut editor = match BirlEditor::new(){
+		Ok(editor) => editor,
+		Err(what) => return Err(format!("Could not start the line editor: {}", what))
+	};
+	editor.set_helper(Some(BirlHelper::new()));
+
+	/* A missing history just means this is the first session. */
+	if let Some(path) = history_path(){
+		let _ = editor.load_history(&path);
+	}
+
+	Ok(editor)
+}
+
+mod tests{
+	#[test]
+	fn completion(){
+		use crate::editor::BirlHelper;
+		use birl::context::Context;
+
+		use rustyline::completion::Completer;
+		use rustyline::history::DefaultHistory;
+
+		/* Where the replacement starts and the candidates for it. */
+		fn complete(helper: &BirlHelper, line: &str) -> (usize, Vec<String>){
+			let history = DefaultHistory::new();
+			let ctx = rustyline::Context::new(&history);
+			let (start, pairs) = helper.complete(line, line.len(), &ctx).unwrap();
+
+			(start, pairs.into_iter().map(|p| p.replacement).collect())
+		}
+
+		let mut c = Context::new();
+		for line in ["VEM: DOIS, 2", "JAULA DOBRA(NUMERO : BATATA DOCE)"].iter(){
+			c.process_line(line).unwrap();
+		}
+
+		/* Called through the type, since Completer has an update of its
+		 * own. */
+		let mut helper = BirlHelper::new();
+		BirlHelper::update(&mut helper, &c);
+
+		/* Key phrases, spaces included, and the shell's own commands. */
+		let (start, phrases) = complete(&helper, "  CE QUER");
+		assert_eq!(start, 2);
+		assert!(phrases.contains(&String::from("CE QUER VER")), "{:?}", phrases);
+		assert!(phrases.contains(&String::from("CE QUER VER ISSO")), "{:?}", phrases);
+		assert!(phrases.iter().all(|p| p.starts_with("CE QUER")), "{:?}", phrases);
+
+		assert_eq!(complete(&helper, ":d"), (0, vec![String::from(":dis")]));
+		assert_eq!(complete(&helper, ":dis "), (5, vec![]));
+
+		/* After the colon, names from the context, including the variables
+		 * of the JAULA being typed, and types. */
+		let mut names = complete(&helper, "CE QUER VER: 1 + DO");
+		names.1.sort();
+		assert_eq!(names, (17, vec![String::from("DOBRA"), String::from("DOIS")]));
+
+		assert_eq!(complete(&helper, "BORA: X, NUM"), (9, vec![String::from("NUMERO")]));
+		assert_eq!(complete(&helper, "VEM: L : MAR"), (9, vec![String::from("MARMITA")]));
+		assert_eq!(complete(&helper, "CE QUER VER: "), (13, vec![]));
+
+		/* Once the JAULA is closed, its variables are gone. */
+		c.process_line("SAINDO DA JAULA").unwrap();
+		BirlHelper::update(&mut helper, &c);
+		assert_eq!(complete(&helper, "BORA: X, NUM"), (9, vec![]));
+	}
+}
@@ -1,5 +1,12 @@
 extern crate birl;
 
+mod debug;
+mod editor;
+mod meta;
+
+use birl::context::{Context, BIRL_GLOBAL_FUNCTION_ID};
+use birl::error::RuntimeError;
+
 fn main(){
 	/* Print heading info. */
 	eprintln!("O SHELL QUE CONSTRÓI FIBRA. VERSÃO {}", env!("CARGO_PKG_VERSION"));
@@ -7,74 +14,90 @@ fn main(){
 	eprintln!("SHELL © 2018, MATHEUS BRANCO BORELLA.");
 	eprintln!();
 
-	/* Setup BIRL.
-	 * The Context interpreter is not built to run interactively,
-	 * by default, as it lacks much of the framework that would be
-	 * needed to properly implement an interactive shell with
-	 * immidiate evaluation of expresssions.
-	 * 
-	 * So, as a workaround, the shell cycles the interpreter manually
-	 * to the completion of all instructions added by a line. To
-	 * achieve that, we first call the root function, as any
-	 * expression typed into the interpreted will be compiled as a
-	 * root-level instruction.
-	 */
-	use birl::context::Context;
-	let mut c = Context::new();
-	
-	use birl::context::BIRL_GLOBAL_FUNCTION_ID;
-	c.call_function_by_id(BIRL_GLOBAL_FUNCTION_ID, vec![])
-		.expect("Could not setup BIRL runtime.");
+	/* With -d, run a file under the debugger instead of starting the
+	 * interactive interpreter. */
+	let args: Vec<String> = std::env::args().skip(1).collect();
+	match args.first().map(|a| a.as_str()){
+		Some("-d") | Some("--depurar") => {
+			let file = match args.get(1){
+				Some(file) => file,
+				None => {
+					eprintln!("Uso: shell --depurar arquivo.birl");
+					std::process::exit(2)
+				}
+			};
 
-	/* Bind the Context interpreter to standard IO */
-	let _ = c.set_stdin({
-		use std::io;
-		let reader = io::BufReader::new(io::stdin());
-		Some(Box::new(reader))
-	});
-	let _ = c.set_stdout({
-		use std::io;
-		Some(Box::new(io::stdout()))
-	});
+			if let Err(what) = debug::run(file){
+				eprintln!("{}", what);
+				std::process::exit(1)
+			}
+			return
+		},
+		Some(other) => {
+			eprintln!("Opção desconhecida: {}. Use -d arquivo.birl pra depurar.", other);
+			std::process::exit(2)
+		},
+		None => {}
+	}
+
+	let mut c = new_context();
+
+	let mut editor = match editor::new(){
+		Ok(editor) => editor,
+		Err(what) => {
+			eprintln!("{}", what);
+			std::process::exit(1)
+		}
+	};
 
 	/* Enter interactive loop */
-	use std::io::{stdin, BufReader, BufRead};
-	let mut prompt = BufReader::new(stdin());
 	loop{
-		eprint!("> ");
+		let prompt = prompt(c.open_blocks());
 
-		let mut line = String::new();
-		match prompt.read_line(&mut line){
-			Ok(count) => if count == 0 {
-				eprintln!("Reached end of input.");
+		/* Whatever the program printed must come before the prompt. */
+		c.vm.flush_stdout();
+		if let Some(helper) = editor.helper_mut(){
+			helper.update(&c);
+		}
+
+		use rustyline::error::ReadlineError;
+		let line = match editor.readline(&prompt){
+			Ok(line) => line,
+			/* Ctrl-C only throws away the line being typed. */
+			Err(ReadlineError::Interrupted) => continue,
+			Err(ReadlineError::Eof) => {
+				if c.open_blocks() > 0{
+					eprintln!("Reached end of input with {} unfinished block(s).", c.open_blocks());
+				} else {
+					eprintln!("Reached end of input.");
+				}
 				break
 			},
 			Err(what) => {
 				eprintln!("A read error occured: {:?}", what);
 				break
 			}
+		};
+
+		if !line.trim().is_empty(){
+			let _ = editor.add_history_entry(line.as_str());
+		}
+
+		/* No BIRL command starts with a colon, so these are free for
+		 * the shell's own commands. */
+		if line.trim_start().starts_with(':'){
+			meta::run(&mut c, line.trim(), &mut std::io::stderr());
+			continue
 		}
 
-		/* Parse and evaluate */
-		if let Err(what) = c.process_line(&line){
+		if let Err(what) = eval(&mut c, &line){
 			eprintln!("{}", what);
-		} else {
-			/* Drives the currently pending instructions to
-			 * completion. */
-			let mut saturate = || loop{
-				let status = c.execute_next_instruction();
-
-				use birl::vm::ExecutionStatus as Es;
-				match status{
-					Ok(Es::Quit) => break Ok(()),
-					Err(what)    => break Err(what),
-					_ => {}
-				}
-			};
-			
-			if let Err(what) = saturate(){
-				eprintln!("{}", what);
-			}
+		}
+	}
+
+	if let Some(path) = editor::history_path(){
+		if let Err(what) = editor.save_history(&path){
+			eprintln!("Could not save the history to {}: {}", path.display(), what);
 		}
 	}
 
@@ -82,3 +105,125 @@ fn main(){
 	c.set_stdout(None).unwrap().flush()
 		.expect("Could not flush io::stdout().");
 }
+
+/* Setup BIRL.
+ * The Context interpreter is not built to run interactively,
+ * by default, as it lacks much of the framework that would be
+ * needed to properly implement an interactive shell with
+ * immidiate evaluation of expresssions.
+ * 
+ * So, as a workaround, the shell cycles the interpreter manually
+ * to the completion of all instructions added by a line. To
+ * achieve that, we first call the root function, as any
+ * expression typed into the interpreted will be compiled as a
+ * root-level instruction.
+ */
+pub fn new_context() -> Context{
+	let mut c = Context::new();
+
+	c.call_function_by_id(BIRL_GLOBAL_FUNCTION_ID, vec![])
+		.expect("Could not setup BIRL runtime.");
+
+	/* Bind the Context interpreter to standard IO. The editor reads
+	 * its lines through the buffer std keeps for stdin, so the program
+	 * must not take more than a byte at a time out of it, or the lines
+	 * typed after its input would be swallowed by its own buffer. */
+	let _ = c.set_stdin({
+		use std::io;
+		let reader = io::BufReader::with_capacity(1, io::stdin());
+		Some(Box::new(reader))
+	});
+	let _ = c.set_stdout({
+		use std::io;
+		Some(Box::new(io::stdout()))
+	});
+
+	/* Run the global constants right away, so they can be seen
+	 * before the first line is typed. */
+	saturate(&mut c).expect("Could not setup BIRL runtime.");
+
+	c
+}
+
+/* Lines typed inside a JAULA or any other block only get compiled, so
+ * show the user we're still waiting for its end. */
+fn prompt(depth: usize) -> String{
+	if depth == 0{
+		String::from("> ")
+	} else {
+		format!("{}... ", "  ".repeat(depth - 1))
+	}
+}
+
+/* Compiles a line and runs it, unless it leaves a block open. Blocks
+ * are only run once they're closed. Running them line by line would
+ * evaluate a loop's condition before its body even exists. */
+fn eval(c: &mut Context, line: &str) -> Result<(), String>{
+	if let Err(what) = c.process_line(line){
+		return Err(what.to_string())
+	}
+
+	if c.open_blocks() > 0{
+		return Ok(())
+	}
+
+	saturate(c).map_err(|what| what.to_string())
+}
+
+/* Drives the currently pending instructions to completion. */
+pub fn saturate(c: &mut Context) -> Result<(), RuntimeError>{
+	loop{
+		let status = c.execute_next_instruction();
+
+		use birl::vm::ExecutionStatus as Es;
+		match status{
+			Ok(Es::Quit) => break Ok(()),
+			Err(what)    => break Err(what),
+			_ => {}
+		}
+	}
+}
+
+mod tests{
+	#[test]
+	fn open_blocks(){
+		use crate::{eval, new_context, prompt};
+		use birl::context::RawValue;
+
+		let mut c = new_context();
+		let _ = c.set_stdout(Some(Box::new(std::io::sink())));
+
+		/* Each line and the prompt shown after it. */
+		let lines = [
+			("VEM: N, 0", "> "),
+			("JAULA SOMA(A : BATATA DOCE)", "... "),
+			("E VERDADE: A > 0", "  ... "),
+			("BORA: N, N + A", "  ... "),
+			("SENÃO", "  ... "),
+			("BORA: N, 0", "  ... "),
+			("FIM", "... "),
+			("SAINDO DA JAULA", "> "),
+			("REPETE: 3", "... "),
+			("E HORA DO: SOMA, 2", "... "),
+		];
+
+		assert_eq!(prompt(c.open_blocks()), "> ");
+		for (line, expected) in lines.iter(){
+			if let Err(what) = eval(&mut c, line){
+				panic!("Erro em {} : {}", line, what)
+			}
+
+			assert_eq!(prompt(c.open_blocks()), *expected, "{}", line);
+		}
+
+		/* A line that fails doesn't open or close anything. */
+		assert!(eval(&mut c, "BORA: N,").is_err());
+		assert_eq!(prompt(c.open_blocks()), "... ");
+
+		/* The loop only runs once it's closed. */
+		assert_eq!(c.get_global("N"), Ok(Some(RawValue::Integer(0))));
+		assert_eq!(eval(&mut c, "FIM"), Ok(()));
+		assert_eq!(prompt(c.open_blocks()), "> ");
+		assert_eq!(c.get_global("N"), Ok(Some(RawValue::Integer(6))));
+	}
+}
@@ -0,0 +1,270 @@
+use birl::context::{Context, FunctionEntry, BIRL_GLOBAL_FUNCTION_ID};
+
+use std::io::{self, Write};
+
+const HELP: &str = "\
+Comandos do shell:
+    :funcs          Lista as JAULAs declaradas
+    :vars           Mostra as variáveis globais e seus valores
+    :load ARQUIVO   Compila e roda o código global de um arquivo
+    :reset          Descarta tudo que foi declarado e começa de novo
+    :dis [JAULA]    Mostra as instruções de uma JAULA, ou do código global
+    :stack          Mostra as JAULAs em execução
+    :help           Mostra essa mensagem";
+
+/* For completion. */
+pub const COMMANDS: &[&str] = &[":funcs", ":vars", ":load", ":reset", ":dis", ":stack", ":help"];
+
+fn signature(entry: &FunctionEntry) -> String{
+	let params: Vec<String> = entry.params.iter()
+		.map(|p| format!("{} : {}", p.name, p.kind))
+		.collect();
+
+	let returns = match entry.return_kind{
+		Some(kind) => format!(" : {}", kind),
+		None => String::new()
+	};
+
+	if params.is_empty(){
+		format!("JAULA {}{}", entry.name, returns)
+	} else {
+		format!("JAULA {}({}){}", entry.name, params.join(", "), returns)
+	}
+}
+
+fn funcs(c: &Context, out: &mut dyn Write) -> io::Result<()>{
+	for entry in c.functions().iter().filter(|f| f.id != BIRL_GLOBAL_FUNCTION_ID){
+		let origin = if entry.is_native(){
+			String::from(" (nativa)")
+		} else if let Some(file) = &entry.file{
+			format!(" ({})", file)
+		} else {
+			String::new()
+		};
+
+		writeln!(out, "{}{}", signature(entry), origin)?;
+	}
+
+	Ok(())
+}
+
+fn vars(c: &Context, out: &mut dyn Write) -> io::Result<()>{
+	let global = match c.get_entry_by_id(BIRL_GLOBAL_FUNCTION_ID){
+		Some(global) => global,
+		None => return Ok(())
+	};
+
+	for var in &global.vars{
+		let kind = if var.writeable { "" } else { " (constante)" };
+		let name = match var.kind{
+			Some(k) => format!("{} : {}", var.name, k),
+			None => var.name.clone()
+		};
+
+		match c.get_global(&var.name){
+			Ok(Some(value)) => writeln!(out, "{} = {}{}", name, value, kind)?,
+			/* Declared, but the code giving it a value didn't run. */
+			Ok(None) => writeln!(out, "{} (sem valor){}", name, kind)?,
+			Err(what) => writeln!(out, "{} : {}", name, what)?
+		}
+	}
+
+	Ok(())
+}
+
+fn load(c: &mut Context, file: &str, out: &mut dyn Write) -> io::Result<()>{
+	if file.is_empty(){
+		return writeln!(out, "Passe o arquivo a ser carregado.")
+	}
+
+	if c.open_blocks() > 0{
+		return writeln!(out, "Termine o bloco aberto antes de carregar um arquivo.")
+	}
+
+	if let Err(what) = c.add_file(file){
+		return writeln!(out, "{}", what)
+	}
+
+	if let Err(what) = crate::saturate(c){
+		writeln!(out, "{}", what)?;
+	}
+
+	Ok(())
+}
+
+fn dis(c: &Context, name: &str, out: &mut dyn Write) -> io::Result<()>{
+	let entry = if name.is_empty(){
+		c.get_entry_by_id(BIRL_GLOBAL_FUNCTION_ID)
+	} else {
+		c.get_function_by_name(name)
+	};
+
+	let entry = match entry{
+		Some(entry) => entry,
+		None => return writeln!(out, "A JAULA {} não existe.", name)
+	};
+
+	writeln!(out, "{} ({} instruções)", signature(entry), entry.body.len())?;
+	for (pc, instruction) in entry.body.iter().enumerate(){
+		if entry.starts_line(pc){
+			if let Some(line) = entry.line_at(pc){
+				writeln!(out, "  ; linha {}", line)?;
+			}
+		}
+
+		writeln!(out, "  {:>4}  {:?}", pc, instruction)?;
+	}
+
+	Ok(())
+}
+
+fn stack(c: &Context, out: &mut dyn Write) -> io::Result<()>{
+	/* Newest first, same as the debugger. */
+	for frame in c.callstack().iter().rev(){
+		let state = if frame.ready { "" } else { " (recebendo argumentos)" };
+
+		match frame.line{
+			Some(line) => writeln!(out, "JAULA {}, linha {}, instrução {}{}",
+				frame.function, line, frame.program_counter, state)?,
+			None => writeln!(out, "JAULA {}, instrução {}{}",
+				frame.function, frame.program_counter, state)?
+		}
+	}
+
+	writeln!(out, "{} instruções executadas.", c.vm.get_instructions_executed())
+}
+
+/* Runs one of the shell's own commands, writing what it shows to out.
+ * The line still has the colon. */
+pub fn run(c: &mut Context, line: &str, out: &mut dyn Write){
+	let line = line.trim_start_matches(':');
+	let (command, argument) = match line.find(char::is_whitespace){
+		Some(i) => (&line[..i], line[i..].trim()),
+		None => (line, "")
+	};
+
+	/* There's nowhere left to report a failed write to. */
+	let _ = match command{
+		"funcs" => funcs(c, out),
+		"vars" => vars(c, out),
+		"load" => load(c, argument, out),
+		"reset" => {
+			*c = crate::new_context();
+			writeln!(out, "Tudo foi descartado.")
+		},
+		"dis" => dis(c, argument, out),
+		"stack" => stack(c, out),
+		"help" => writeln!(out, "{}", HELP),
+		_ => writeln!(out, "Comando desconhecido: :{}. Use :help pra ver os comandos.", command)
+	};
+}
+
+mod tests{
+	#[test]
+	fn signature(){
+		use crate::meta::signature;
+		use birl::context::Context;
+
+		let mut c = Context::new();
+		let source = "JAULA F(A : BATATA DOCE, B : FIBRA) : MARMITA\n\
+		              BIRL: [A, B]\n\
+		              SAINDO DA JAULA\n\
+		              JAULA G\n\
+		              SAINDO DA JAULA\n\
+		              JAULA H() : BIRLEANO\n\
+		              BIRL: VERDADE\n\
+		              SAINDO DA JAULA";
+
+		if let Err(what) = c.add_source_string(source.to_owned()){
+			panic!("Erro compilando o código : {}", what)
+		}
+
+		let sign = |name| signature(c.get_function_by_name(name).unwrap());
+
+		assert_eq!(sign("F"), "JAULA F(A : BATATA DOCE, B : FIBRA) : MARMITA");
+		assert_eq!(sign("G"), "JAULA G");
+		assert_eq!(sign("H"), "JAULA H : BIRLEANO");
+	}
+
+	#[test]
+	fn commands(){
+		use crate::{eval, new_context};
+		use birl::context::{Context, RawValue};
+		use birl::parser::{FunctionParameter, TypeKind};
+
+		use std::{env, fs, process};
+
+		/* Runs the command and returns what it showed. */
+		fn run(c: &mut Context, line: &str) -> String{
+			let mut out = vec![];
+			crate::meta::run(c, line, &mut out);
+
+			String::from_utf8(out).unwrap()
+		}
+
+		let mut c = new_context();
+		let _ = c.set_stdout(Some(Box::new(std::io::sink())));
+
+		let lines = [
+			"VEM: X, 1",
+			"JAULA DOBRA(N : BATATA DOCE) : BATATA DOCE",
+			"BIRL: N * 2",
+			"SAINDO DA JAULA",
+		];
+
+		for line in lines.iter(){
+			if let Err(what) = eval(&mut c, line){
+				panic!("Erro em {} : {}", line, what)
+			}
+		}
+
+		let param = FunctionParameter{ name: String::from("V"), kind: TypeKind::Text };
+		c.register_native("NATIVA", vec![param], |args| Ok(args[0].clone())).unwrap();
+
+		assert_eq!(run(&mut c, ":funcs"), "JAULA DOBRA(N : BATATA DOCE) : BATATA DOCE\n\
+		                                   JAULA NATIVA(V : FIBRA) (nativa)\n");
+
+		/* Globals declared in a block that's still open have no value yet. */
+		eval(&mut c, "REPETE: 1").unwrap();
+		eval(&mut c, "VEM: Y, 2").unwrap();
+
+		let vars = run(&mut c, ":vars");
+		assert!(vars.contains("\nUM : BATATA DOCE = 1 (constante)\n"), "{}", vars);
+		assert!(vars.contains("\nX = 1\n"), "{}", vars);
+		assert!(vars.ends_with("\nY (sem valor)\n"), "{}", vars);
+
+		assert_eq!(run(&mut c, ":load outro.birl"), "Termine o bloco aberto antes de carregar um arquivo.\n");
+		eval(&mut c, "FIM").unwrap();
+
+		let dis = run(&mut c, ":dis DOBRA");
+		assert!(dis.starts_with("JAULA DOBRA(N : BATATA DOCE) : BATATA DOCE ("), "{}", dis);
+		assert!(dis.contains("  ; linha 3\n"), "{}", dis);
+		assert_eq!(run(&mut c, ":dis NADA"), "A JAULA NADA não existe.\n");
+		assert!(run(&mut c, ":dis").starts_with("JAULA __global__ ("));
+
+		let stack = run(&mut c, ":stack");
+		assert!(stack.ends_with(" instruções executadas.\n"), "{}", stack);
+
+		/* Loading a file compiles it and runs its global code. */
+		let file = env::temp_dir().join(format!("birl_shell_{}.birl", process::id()));
+		fs::write(&file, "JAULA TRIPLA(N : BATATA DOCE)\nBIRL: N * 3\nSAINDO DA JAULA\nVEM: Z, TRIPLA(2)").unwrap();
+		let path = file.to_string_lossy().into_owned();
+
+		assert_eq!(run(&mut c, &format!(":load {}", path)), "");
+		let _ = fs::remove_file(&file);
+
+		assert!(run(&mut c, ":funcs").ends_with(&format!("JAULA TRIPLA(N : BATATA DOCE) ({})\n", path)));
+		assert_eq!(c.get_global("Z"), Ok(Some(RawValue::Integer(6))));
+		assert_eq!(run(&mut c, ":load"), "Passe o arquivo a ser carregado.\n");
+		assert_ne!(run(&mut c, &format!(":load {}", path)), "");
+
+		/* Reset throws away everything but the default constants. */
+		assert_eq!(run(&mut c, ":reset"), "Tudo foi descartado.\n");
+		assert_eq!(run(&mut c, ":funcs"), "");
+		assert!(!run(&mut c, ":vars").contains("X = 1"));
+		assert!(c.get_function_by_name("DOBRA").is_none());
+
+		assert!(run(&mut c, ":help").contains(":dis [JAULA]"));
+		assert_eq!(run(&mut c, ":nada"), "Comando desconhecido: :nada. Use :help pra ver os comandos.\n");
+	}
+}